@@ -0,0 +1,40 @@
+use blunderbuss::{
+    attacks::{king_attacks, knight_attacks},
+    bitboard::Bitboard,
+    simd::{leaper_attacks, scalar_leaper_attacks, LeaperAttacks},
+};
+
+// fixed sample size so the first AVX2 warm-up sample doesn't pick a tiny iteration count
+fn main() {
+    divan::main();
+}
+
+const KNIGHTS: [u64; 2] = [0x0000000000240000, 0x0000040000000200];
+const KINGS: [u64; 2] = [0x4000000000000000, 0x0000000000000040];
+
+fn inputs() -> ([Bitboard; 2], [Bitboard; 2]) {
+    let knights = divan::black_box(KNIGHTS).map(Bitboard);
+    let kings = divan::black_box(KINGS).map(Bitboard);
+    (knights, kings)
+}
+
+#[divan::bench(sample_size = 1000)]
+fn per_square() -> LeaperAttacks {
+    let (knights, kings) = inputs();
+    LeaperAttacks {
+        knights: knights.map(|bb| bb.fold(Bitboard(0), |acc, sq| acc | knight_attacks(sq))),
+        kings: kings.map(|bb| bb.fold(Bitboard(0), |acc, sq| acc | king_attacks(sq))),
+    }
+}
+
+#[divan::bench(sample_size = 1000)]
+fn scalar_set() -> LeaperAttacks {
+    let (knights, kings) = inputs();
+    scalar_leaper_attacks(knights, kings)
+}
+
+#[divan::bench(sample_size = 1000)]
+fn dispatched_set() -> LeaperAttacks {
+    let (knights, kings) = inputs();
+    leaper_attacks(knights, kings)
+}
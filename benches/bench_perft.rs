@@ -0,0 +1,45 @@
+use blunderbuss::{
+    attacks,
+    game::Game,
+    perft::{perft, perft_nodes},
+    search::{search_blocking, SearchLimits},
+    tt::TranspositionTable,
+    Position,
+};
+use divan::{AllocProfiler, Bencher};
+
+// counts allocations alongside the times. perft keeps its move lists on the stack and allocates
+// nothing. negamax and quiescence search don't either: their moves are stack move lists, static
+// exchange evaluation skips recording its sequence and the table is sized up front. a search makes
+// two allocations in total, for its stop channel and its pv, whatever the depth, so one creeping
+// into a per-node path shows up here as a count that grows with depth
+#[global_allocator]
+static ALLOC: AllocProfiler = AllocProfiler::system();
+
+fn main() {
+    attacks::init();
+    divan::main();
+}
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+#[divan::bench(args = [2, 3])]
+fn nodes(bencher: Bencher, depth: u8) {
+    bencher.with_inputs(|| Position::from_fen(KIWIPETE)).bench_local_refs(|position| perft_nodes(position, depth));
+}
+
+#[divan::bench(args = [1, 2])]
+fn detailed(bencher: Bencher, depth: u8) {
+    bencher.with_inputs(|| Position::from_fen(KIWIPETE)).bench_local_refs(|position| perft(position, depth));
+}
+
+#[divan::bench(args = [3, 4])]
+fn search(bencher: Bencher, depth: u8) {
+    let limits = SearchLimits { depth, nodes: u32::MAX, movetime: 0 };
+    // cleared each time, or every search after the first would find its results already there
+    let tt = TranspositionTable::new(1);
+    bencher.with_inputs(|| Game::new(Position::from_fen(KIWIPETE))).bench_local_refs(|game| {
+        tt.clear();
+        search_blocking(game, limits, &tt, false)
+    });
+}
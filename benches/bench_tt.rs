@@ -0,0 +1,77 @@
+use blunderbuss::tt::{Bound, TranspositionTable, TtEntry};
+use divan::{counter::ItemsCount, Bencher};
+
+fn main() {
+    divan::main();
+}
+
+// a 1 MB table fits in cache and a 64 MB one doesn't, so the second shows what a probe costs when
+// its bucket has to come from memory
+const SIZES: [usize; 2] = [1, 64];
+const KEYS: usize = 4096;
+
+// xorshift64, random enough to spread keys over the table the way zobrist hashes do
+fn keys(mut seed: u64) -> Vec<u64> {
+    (0..KEYS)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        })
+        .collect()
+}
+
+fn entry(key: u64) -> TtEntry {
+    TtEntry { mv: key as u16, score: (key >> 16) as i16 as i32, depth: (key >> 32) as u8 % 32, bound: Bound::Exact }
+}
+
+fn filled(mb: usize) -> (TranspositionTable, Vec<u64>) {
+    let tt = TranspositionTable::new(mb);
+    let keys = keys(0x9e37_79b9_7f4a_7c15);
+    for &key in &keys {
+        tt.store(key, entry(key));
+    }
+    (tt, keys)
+}
+
+#[divan::bench(args = SIZES)]
+fn probe_hit(bencher: Bencher, mb: usize) {
+    let (tt, keys) = filled(mb);
+    bencher
+        .counter(ItemsCount::new(KEYS))
+        .bench_local(|| keys.iter().filter_map(|&key| tt.probe(divan::black_box(key))).count());
+}
+
+#[divan::bench(args = SIZES)]
+fn probe_miss(bencher: Bencher, mb: usize) {
+    let (tt, _) = filled(mb);
+    let missing = keys(0x2545_f491_4f6c_dd1d);
+    bencher
+        .counter(ItemsCount::new(KEYS))
+        .bench_local(|| missing.iter().filter_map(|&key| tt.probe(divan::black_box(key))).count());
+}
+
+#[divan::bench(args = SIZES)]
+fn store(bencher: Bencher, mb: usize) {
+    let (tt, keys) = filled(mb);
+    bencher.counter(ItemsCount::new(KEYS)).bench_local(|| {
+        for &key in &keys {
+            tt.store(divan::black_box(key), entry(key));
+        }
+    });
+}
+
+// threads probing and storing the same keys at once, as search threads sharing the table do. with
+// no lock the time per item should stay close to a single thread's
+#[divan::bench(args = SIZES, threads = [1, 4])]
+fn shared(bencher: Bencher, mb: usize) {
+    let (tt, keys) = filled(mb);
+    bencher.counter(ItemsCount::new(KEYS)).bench(|| {
+        for &key in &keys {
+            if tt.probe(divan::black_box(key)).is_none() {
+                tt.store(key, entry(key));
+            }
+        }
+    });
+}
@@ -17,4 +17,10 @@ expect $perft
 log_user 1
 set output $expect_out(buffer)
 set idx [string first "quit" $output]
-puts [string range $output $idx+6 end-1]
\ No newline at end of file
+set output [string range $output $idx+6 end-1]
+
+# perftree wants "move count" lines then the bare total, without the timing
+regsub {\r?\nTime:.*} $output {} output
+regsub {Nodes searched: } $output {} output
+regsub -all {: } $output { } output
+puts $output
\ No newline at end of file
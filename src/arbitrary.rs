@@ -0,0 +1,98 @@
+// Proptest strategies for legal positions and the games that reach them, for property testing
+// anything built on the core: FEN and SAN round trips, make_move against positions built from
+// scratch, incremental hashes. Games are random legal moves from a random Chess960 start, so
+// every position is reachable and legal, and they shrink towards fewer moves from the standard
+// start.
+
+use proptest::{prelude::*, sample::select};
+
+use crate::{chess960::N_CHESS960_POSITIONS, game::Game, movegen::Move, position::Position};
+
+pub const DEFAULT_MAX_PLIES: usize = 100;
+// offset so shrinking the start number towards 0 shrinks towards the standard start
+const STANDARD_START: usize = 518;
+
+pub fn legal_moves(position: &Position) -> Vec<Move> {
+    position.gen_moves().filter(|&mv| position.is_legal(mv)).collect()
+}
+
+// each choice picks one of the legal moves, wrapping round when it's past the end. the game stops
+// early at mate or stalemate
+pub fn play_choices(start: Position, choices: &[usize]) -> Game {
+    let mut game = Game::new(start);
+    for choice in choices {
+        let moves = legal_moves(game.position());
+        if moves.is_empty() {
+            break;
+        }
+        game.push(moves[choice % moves.len()]);
+    }
+    game
+}
+
+pub fn arb_game(max_plies: usize) -> impl Strategy<Value = Game> {
+    (0..N_CHESS960_POSITIONS, prop::collection::vec(any::<usize>(), 0..=max_plies)).prop_map(|(n, choices)| {
+        let start = Position::chess960((n + STANDARD_START) % N_CHESS960_POSITIONS).unwrap();
+        play_choices(start, &choices)
+    })
+}
+
+pub fn arb_position() -> impl Strategy<Value = Position> {
+    arb_game(DEFAULT_MAX_PLIES).prop_map(|game| *game.position())
+}
+
+// a position with a legal move in it
+pub fn arb_position_and_move() -> impl Strategy<Value = (Position, Move)> {
+    arb_position()
+        .prop_filter("no legal moves", |position| !legal_moves(position).is_empty())
+        .prop_flat_map(|position| (Just(position), select(legal_moves(&position))))
+}
+
+impl Arbitrary for Game {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Game>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        arb_game(DEFAULT_MAX_PLIES).boxed()
+    }
+}
+
+impl Arbitrary for Position {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Position>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        arb_position().boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn fen_round_trip(position in any::<Position>()) {
+            let fen = position.write_fen();
+            let read = Position::try_from_fen(&fen).unwrap();
+            prop_assert_eq!(read.write_fen(), fen);
+            prop_assert_eq!(read.hash, position.hash);
+        }
+
+        #[test]
+        fn san_round_trip((position, mv) in arb_position_and_move()) {
+            prop_assert_eq!(position.find_san_move(&position.san(mv)), Some(mv));
+        }
+
+        #[test]
+        fn incremental_keys(game in any::<Game>()) {
+            let mut position = *game.start();
+            for &mv in game.moves() {
+                position.make_move(mv);
+                let mut fresh = position;
+                fresh.gen_zobrist_hash();
+                prop_assert!(position.diff(&fresh).is_empty(), "after {}: {:?}", mv, position.diff(&fresh));
+            }
+        }
+    }
+}
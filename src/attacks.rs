@@ -0,0 +1,363 @@
+// All precomputed attack tables live here. Leaper attacks are built at compile time, slider and
+// line tables are built once behind a Once, either lazily or up front through init, and are
+// shared read-only between search threads. Zobrist codes are compile time constants in zobrist.rs.
+
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use spin::Once;
+
+use num::FromPrimitive;
+
+use crate::prelude::*;
+use crate::{
+    bitboard::{Bitboard, File, Rank, Square},
+    position::Colour,
+};
+
+#[cfg(not(any(feature = "black-magic", feature = "kogge-stone")))]
+use crate::magic::{magics, BISHOP_BITS, ROOK_BITS};
+
+const KING_ATTACKS: [Bitboard; 64] = build_king_tbl();
+const KNIGHT_ATTACKS: [Bitboard; 64] = build_knight_tbl();
+const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [build_pawn_tbl(Colour::White), build_pawn_tbl(Colour::Black)];
+
+static LINES: Once<Box<LineTables>> = Once::new();
+
+struct LineTables {
+    between: [[Bitboard; 64]; 64],
+    line: [[Bitboard; 64]; 64],
+    ray: [[Bitboard; 64]; 64],
+}
+
+// builds every runtime table so the first search doesn't pay for it
+pub fn init() {
+    init_sliders();
+    lines();
+}
+
+fn init_sliders() {
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    pick_sliders();
+
+    #[cfg(not(all(feature = "pext", target_arch = "x86_64")))]
+    init_slider_fallback();
+}
+
+// the tables the sliders use without pext, none for kogge-stone
+fn init_slider_fallback() {
+    #[cfg(all(feature = "black-magic", not(feature = "kogge-stone")))]
+    crate::black_magic::tables();
+
+    #[cfg(not(any(feature = "black-magic", feature = "kogge-stone")))]
+    magics();
+}
+
+const fn build_king_tbl() -> [Bitboard; 64] {
+    let mut moves = [Bitboard(0); 64];
+    let mut sq = 0;
+    while sq < 64 {
+        moves[sq] = all_king_attacks(Bitboard(1 << sq));
+        sq += 1;
+    }
+
+    moves
+}
+
+const fn build_knight_tbl() -> [Bitboard; 64] {
+    let mut moves = [Bitboard(0); 64];
+    let mut sq = 0;
+    while sq < 64 {
+        moves[sq] = all_knight_attacks(Bitboard(1 << sq));
+        sq += 1;
+    }
+
+    moves
+}
+
+const fn build_pawn_tbl(side: Colour) -> [Bitboard; 64] {
+    let mut attacks = [Bitboard(0); 64];
+    let mut sq = 0;
+    while sq < 64 {
+        attacks[sq] = all_pawn_attacks(Bitboard(1 << sq), side);
+        sq += 1;
+    }
+
+    attacks
+}
+
+fn lines() -> &'static LineTables {
+    LINES.call_once(|| {
+        let mut tables = Box::new(LineTables {
+            between: [[Bitboard(0); 64]; 64],
+            line: [[Bitboard(0); 64]; 64],
+            ray: [[Bitboard(0); 64]; 64],
+        });
+
+        for a in 0..64 {
+            let sq_a = Square::from_usize(a).unwrap();
+            for b in 0..64 {
+                let sq_b = Square::from_usize(b).unwrap();
+                let (bb_a, bb_b) = (Bitboard::from(sq_a), Bitboard::from(sq_b));
+                let empty = Bitboard(0);
+                if rook_attacks(sq_a, empty).is_set(sq_b) {
+                    tables.between[a][b] = rook_attacks(sq_a, bb_b) & rook_attacks(sq_b, bb_a);
+                    tables.line[a][b] = rook_attacks(sq_a, empty) & rook_attacks(sq_b, empty) | bb_a | bb_b;
+                } else if bishop_attacks(sq_a, empty).is_set(sq_b) {
+                    tables.between[a][b] = bishop_attacks(sq_a, bb_b) & bishop_attacks(sq_b, bb_a);
+                    tables.line[a][b] = bishop_attacks(sq_a, empty) & bishop_attacks(sq_b, empty) | bb_a | bb_b;
+                }
+                if !tables.line[a][b].is_empty() {
+                    tables.ray[a][b] = build_ray(sq_a, sq_b);
+                }
+            }
+        }
+
+        tables
+    })
+}
+
+// steps from a towards b until falling off the board
+fn build_ray(a: Square, b: Square) -> Bitboard {
+    let file_step = (b.file() as i8 - a.file() as i8).signum();
+    let rank_step = (b.rank() as i8 - a.rank() as i8).signum();
+
+    let mut ray = Bitboard(0);
+    let (mut file, mut rank) = (a.file() as i8 + file_step, a.rank() as i8 + rank_step);
+    while let (Some(f), Some(r)) = (File::from_i8(file), Rank::from_i8(rank)) {
+        ray.set(Square::make(f, r));
+        file += file_step;
+        rank += rank_step;
+    }
+
+    ray
+}
+
+// squares strictly between a and b, empty if they don't share a line
+pub fn between(a: Square, b: Square) -> Bitboard {
+    lines().between[a as usize][b as usize]
+}
+
+// the whole rank, file or diagonal through a and b from edge to edge, empty if they don't share one
+pub fn line(a: Square, b: Square) -> Bitboard {
+    lines().line[a as usize][b as usize]
+}
+
+// squares leaving a in the direction of b up to the edge of the board, passing through b,
+// empty if they don't share a line
+pub fn ray(a: Square, b: Square) -> Bitboard {
+    lines().ray[a as usize][b as usize]
+}
+
+pub fn aligned(a: Square, b: Square, c: Square) -> bool {
+    line(a, b).is_set(c)
+}
+
+pub fn king_attacks(sq: Square) -> Bitboard {
+    KING_ATTACKS[sq]
+}
+
+pub fn knight_attacks(sq: Square) -> Bitboard {
+    KNIGHT_ATTACKS[sq]
+}
+
+pub fn pawn_attacks(sq: Square, side: Colour) -> Bitboard {
+    PAWN_ATTACKS[side][sq]
+}
+
+// every square attacked by at least one of the kings, see simd.rs for the batched version
+pub const fn all_king_attacks(kings: Bitboard) -> Bitboard {
+    let king = kings.0;
+    Bitboard(king << 7 & !Bitboard::H_FILE.0
+        | king << 8
+        | king << 9 & !Bitboard::A_FILE.0
+        | king << 1 & !Bitboard::A_FILE.0
+        | king >> 7 & !Bitboard::A_FILE.0
+        | king >> 8
+        | king >> 9 & !Bitboard::H_FILE.0
+        | king >> 1 & !Bitboard::H_FILE.0)
+}
+
+// every square attacked by at least one of the knights
+pub const fn all_knight_attacks(knights: Bitboard) -> Bitboard {
+    let knight = knights.0;
+    Bitboard(knight << 6 & !Bitboard::H_FILE.0 & !Bitboard::G_FILE.0
+        | knight << 15 & !Bitboard::H_FILE.0
+        | knight << 17 & !Bitboard::A_FILE.0
+        | knight << 10 & !Bitboard::A_FILE.0 & !Bitboard::B_FILE.0
+        | knight >> 6 & !Bitboard::A_FILE.0 & !Bitboard::B_FILE.0
+        | knight >> 15 & !Bitboard::A_FILE.0
+        | knight >> 17 & !Bitboard::H_FILE.0
+        | knight >> 10 & !Bitboard::H_FILE.0 & !Bitboard::G_FILE.0)
+}
+
+// every square attacked by at least one of the pawns
+pub const fn all_pawn_attacks(pawns: Bitboard, side: Colour) -> Bitboard {
+    let pawns = pawns.0;
+    match side {
+        Colour::White => Bitboard(pawns >> 7 & !Bitboard::A_FILE.0 | pawns >> 9 & !Bitboard::H_FILE.0),
+        Colour::Black => Bitboard(pawns << 7 & !Bitboard::H_FILE.0 | pawns << 9 & !Bitboard::A_FILE.0),
+    }
+}
+
+pub fn rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    // SAFETY: it only ever holds a SliderFn, and pext's only when the CPU has BMI2
+    return unsafe { core::mem::transmute::<*mut (), SliderFn>(ROOK_ATTACKS.load(Ordering::Relaxed))(sq, occ) };
+
+    #[cfg(not(all(feature = "pext", target_arch = "x86_64")))]
+    fallback_rook_attacks(sq, occ)
+}
+
+pub fn bishop_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    // SAFETY: it only ever holds a SliderFn, and pext's only when the CPU has BMI2
+    return unsafe { core::mem::transmute::<*mut (), SliderFn>(BISHOP_ATTACKS.load(Ordering::Relaxed))(sq, occ) };
+
+    #[cfg(not(all(feature = "pext", target_arch = "x86_64")))]
+    fallback_bishop_attacks(sq, occ)
+}
+
+// with pext built in, the lookups are picked once rather than checked on every call: each static
+// starts as a function that picks both, swaps them in and then looks up through its pick
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+type SliderFn = unsafe fn(Square, Bitboard) -> Bitboard;
+
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+static ROOK_ATTACKS: AtomicPtr<()> = AtomicPtr::new(pick_rook_attacks as SliderFn as *mut ());
+
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+static BISHOP_ATTACKS: AtomicPtr<()> = AtomicPtr::new(pick_bishop_attacks as SliderFn as *mut ());
+
+// pext where it's fast, the other backend everywhere else
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+fn pick_sliders() {
+    let (rook, bishop): (SliderFn, SliderFn) = if crate::cpu::features().fast_pext {
+        crate::pext::tables();
+        (crate::pext::rook_attacks, crate::pext::bishop_attacks)
+    } else {
+        init_slider_fallback();
+        (fallback_rook_attacks, fallback_bishop_attacks)
+    };
+    ROOK_ATTACKS.store(rook as *mut (), Ordering::Relaxed);
+    BISHOP_ATTACKS.store(bishop as *mut (), Ordering::Relaxed);
+}
+
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+unsafe fn pick_rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    pick_sliders();
+    rook_attacks(sq, occ)
+}
+
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+unsafe fn pick_bishop_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    pick_sliders();
+    bishop_attacks(sq, occ)
+}
+
+fn fallback_rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    #[cfg(feature = "kogge-stone")]
+    return crate::kogge_stone::rook_attacks(sq, occ);
+
+    #[cfg(all(feature = "black-magic", not(feature = "kogge-stone")))]
+    return crate::black_magic::rook_attacks(sq, occ);
+
+    #[cfg(not(any(feature = "black-magic", feature = "kogge-stone")))]
+    magic_rook_attacks(sq, occ)
+}
+
+fn fallback_bishop_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    #[cfg(feature = "kogge-stone")]
+    return crate::kogge_stone::bishop_attacks(sq, occ);
+
+    #[cfg(all(feature = "black-magic", not(feature = "kogge-stone")))]
+    return crate::black_magic::bishop_attacks(sq, occ);
+
+    #[cfg(not(any(feature = "black-magic", feature = "kogge-stone")))]
+    magic_bishop_attacks(sq, occ)
+}
+
+pub fn queen_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    rook_attacks(sq, occ) | bishop_attacks(sq, occ)
+}
+
+#[cfg(not(any(feature = "black-magic", feature = "kogge-stone")))]
+fn magic_rook_attacks(sq: Square, mut occ: Bitboard) -> Bitboard {
+    let magics = magics();
+    let magic = &magics.rook_magics[sq as usize];
+    occ &= magic.mask;
+    occ.0 = occ.0.wrapping_mul(magic.magic);
+    occ >>= 64 - ROOK_BITS[sq];
+
+    magics.attacks[magic.offset + occ.0 as usize]
+}
+
+#[cfg(not(any(feature = "black-magic", feature = "kogge-stone")))]
+fn magic_bishop_attacks(sq: Square, mut occ: Bitboard) -> Bitboard {
+    let magics = magics();
+    let magic = &magics.bishop_magics[sq as usize];
+    occ &= magic.mask;
+    occ.0 = occ.0.wrapping_mul(magic.magic);
+    occ >>= 64 - BISHOP_BITS[sq];
+
+    magics.attacks[magic.offset + occ.0 as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::XorShift;
+    use Square::*;
+
+    #[test]
+    fn slider_attacks_match_ray_attacks() {
+        let mut prng = XorShift::new();
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+            for _ in 0..1000 {
+                let occ = Bitboard(prng.gen_next() & prng.gen_next());
+                assert_eq!(rook_attacks(sq, occ), crate::magic::rook_attacks(sq, occ));
+                assert_eq!(bishop_attacks(sq, occ), crate::magic::bishop_attacks(sq, occ));
+            }
+        }
+    }
+
+    #[test]
+    fn between_test() {
+        assert_eq!(between(A1, A4), Bitboard::from(A2) | Bitboard::from(A3));
+        assert_eq!(between(H8, E5), Bitboard::from(G7) | Bitboard::from(F6));
+        assert_eq!(between(A1, B1), Bitboard(0));
+        assert_eq!(between(A1, B3), Bitboard(0));
+    }
+
+    #[test]
+    fn line_test() {
+        assert_eq!(line(C4, C6), Bitboard::C_FILE);
+        assert_eq!(line(B2, G7), line(A1, H8));
+        assert!(line(A1, H8).is_set(D4));
+        assert_eq!(line(A1, B3), Bitboard(0));
+    }
+
+    #[test]
+    fn ray_test() {
+        assert_eq!(ray(C3, E5), Bitboard::from(D4) | Bitboard::from(E5) | Bitboard::from(F6) | Bitboard::from(G7) | Bitboard::from(H8));
+        assert_eq!(ray(E5, C3), Bitboard::from(D4) | Bitboard::from(C3) | Bitboard::from(B2) | Bitboard::from(A1));
+        assert_eq!(ray(A1, A2), Bitboard::A_FILE & !Bitboard::from(A1));
+        assert_eq!(ray(A1, B3), Bitboard(0));
+
+        assert!(aligned(A1, C3, H8));
+        assert!(aligned(E1, E4, E8));
+        assert!(!aligned(A1, C3, H7));
+        assert!(!aligned(A1, B3, C5));
+    }
+
+    #[test]
+    fn pawn_attacks_test() {
+        assert_eq!(pawn_attacks(A2, Colour::White), Bitboard::from(B3));
+        assert_eq!(pawn_attacks(E7, Colour::Black), Bitboard::from(D6) | Bitboard::from(F6));
+
+        let pawns = Bitboard::from(A2) | Bitboard::from(H2) | Bitboard::from(D4);
+        let attacks = Bitboard::from(B3) | Bitboard::from(G3) | Bitboard::from(C5) | Bitboard::from(E5);
+        assert_eq!(all_pawn_attacks(pawns, Colour::White), attacks);
+    }
+}
@@ -1,49 +1,201 @@
-use std::{env, fs, time::Instant};
-
-use crate::{engine::Engine, interface::SearchControl, position::Position, search::SearchCommand};
-
-const NUM_TESTS: usize = 50;
-const TEST_TIME: u32 = 1000;
-const TEST_DEPTH: u8 = 6;
-
-impl Engine {
-    pub fn benchmark(&mut self) {
-        let mut path = env::current_dir().unwrap();
-        path.push("arasan2023.epd");
-        let contents = fs::read_to_string(path).unwrap();
-        let tests: Vec<&str> = contents.split('\n').take(NUM_TESTS).collect();
-
-        let start_time = Instant::now();
-        self.nodes = 0;
-        for (i, test) in tests.iter().enumerate() {
-            let mut test: Vec<String> = test.split(';').map(|s| s.to_string()).collect();
-            let bm_offset = test[0].find("bm").unwrap_or(test[0].len());
-            let fen: String = test[0].drain(..bm_offset).collect();
-
-            println!("\nTest: {}/{} \"{}\"", i + 1, NUM_TESTS, fen);
-
-            self.position = Position::from_fen(&fen);
-            let mut control = SearchControl::new();
-            // control.movetime = TEST_TIME;
-            control.depth = TEST_DEPTH;
-
-            self.search(control);
-
-            while self.search_handle.is_some() {
-                self.receive_info();
-
-                if self.max_time != 0
-                    && self.search_time.elapsed().as_millis() as u32 > self.max_time
-                {
-                    self.search_tx.send(SearchCommand::Stop).unwrap();
-                    self.max_time = 0;
-                }
-            }
+// A fixed search over built-in positions. Every search runs to full depth on this thread with
+// nothing able to stop it, so the total node count is the same on every run and acts as a
+// signature for the binary in OpenBench style testing.
+
+use std::{
+    fs,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::unbounded;
+use log::warn;
+
+use crate::{
+    engine::EngineError,
+    epd::EpdRecord,
+    game::Game,
+    logging::Output,
+    movegen::Move,
+    phases::{take_totals, PhaseTotal, PHASES},
+    position::Position,
+    search::{iterative_deepening, FullInfo, SearchCommand, SearchError, SearchListener, MAX_DEPTH},
+    tt::{TranspositionTable, DEFAULT_HASH_MB},
+    send,
+};
+
+pub const DEFAULT_BENCH_DEPTH: u8 = 6;
+
+// compiled in so bench works from any directory and from a lone binary, as OpenBench workers get it
+const BENCH_EPD: &str = include_str!("bench.epd");
+
+#[derive(Debug)]
+pub struct BenchOptions {
+    pub depth: u8,
+    // searches each position for this many ms instead of to depth, which isn't deterministic
+    pub movetime: u32,
+    // an EPD file to use instead of the built-in positions
+    pub file: Option<String>,
+    // only the first count positions
+    pub count: Option<usize>,
+}
+
+impl BenchOptions {
+    pub fn new() -> Self {
+        Self {
+            depth: DEFAULT_BENCH_DEPTH,
+            movetime: 0,
+            file: None,
+            count: None,
+        }
+    }
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct BenchResult {
+    nodes: u64,
+    time: u64,
+    depth: u8,
+    score: i32,
+    best: Option<Move>,
+}
+
+// the total node count over every position, or a file error if the positions file can't be read
+pub fn benchmark(out: &Output, options: &BenchOptions) -> Result<u64, EngineError> {
+    let positions = match &options.file {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(contents) => read_positions(&contents),
+            Err(error) => return Err(EngineError::File { path: path.clone(), error }),
+        },
+        None => read_positions(BENCH_EPD),
+    };
+    let count = options.count.unwrap_or(positions.len()).min(positions.len());
+
+    // from earlier searches on this thread
+    take_totals();
+    let start_time = Instant::now();
+    let mut results = Vec::new();
+    let tt = TranspositionTable::new(DEFAULT_HASH_MB);
+    for (i, position) in positions.into_iter().take(count).enumerate() {
+        let result = bench_position(position, &tt, options);
+        send!(
+            out,
+            "Position {}/{} \"{}\": depth {} score {} best {} {} nodes {} ms {} nps",
+            i + 1,
+            count,
+            position.write_fen(),
+            result.depth,
+            result.score,
+            result.best.map_or("none".to_string(), |mv| mv.to_string()),
+            result.nodes,
+            result.time,
+            result.nodes * 1000 / result.time.max(1)
+        );
+        results.push(result);
+    }
+
+    let total_time = start_time.elapsed().as_millis().max(1) as u64;
+    let total_nodes: u64 = results.iter().map(|result| result.nodes).sum();
+    let average_depth = results.iter().map(|result| result.depth as f64).sum::<f64>() / results.len().max(1) as f64;
+    send!(out, "=============================================");
+    send!(out, "{} positions, average depth {:.1}", results.len(), average_depth);
+    send!(out, "{} ms", total_time);
+    send!(out, "{} nodes {} nps", total_nodes, total_nodes * 1000 / total_time);
+    if let Some(totals) = take_totals() {
+        write_phase_totals(out, &totals, total_time);
+    }
+
+    Ok(total_nodes)
+}
+
+// the rest of the time is the search itself, move ordering and the like
+fn write_phase_totals(out: &Output, totals: &[PhaseTotal], total_time: u64) {
+    for (phase, total) in PHASES.iter().zip(totals) {
+        let ms = total.time.as_secs_f64() * 1000.0;
+        send!(
+            out,
+            "{:<10} {:>8.0} ms {:>5.1}% {:>10} calls {:>6.1} ns/call",
+            phase.name(),
+            ms,
+            ms * 100.0 / total_time as f64,
+            total.calls,
+            total.time.as_nanos() as f64 / total.calls.max(1) as f64
+        );
+    }
+}
+
+// lines that don't parse are skipped, a suite being slightly off shouldn't stop the bench
+fn read_positions(contents: &str) -> Vec<Position> {
+    let mut positions = Vec::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match EpdRecord::parse(line) {
+            Ok(record) => positions.push(record.position),
+            Err(e) => warn!("parsing EPD: {}", e),
         }
+    }
+    positions
+}
+
+// the table is cleared first, so each position's nodes don't depend on the ones before it
+fn bench_position(position: Position, tt: &TranspositionTable, options: &BenchOptions) -> BenchResult {
+    tt.clear();
+    let (stop_tx, stop_rx) = unbounded();
+    let depth = if options.movetime != 0 {
+        // the timer outliving the search is harmless, its channel is gone by then
+        let movetime = options.movetime as u64;
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(movetime));
+            let _ = stop_tx.send(SearchCommand::Stop);
+        });
+        MAX_DEPTH as u8
+    } else {
+        options.depth
+    };
+
+    let start_time = Instant::now();
+    let mut result = BenchResult { nodes: 0, time: 0, depth: 0, score: 0, best: None };
+    // collecting the result can't fail
+    let history = Game::new(position).hash_history();
+    _ = iterative_deepening(position, depth, u32::MAX, history, tt, &mut result, stop_rx, false);
+    result.time = start_time.elapsed().as_millis() as u64;
+    result
+}
+
+impl SearchListener for BenchResult {
+    fn on_depth_complete(&mut self, info: FullInfo) -> Result<(), SearchError> {
+        self.nodes += info.nodes as u64;
+        self.depth = info.depth;
+        self.score = info.score;
+        Ok(())
+    }
+
+    fn on_finished(&mut self, best: Option<Move>) -> Result<(), SearchError> {
+        self.best = best;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_positions_parse() {
+        assert_eq!(read_positions(BENCH_EPD).len(), BENCH_EPD.lines().count());
+    }
 
-        let total_time = start_time.elapsed().as_millis();
-        let nps = self.nodes / total_time as u32 * 1000;
-        println!("=============================================");
-        println!("{} ms, {} nodes, {} nps", total_time, self.nodes, nps);
+    #[test]
+    fn bench_is_deterministic() {
+        let options = BenchOptions { depth: 3, ..BenchOptions::new() };
+        let position = read_positions(BENCH_EPD)[1];
+        let tt = TranspositionTable::new(1);
+        let nodes = bench_position(position, &tt, &options).nodes;
+        assert!(nodes > 0);
+        assert_eq!(bench_position(position, &tt, &options).nodes, nodes);
     }
 }
@@ -1,8 +1,12 @@
-use std::{fmt::{Display, Formatter}, ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Deref, DerefMut, Index, IndexMut, Not, Shl, ShlAssign, Shr, ShrAssign}};
+use core::{fmt::{Display, Formatter}, ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Deref, DerefMut, Index, IndexMut, Not, Shl, ShlAssign, Shr, ShrAssign}};
 
 use num::{Integer, FromPrimitive};
+use enum_iterator::Sequence;
 use num_derive::{FromPrimitive, ToPrimitive};
 
+use crate::position::Colour;
+use Square::*;
+
 #[derive(FromPrimitive, ToPrimitive, Clone, Copy, PartialEq, PartialOrd, Debug)]
 pub enum Square {
     A8,  B8,  C8,  D8,  E8,  F8,  G8,  H8, 
@@ -16,16 +20,69 @@ pub enum Square {
 }
 
 impl Square {
+    pub const ALL: [Square; 64] = [
+        A8, B8, C8, D8, E8, F8, G8, H8,
+        A7, B7, C7, D7, E7, F7, G7, H7,
+        A6, B6, C6, D6, E6, F6, G6, H6,
+        A5, B5, C5, D5, E5, F5, G5, H5,
+        A4, B4, C4, D4, E4, F4, G4, H4,
+        A3, B3, C3, D3, E3, F3, G3, H3,
+        A2, B2, C2, D2, E2, F2, G2, H2,
+        A1, B1, C1, D1, E1, F1, G1, H1,
+    ];
+
+    // mirrors across the line between the fourth and fifth ranks, a1 <-> a8
+    pub const fn flip_vertical(self) -> Square {
+        Square::ALL[self as usize ^ 56]
+    }
+
+    // mirrors across the line between the d and e files, a1 <-> h1
+    pub const fn flip_horizontal(self) -> Square {
+        Square::ALL[self as usize ^ 7]
+    }
+
+    // the square as seen from side's point of view, so tables written for white work for black
+    pub const fn relative_to(self, side: Colour) -> Square {
+        match side {
+            Colour::White => self,
+            Colour::Black => self.flip_vertical(),
+        }
+    }
+
+    pub fn make(file: File, rank: Rank) -> Square {
+        Square::from_u8(rank as u8 * 8 + file as u8).unwrap()
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn add(self, i: i8) -> Option<Square> {
         Square::from_i8((self as i8) + i)
     }
 
-    pub fn rank(&self) -> i8 {
-        *self as i8 / 8
+    pub fn rank(&self) -> Rank {
+        Rank::from_u8(*self as u8 / 8).unwrap()
+    }
+
+    pub fn file(&self) -> File {
+        File::from_u8(*self as u8 % 8).unwrap()
+    }
+
+    // number of king moves between the squares
+    pub fn distance(a: Square, b: Square) -> u8 {
+        DISTANCE[a as usize][b as usize]
     }
 
-    pub fn file(&self) -> i8 {
-        *self as i8 % 8
+    // number of rook steps between the squares
+    pub fn manhattan_distance(a: Square, b: Square) -> u8 {
+        MANHATTAN_DISTANCE[a as usize][b as usize]
+    }
+
+    // king moves to the nearest of d4, e4, d5 and e5
+    pub fn center_distance(self) -> u8 {
+        CENTER_DISTANCE[self]
+    }
+
+    pub fn center_manhattan_distance(self) -> u8 {
+        CENTER_MANHATTAN_DISTANCE[self]
     }
 
     pub fn from_algebraic(algebraic: &str) -> Option<Square> {
@@ -37,17 +94,88 @@ impl Square {
         if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
             return None;
         }
-        let file = file as u8 - b'a';
-        let rank = 8 - (rank as u8 - b'0');
-        Some(Square::from_u8(rank * 8 + file).unwrap())
+        let file = File::from_u8(file as u8 - b'a')?;
+        let rank = Rank::from_u8(b'8' - rank as u8)?;
+        Some(Square::make(file, rank))
+    }
+}
+
+const DISTANCE: [[u8; 64]; 64] = build_distance_tbl(false);
+const MANHATTAN_DISTANCE: [[u8; 64]; 64] = build_distance_tbl(true);
+const CENTER_DISTANCE: [u8; 64] = build_center_distance_tbl(false);
+const CENTER_MANHATTAN_DISTANCE: [u8; 64] = build_center_distance_tbl(true);
+
+const fn combine_distance(files: u8, ranks: u8, manhattan: bool) -> u8 {
+    if manhattan {
+        files + ranks
+    } else if files > ranks {
+        files
+    } else {
+        ranks
+    }
+}
+
+const fn build_distance_tbl(manhattan: bool) -> [[u8; 64]; 64] {
+    let mut distance = [[0; 64]; 64];
+
+    let mut a = 0;
+    while a < 64 {
+        let mut b = 0;
+        while b < 64 {
+            let files = (a as i8 % 8 - b as i8 % 8).unsigned_abs();
+            let ranks = (a as i8 / 8 - b as i8 / 8).unsigned_abs();
+            distance[a][b] = combine_distance(files, ranks, manhattan);
+            b += 1;
+        }
+        a += 1;
     }
+
+    distance
+}
+
+const fn build_center_distance_tbl(manhattan: bool) -> [u8; 64] {
+    let mut distance = [0; 64];
+
+    let mut sq = 0;
+    while sq < 64 {
+        // the centre occupies files d-e and ranks 4-5, indices 3 and 4 in both directions
+        let file = sq as u8 % 8;
+        let rank = sq as u8 / 8;
+        let files = if file < 4 { 3 - file } else { file - 4 };
+        let ranks = if rank < 4 { 3 - rank } else { rank - 4 };
+        distance[sq] = combine_distance(files, ranks, manhattan);
+        sq += 1;
+    }
+
+    distance
 }
 
 impl Display for Square {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let rank = *self as u8 / 8;
-        let file = *self as u8 % 8;
-        write!(f, "{}{}",(file + b'a') as char, (8 - rank))
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+#[derive(FromPrimitive, ToPrimitive, Clone, Copy, PartialEq, Eq, PartialOrd, Debug, Sequence)]
+pub enum File {
+    A, B, C, D, E, F, G, H,
+}
+
+impl Display for File {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", (b'a' + *self as u8) as char)
+    }
+}
+
+// ordered to match the square layout, so the eighth rank comes first
+#[derive(FromPrimitive, ToPrimitive, Clone, Copy, PartialEq, Eq, PartialOrd, Debug, Sequence)]
+pub enum Rank {
+    R8, R7, R6, R5, R4, R3, R2, R1,
+}
+
+impl Display for Rank {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", 8 - *self as u8)
     }
 }
 
@@ -72,9 +200,80 @@ pub struct Bitboard(pub u64);
 impl Bitboard {
     pub const A_FILE: Bitboard = Bitboard(0x0101010101010101);
     pub const B_FILE: Bitboard = Bitboard(0x0202020202020202);
+    pub const C_FILE: Bitboard = Bitboard(0x0404040404040404);
+    pub const D_FILE: Bitboard = Bitboard(0x0808080808080808);
+    pub const E_FILE: Bitboard = Bitboard(0x1010101010101010);
+    pub const F_FILE: Bitboard = Bitboard(0x2020202020202020);
     pub const G_FILE: Bitboard = Bitboard(0x4040404040404040);
     pub const H_FILE: Bitboard = Bitboard(0x8080808080808080);
 
+    // a8 is bit 0, so the eighth rank occupies the low byte
+    pub const RANK_8: Bitboard = Bitboard(0x00000000000000FF);
+    pub const RANK_7: Bitboard = Bitboard(0x000000000000FF00);
+    pub const RANK_6: Bitboard = Bitboard(0x0000000000FF0000);
+    pub const RANK_5: Bitboard = Bitboard(0x00000000FF000000);
+    pub const RANK_4: Bitboard = Bitboard(0x000000FF00000000);
+    pub const RANK_3: Bitboard = Bitboard(0x0000FF0000000000);
+    pub const RANK_2: Bitboard = Bitboard(0x00FF000000000000);
+    pub const RANK_1: Bitboard = Bitboard(0xFF00000000000000);
+
+    pub const CENTER: Bitboard = Bitboard((Self::D_FILE.0 | Self::E_FILE.0) & (Self::RANK_4.0 | Self::RANK_5.0));
+    pub const EXTENDED_CENTER: Bitboard = Bitboard(
+        (Self::C_FILE.0 | Self::D_FILE.0 | Self::E_FILE.0 | Self::F_FILE.0)
+            & (Self::RANK_3.0 | Self::RANK_4.0 | Self::RANK_5.0 | Self::RANK_6.0),
+    );
+
+    pub const QUEEN_SIDE: Bitboard = Bitboard(Self::A_FILE.0 | Self::B_FILE.0 | Self::C_FILE.0 | Self::D_FILE.0);
+    pub const KING_SIDE: Bitboard = Bitboard(Self::E_FILE.0 | Self::F_FILE.0 | Self::G_FILE.0 | Self::H_FILE.0);
+    pub const CENTER_FILES: Bitboard = Bitboard(Self::C_FILE.0 | Self::D_FILE.0 | Self::E_FILE.0 | Self::F_FILE.0);
+
+    // a8 and h1 are light
+    pub const LIGHT_SQUARES: Bitboard = Bitboard(0xAA55AA55AA55AA55);
+    pub const DARK_SQUARES: Bitboard = Bitboard(!Self::LIGHT_SQUARES.0);
+
+    pub const WHITE_HALF: Bitboard = Bitboard(Self::RANK_1.0 | Self::RANK_2.0 | Self::RANK_3.0 | Self::RANK_4.0);
+    pub const BLACK_HALF: Bitboard = Bitboard(!Self::WHITE_HALF.0);
+
+    // ranks one to four for white, five to eight for black
+    pub const fn half(side: Colour) -> Self {
+        match side {
+            Colour::White => Self::WHITE_HALF,
+            Colour::Black => Self::BLACK_HALF,
+        }
+    }
+
+    // the files around a king on the given file that a pawn storm or attack would target
+    pub const fn king_flank(file: File) -> Self {
+        match file {
+            File::A | File::B | File::C => Bitboard(Self::QUEEN_SIDE.0 & !Self::D_FILE.0),
+            File::D | File::E => Self::CENTER_FILES,
+            File::F | File::G | File::H => Bitboard(Self::KING_SIDE.0 & !Self::E_FILE.0),
+        }
+    }
+
+    // every square of the same colour as sq
+    pub const fn same_colour_squares(sq: Square) -> Self {
+        if Self::LIGHT_SQUARES.0 & (1 << sq as u64) != 0 {
+            Self::LIGHT_SQUARES
+        } else {
+            Self::DARK_SQUARES
+        }
+    }
+
+    pub const fn file(file: File) -> Self {
+        Bitboard(Self::A_FILE.0 << file as u8)
+    }
+
+    pub const fn rank(rank: Rank) -> Self {
+        Bitboard(Self::RANK_8.0 << (8 * rank as u8))
+    }
+
+    // files either side of the given file, not including the file itself
+    pub const fn adjacent_files(file: File) -> Self {
+        let file = Self::file(file).0;
+        Bitboard((file << 1 & !Self::A_FILE.0) | (file >> 1 & !Self::H_FILE.0))
+    }
+
     pub const fn from(sq: Square) -> Self {
         Bitboard(1 << sq as u64)
     }
@@ -99,19 +298,102 @@ impl Bitboard {
         Square::from_u32(self.0.trailing_zeros())
     }
 
+    pub fn pop_lsb(&mut self) -> Option<Square> {
+        let sq = self.get_lsb();
+        self.0 &= self.0.wrapping_sub(1);
+        sq
+    }
+
+    pub fn msb(&self) -> Option<Square> {
+        63u32.checked_sub(self.0.leading_zeros()).and_then(Square::from_u32)
+    }
+
+    pub fn popcount(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub fn more_than_one(&self) -> bool {
+        self.0 & self.0.wrapping_sub(1) != 0
+    }
+
+    pub fn only_one(&self) -> bool {
+        self.0 != 0 && !self.more_than_one()
+    }
+
+    // smears every set bit towards the eighth rank
+    pub const fn north_fill(self) -> Self {
+        let mut bb = self.0;
+        bb |= bb >> 8;
+        bb |= bb >> 16;
+        bb |= bb >> 32;
+        Bitboard(bb)
+    }
+
+    // smears every set bit towards the first rank
+    pub const fn south_fill(self) -> Self {
+        let mut bb = self.0;
+        bb |= bb << 8;
+        bb |= bb << 16;
+        bb |= bb << 32;
+        Bitboard(bb)
+    }
+
+    // fills towards the opponent's side of the board from the point of view of side
+    pub const fn front_fill(self, side: Colour) -> Self {
+        match side {
+            Colour::White => self.north_fill(),
+            Colour::Black => self.south_fill(),
+        }
+    }
+
+    pub const fn rear_fill(self, side: Colour) -> Self {
+        match side {
+            Colour::White => self.south_fill(),
+            Colour::Black => self.north_fill(),
+        }
+    }
+
+    // every file containing at least one set bit
+    pub const fn file_fill(self) -> Self {
+        Bitboard(self.north_fill().0 | self.south_fill().0)
+    }
+
+    // mirrors the ranks, so each square maps to Square::flip_vertical
+    pub const fn flip_vertical(self) -> Self {
+        Bitboard(self.0.swap_bytes())
+    }
+
     pub fn intersects(&self, other: Bitboard) -> bool {
         (self.0 & other.0) != 0
     }
+
+    // every subset of the set bits, starting with the empty board, in the same order as
+    // counting up through the bits of the mask
+    pub fn subsets(self) -> Subsets {
+        Subsets { mask: self, next: Some(Bitboard(0)) }
+    }
+}
+
+pub struct Subsets {
+    mask: Bitboard,
+    next: Option<Bitboard>,
+}
+
+impl Iterator for Subsets {
+    type Item = Bitboard;
+    fn next(&mut self) -> Option<Self::Item> {
+        let subset = self.next?;
+        // carry-rippler: borrowing through the unset bits of the mask increments the subset
+        let next = Bitboard(subset.0.wrapping_sub(self.mask.0) & self.mask.0);
+        self.next = (!next.is_empty()).then_some(next);
+        Some(subset)
+    }
 }
 
 impl Iterator for Bitboard {
     type Item = Square;
     fn next(&mut self) -> Option<Self::Item> {
-        let sq = self.get_lsb();
-        if let Some(sq) = sq {
-            self.reset(sq);
-        }
-        sq
+        self.pop_lsb()
     }
 }
 
@@ -129,7 +411,7 @@ impl DerefMut for Bitboard {
 }
 
 impl Display for Bitboard {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         writeln!(f)?;
         let mut sq = 0;
         for rank in (1..=8).rev() {
@@ -247,3 +529,108 @@ where
         self.0 <<= rhs;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_and_rank_test() {
+        assert_eq!(Bitboard::file(Square::C4.file()), Bitboard::C_FILE);
+        assert_eq!(Bitboard::rank(Square::C4.rank()), Bitboard::RANK_4);
+        assert_eq!(Bitboard::adjacent_files(File::A), Bitboard::B_FILE);
+        assert_eq!(Bitboard::adjacent_files(File::E), Bitboard::D_FILE | Bitboard::F_FILE);
+        assert_eq!(Bitboard::adjacent_files(File::H), Bitboard::G_FILE);
+        for sq in (0..64).map(|i| Square::from_usize(i).unwrap()) {
+            assert!(Bitboard::file(sq.file()).is_set(sq));
+            assert!(Bitboard::rank(sq.rank()).is_set(sq));
+        }
+    }
+
+    #[test]
+    fn square_coordinates_test() {
+        assert_eq!(Square::make(File::E, Rank::R4), Square::E4);
+        assert_eq!(Square::E4.file(), File::E);
+        assert_eq!(Square::E4.rank(), Rank::R4);
+        assert_eq!(Square::from_algebraic("g7"), Some(Square::G7));
+        assert_eq!(Square::H1.to_string(), "h1");
+
+        assert_eq!(Square::distance(Square::A1, Square::H8), 7);
+        assert_eq!(Square::distance(Square::E4, Square::F6), 2);
+        assert_eq!(Square::distance(Square::C3, Square::C3), 0);
+        assert_eq!(Square::manhattan_distance(Square::A1, Square::H8), 14);
+        assert_eq!(Square::manhattan_distance(Square::E4, Square::F6), 3);
+        assert_eq!(Square::A1.center_distance(), 3);
+        assert_eq!(Square::B7.center_manhattan_distance(), 4);
+        assert_eq!(Square::E5.center_distance(), 0);
+
+        assert_eq!(Square::B2.flip_vertical(), Square::B7);
+        assert_eq!(Square::B2.flip_horizontal(), Square::G2);
+        assert_eq!(Square::C3.relative_to(Colour::White), Square::C3);
+        assert_eq!(Square::C3.relative_to(Colour::Black), Square::C6);
+        assert!(Square::ALL.iter().enumerate().all(|(i, sq)| *sq as usize == i));
+
+        let squares: Vec<Square> = enum_iterator::all::<Rank>()
+            .flat_map(|rank| enum_iterator::all::<File>().map(move |file| Square::make(file, rank)))
+            .collect();
+        assert_eq!(squares, (0..64).map(|i| Square::from_usize(i).unwrap()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn region_test() {
+        assert_eq!(Bitboard::CENTER, Bitboard::from(D4) | Bitboard::from(E4) | Bitboard::from(D5) | Bitboard::from(E5));
+        assert_eq!(Bitboard::EXTENDED_CENTER.count_ones(), 16);
+        assert!(Bitboard::EXTENDED_CENTER.is_set(C3) && Bitboard::EXTENDED_CENTER.is_set(F6));
+
+        assert!(Bitboard::LIGHT_SQUARES.is_set(H1) && Bitboard::LIGHT_SQUARES.is_set(A8));
+        assert!(Bitboard::DARK_SQUARES.is_set(A1) && Bitboard::DARK_SQUARES.is_set(H8));
+        assert_eq!(Bitboard::same_colour_squares(E4), Bitboard::LIGHT_SQUARES);
+
+        assert!(Bitboard::half(Colour::White).is_set(E4) && !Bitboard::half(Colour::White).is_set(E5));
+        assert_eq!(Bitboard::half(Colour::Black), Bitboard::RANK_5 | Bitboard::RANK_6 | Bitboard::RANK_7 | Bitboard::RANK_8);
+
+        assert_eq!(Bitboard::king_flank(File::G), Bitboard::F_FILE | Bitboard::G_FILE | Bitboard::H_FILE);
+        assert_eq!(Bitboard::king_flank(File::E), Bitboard::CENTER_FILES);
+        assert_eq!(Bitboard::QUEEN_SIDE | Bitboard::KING_SIDE, Bitboard(!0));
+    }
+
+    #[test]
+    fn subsets_test() {
+        let mask = Bitboard::from(Square::B2) | Bitboard::from(Square::E4) | Bitboard::from(Square::H8);
+        let subsets: Vec<Bitboard> = mask.subsets().collect();
+        assert_eq!(subsets.len(), 8);
+        assert_eq!(subsets[0], Bitboard(0));
+        assert_eq!(subsets[1], Bitboard::from(Square::H8));
+        assert_eq!(subsets[7], mask);
+        assert!(subsets.iter().all(|subset| *subset & !mask == Bitboard(0)));
+
+        assert_eq!(Bitboard(0).subsets().collect::<Vec<_>>(), vec![Bitboard(0)]);
+    }
+
+    #[test]
+    fn bit_utilities_test() {
+        let mut bb = Bitboard::from(Square::C7) | Bitboard::from(Square::F2);
+        assert!(bb.more_than_one());
+        assert!(!bb.only_one());
+        assert_eq!(bb.msb(), Some(Square::F2));
+        assert_eq!(bb.pop_lsb(), Some(Square::C7));
+        assert!(bb.only_one());
+        assert_eq!(bb.pop_lsb(), Some(Square::F2));
+        assert_eq!(bb.pop_lsb(), None);
+        assert_eq!(bb.msb(), None);
+        assert!(!bb.only_one());
+    }
+
+    #[test]
+    fn fill_test() {
+        let pawn = Bitboard::from(Square::D4);
+        let above = Bitboard::from(Square::D5) | Bitboard::from(Square::D6)
+            | Bitboard::from(Square::D7) | Bitboard::from(Square::D8);
+        assert_eq!(pawn.front_fill(Colour::White), pawn | above);
+        assert_eq!(pawn.rear_fill(Colour::Black), pawn | above);
+        assert_eq!(pawn.front_fill(Colour::Black), Bitboard::D_FILE & !above);
+        assert_eq!(pawn.file_fill(), Bitboard::D_FILE);
+        assert_eq!(pawn.flip_vertical(), Bitboard::from(Square::D5));
+        assert_eq!(Bitboard::RANK_2.flip_vertical(), Bitboard::RANK_7);
+    }
+}
@@ -0,0 +1,288 @@
+// Black magic bitboards: the complement of the mask is OR'd into the occupancy before the multiply,
+// which lets every square share one fixed shift instead of looking up a per-square bit count.
+//
+// Every square's table lives in one shared attack array at its own offset, overlapping the others
+// wherever they agree. The magics and offsets are Volker Annuss's published ones, searched for how
+// tightly they pack: 87988 entries (about 700 KB) rather than the 294912 of a full table per square.
+
+use spin::Once;
+use crate::prelude::*;
+
+use num::FromPrimitive;
+
+use crate::{
+    bitboard::{Bitboard, Square},
+    magic::{bishop_attacks_mask, rook_attacks_mask},
+};
+
+const ROOK_INDEX_BITS: u32 = 12;
+const BISHOP_INDEX_BITS: u32 = 9;
+const ATTACKS_LEN: usize = 87988;
+
+static BLACK_MAGICS: Once<BlackMagics> = Once::new();
+
+pub fn tables() -> &'static BlackMagics {
+    BLACK_MAGICS.call_once(BlackMagics::init)
+}
+
+pub struct BlackMagics {
+    pub rook_not_masks: [Bitboard; 64],
+    pub bishop_not_masks: [Bitboard; 64],
+    pub attacks: Vec<Bitboard>,
+}
+
+impl BlackMagics {
+    fn init() -> Self {
+        let mut magics = BlackMagics {
+            rook_not_masks: [Bitboard(0); 64],
+            bishop_not_masks: [Bitboard(0); 64],
+            attacks: vec![Bitboard(0); ATTACKS_LEN],
+        };
+
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+            magics.rook_not_masks[i] = !rook_attacks_mask(sq);
+            magics.bishop_not_masks[i] = !bishop_attacks_mask(sq);
+            for (idx, attacks) in table_entries(sq, true, ROOK_BLACK_MAGICS[i]) {
+                magics.attacks[ROOK_OFFSETS[i] + idx] = attacks;
+            }
+            for (idx, attacks) in table_entries(sq, false, BISHOP_BLACK_MAGICS[i]) {
+                magics.attacks[BISHOP_OFFSETS[i] + idx] = attacks;
+            }
+        }
+
+        magics
+    }
+}
+
+// the index and attacks of every occupancy of the square's mask, in index order
+fn table_entries(sq: Square, rook: bool, magic: u64) -> Vec<(usize, Bitboard)> {
+    let mut entries: Vec<(usize, Bitboard)> = if rook {
+        let mask = rook_attacks_mask(sq);
+        mask.subsets().map(|occ| (rook_index(occ, !mask, magic), crate::magic::rook_attacks(sq, occ))).collect()
+    } else {
+        let mask = bishop_attacks_mask(sq);
+        mask.subsets().map(|occ| (bishop_index(occ, !mask, magic), crate::magic::bishop_attacks(sq, occ))).collect()
+    };
+    entries.sort_by_key(|&(idx, _)| idx);
+    entries.dedup();
+    entries
+}
+
+fn rook_index(occ: Bitboard, not_mask: Bitboard, magic: u64) -> usize {
+    ((occ | not_mask).0.wrapping_mul(magic) >> (64 - ROOK_INDEX_BITS)) as usize
+}
+
+fn bishop_index(occ: Bitboard, not_mask: Bitboard, magic: u64) -> usize {
+    ((occ | not_mask).0.wrapping_mul(magic) >> (64 - BISHOP_INDEX_BITS)) as usize
+}
+
+pub fn rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    let sq = sq as usize;
+    let tables = tables();
+    let idx = rook_index(occ, tables.rook_not_masks[sq], ROOK_BLACK_MAGICS[sq]);
+    tables.attacks[ROOK_OFFSETS[sq] + idx]
+}
+
+pub fn bishop_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    let sq = sq as usize;
+    let tables = tables();
+    let idx = bishop_index(occ, tables.bishop_not_masks[sq], BISHOP_BLACK_MAGICS[sq]);
+    tables.attacks[BISHOP_OFFSETS[sq] + idx]
+}
+
+pub const ROOK_OFFSETS: [usize; 64] = [
+    10890, 50579, 62020, 67322, 80251, 58503, 51175, 83130,
+    50430, 21613, 72625, 80755, 69753, 26973, 84972, 31958,
+    69272, 48372, 65477, 43972, 57154, 53521, 30534, 16548,
+    46407, 11841, 21112, 44214, 57925, 29574, 17309, 40143,
+    64659, 70469, 62917, 60997, 18554, 14385, 0, 38091,
+    25122, 60083, 72209, 67875, 56290, 43807, 73365, 76398,
+    20024, 9513, 24324, 22996, 23213, 56002, 22809, 44545,
+    36072, 4750, 6014, 36054, 78538, 28745, 8555, 1009,
+];
+
+pub const BISHOP_OFFSETS: [usize; 64] = [
+    60984, 66046, 32910, 16369, 42115, 835, 18910, 25911,
+    63301, 16063, 17481, 59361, 18735, 61249, 68938, 61791,
+    21893, 62068, 19829, 26091, 15815, 16419, 59777, 16288,
+    33235, 15459, 15863, 75555, 79445, 15917, 8512, 73069,
+    16078, 19168, 11056, 62544, 80477, 75049, 32947, 59172,
+    55845, 61806, 73601, 15546, 45243, 20333, 33402, 25917,
+    32875, 4639, 17077, 62324, 18159, 61436, 57073, 61025,
+    81259, 64083, 56114, 57058, 58912, 22194, 70880, 11140,
+];
+
+pub const ROOK_BLACK_MAGICS: [u64; 64] = [
+    0x80280013ff84ffff,
+    0x5ffbfefdfef67fff,
+    0xffeffaffeffdffff,
+    0x003000900300008a,
+    0x0050028010500023,
+    0x0020012120a00020,
+    0x0030006000c00030,
+    0x0058005806b00002,
+    0x7fbff7fbfbeafffc,
+    0x0000140081050002,
+    0x0000180043800048,
+    0x7fffe800021fffb8,
+    0xffffcffe7fcfffaf,
+    0x00001800c0180060,
+    0x4f8018005fd00018,
+    0x0000180030620018,
+    0x00300018010c0003,
+    0x0003000c0085ffff,
+    0xfffdfff7fbfefff7,
+    0x7fc1ffdffc001fff,
+    0xfffeffdffdffdfff,
+    0x7c108007befff81f,
+    0x20408007bfe00810,
+    0x0400800558604100,
+    0x0040200010080008,
+    0x0010020008040004,
+    0xfffdfefff7fbfff7,
+    0xfebf7dfff8fefff9,
+    0xc00000ffe001ffe0,
+    0x4af01f00078007c3,
+    0xbffbfafffb683f7f,
+    0x0807f67ffa102040,
+    0x200008e800300030,
+    0x0000008780180018,
+    0x0000010300180018,
+    0x4000008180180018,
+    0x008080310005fffa,
+    0x4000188100060006,
+    0xffffff7fffbfbfff,
+    0x0000802000200040,
+    0x20000202ec002800,
+    0xfffff9ff7cfff3ff,
+    0x000000404b801800,
+    0x2000002fe03fd000,
+    0xffffff6ffe7fcffd,
+    0xbff7efffbfc00fff,
+    0x000000100800a804,
+    0x6054000a58005805,
+    0x0829000101150028,
+    0x00000085008a0014,
+    0x8000002b00408028,
+    0x4000002040790028,
+    0x7800002010288028,
+    0x0000001800e08018,
+    0xa3a80003f3a40048,
+    0x2003d80000500028,
+    0xfffff37eefefdfbe,
+    0x40000280090013c1,
+    0xbf7ffeffbffaf71f,
+    0xfffdffff777b7d6e,
+    0x48300007e8080c02,
+    0xafe0000fff780402,
+    0xee73fffbffbb77fe,
+    0x0002000308482882,
+];
+
+pub const BISHOP_BLACK_MAGICS: [u64; 64] = [
+    0xa7020080601803d8,
+    0x13802040400801f1,
+    0x0a0080181001f60c,
+    0x1840802004238008,
+    0xc03fe00100000000,
+    0x24c00bffff400000,
+    0x0808101f40007f04,
+    0x100808201ec00080,
+    0xffa2feffbfefb7ff,
+    0x083e3ee040080801,
+    0xc0800080181001f8,
+    0x0440007fe0031000,
+    0x2010007ffc000000,
+    0x1079ffe000ff8000,
+    0x3c0708101f400080,
+    0x080614080fa00040,
+    0x7ffe7fff817fcff9,
+    0x7ffebfffa01027fd,
+    0x53018080c00f4001,
+    0x407e0001000ffb8a,
+    0x201fe000fff80010,
+    0xffdfefffde39ffef,
+    0xcc8808000fbf8002,
+    0x7ff7fbfff8203fff,
+    0x8800013e8300c030,
+    0x0420009701806018,
+    0x7ffeff7f7f01f7fd,
+    0x8700303010c0c006,
+    0xc800181810606000,
+    0x20002038001c8010,
+    0x087ff038000fc001,
+    0x00080c0c00083007,
+    0x00000080fc82c040,
+    0x000000407e416020,
+    0x00600203f8008020,
+    0xd003fefe04404080,
+    0xa00020c018003088,
+    0x7fbffe700bffe800,
+    0x107ff00fe4000f90,
+    0x7f8fffcff1d007f8,
+    0x0000004100f88080,
+    0x00000020807c4040,
+    0x00000041018700c0,
+    0x0010000080fc4080,
+    0x1000003c80180030,
+    0xc10000df80280050,
+    0xffffffbfeff80fdc,
+    0x000000101003f812,
+    0x0800001f40808200,
+    0x084000101f3fd208,
+    0x080000000f808081,
+    0x0004000008003f80,
+    0x08000001001fe040,
+    0x72dd000040900a00,
+    0xfffffeffbfeff81d,
+    0xcd8000200febf209,
+    0x100000101ec10082,
+    0x7fbaffffefe0c02f,
+    0x7f83fffffff07f7f,
+    0xfff1fffffff7ffc1,
+    0x0878040000ffe01f,
+    0x945e388000801012,
+    0x0840800080200fda,
+    0x100000c05f582008,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::XorShift;
+
+    #[test]
+    fn black_magics_match_ray_attacks() {
+        let mut prng = XorShift::new();
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+            for _ in 0..1000 {
+                let occ = Bitboard(prng.gen_next() & prng.gen_next());
+                assert_eq!(rook_attacks(sq, occ), crate::magic::rook_attacks(sq, occ));
+                assert_eq!(
+                    bishop_attacks(sq, occ),
+                    crate::magic::bishop_attacks(sq, occ)
+                );
+            }
+        }
+    }
+
+    // every occupancy of every square, so an entry two squares share with different attacks shows
+    #[test]
+    fn shared_table_agrees() {
+        let mut end = 0;
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+            for (idx, attacks) in table_entries(sq, true, ROOK_BLACK_MAGICS[i]) {
+                assert_eq!(tables().attacks[ROOK_OFFSETS[i] + idx], attacks);
+                end = end.max(ROOK_OFFSETS[i] + idx + 1);
+            }
+            for (idx, attacks) in table_entries(sq, false, BISHOP_BLACK_MAGICS[i]) {
+                assert_eq!(tables().attacks[BISHOP_OFFSETS[i] + idx], attacks);
+                end = end.max(BISHOP_OFFSETS[i] + idx + 1);
+            }
+        }
+        assert_eq!(end, ATTACKS_LEN);
+    }
+}
@@ -0,0 +1,128 @@
+// Chess960 start positions by their standard (Scharnagl) number, 518 being the normal start, and
+// Double Fischer Random ones where each side gets its own number.
+
+use crate::prelude::*;
+use crate::position::Position;
+
+pub const N_CHESS960_POSITIONS: usize = 960;
+
+// squares of the two knights among the five left after placing the bishops and queen
+const KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+impl Position {
+    pub fn chess960(n: usize) -> Option<Position> {
+        Position::dfrc(n, n)
+    }
+
+    // white's back rank is position white and black's is position black, mirrored
+    pub fn dfrc(white: usize, black: usize) -> Option<Position> {
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            chess960_back_rank(black)?.to_ascii_lowercase(),
+            chess960_back_rank(white)?
+        );
+
+        Position::try_from_fen(&fen).ok()
+    }
+}
+
+// white's back rank from a to h, e.g. "RNBQKBNR"
+fn chess960_back_rank(mut n: usize) -> Option<String> {
+    if n >= N_CHESS960_POSITIONS {
+        return None;
+    }
+
+    let mut rank = [None; 8];
+    // light squared bishop on b, d, f or h, then the dark one on a, c, e or g
+    rank[n % 4 * 2 + 1] = Some('B');
+    n /= 4;
+    rank[n % 4 * 2] = Some('B');
+    n /= 4;
+
+    let mut place = |piece, nth: usize| {
+        let file = (0..8).filter(|&f| rank[f].is_none()).nth(nth).unwrap();
+        rank[file] = Some(piece);
+    };
+    place('Q', n % 6);
+    n /= 6;
+
+    // the second knight's index shifts down once the first fills an earlier square
+    let (first, second) = KNIGHTS[n];
+    place('N', first);
+    place('N', second - 1);
+
+    // the king always goes between the rooks
+    place('R', 0);
+    place('K', 0);
+    place('R', 0);
+
+    Some(rank.iter().map(|p| p.unwrap()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    #[test]
+    fn chess960_numbering() {
+        assert_eq!(chess960_back_rank(0).unwrap(), "BBQNNRKR");
+        assert_eq!(chess960_back_rank(518).unwrap(), "RNBQKBNR");
+        assert_eq!(chess960_back_rank(959).unwrap(), "RKRNNQBB");
+        assert_eq!(chess960_back_rank(960), None);
+
+        assert_eq!(Position::chess960(518).unwrap().write_fen(), STARTING_FEN);
+        let position = Position::chess960(0).unwrap();
+        assert_eq!(position.write_shredder_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1");
+    }
+
+    #[test]
+    fn chess960_positions_are_distinct() {
+        let mut ranks: Vec<String> = (0..N_CHESS960_POSITIONS).map(|n| chess960_back_rank(n).unwrap()).collect();
+        ranks.sort();
+        ranks.dedup();
+        assert_eq!(ranks.len(), N_CHESS960_POSITIONS);
+        assert!((0..N_CHESS960_POSITIONS).all(|n| Position::chess960(n).is_some()));
+    }
+
+    #[test]
+    fn dfrc_positions() {
+        assert_eq!(Position::dfrc(518, 518), Position::chess960(518));
+        assert_eq!(Position::dfrc(0, 960), None);
+
+        let position = Position::dfrc(0, 959).unwrap();
+        assert_eq!(position.write_fen(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(position.write_shredder_fen(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFca - 0 1");
+
+        for (white, black) in [(0, 959), (518, 0), (123, 456), (959, 1)] {
+            let position = Position::dfrc(white, black).unwrap();
+            for fen in [position.write_fen(), position.write_shredder_fen()] {
+                let parsed = Position::try_from_fen(&fen).unwrap();
+                assert_eq!(parsed, position);
+            }
+        }
+    }
+
+    // each side castles with its own king and rook files
+    #[test]
+    fn dfrc_castling() {
+        let mut position = Position::from_fen("2r3kr/8/8/8/8/8/2P5/RK5R w KQkq - 0 1");
+        assert_eq!(position.write_shredder_fen(), "2r3kr/8/8/8/8/8/2P5/RK5R w HAhc - 0 1");
+
+        // black's king stays put when castling kingside, so it's written as king takes rook
+        for (mv, fen) in [
+            ("b1a1", "2r3kr/8/8/8/8/8/2P5/2KR3R b kq - 1 1"),
+            ("g8h8", "2r2rk1/8/8/8/8/8/2P5/2KR3R w - - 2 2"),
+        ] {
+            let mv = position.find_algebraic_move(mv).unwrap();
+            assert!(position.is_legal(mv));
+            position.make_move(mv);
+            assert_eq!(position.write_fen(), fen);
+        }
+
+        let mut position = Position::from_fen("2r3kr/8/8/8/8/8/2P5/RK5R b KQkq - 0 1");
+        let mv = position.find_algebraic_move("g8c8").unwrap();
+        position.make_move(mv);
+        assert_eq!(position.write_fen(), "2kr3r/8/8/8/8/8/2P5/RK5R w KQ - 1 2");
+    }
+}
@@ -0,0 +1,78 @@
+// Runtime CPU feature detection. The release binary is built for a baseline target, hot paths
+// check these flags once detected and switch to the faster instructions where available.
+
+use core::fmt::Display;
+
+use spin::Once;
+use crate::prelude::*;
+
+static FEATURES: Once<CpuFeatures> = Once::new();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuFeatures {
+    pub popcnt: bool,
+    pub bmi2: bool,
+    // BMI2 with a pext worth using over magics
+    pub fast_pext: bool,
+    pub avx2: bool,
+}
+
+impl CpuFeatures {
+    #[cfg(all(target_arch = "x86_64", feature = "std"))]
+    fn detect() -> Self {
+        let bmi2 = is_x86_feature_detected!("bmi2");
+        CpuFeatures {
+            popcnt: is_x86_feature_detected!("popcnt"),
+            bmi2,
+            fast_pext: bmi2 && !microcoded_pext(),
+            avx2: is_x86_feature_detected!("avx2"),
+        }
+    }
+
+    // without std there's no runtime detection, only what the target was built for
+    #[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+    fn detect() -> Self {
+        CpuFeatures {
+            popcnt: cfg!(target_feature = "popcnt"),
+            bmi2: cfg!(target_feature = "bmi2"),
+            fast_pext: cfg!(target_feature = "bmi2") && !microcoded_pext(),
+            avx2: cfg!(target_feature = "avx2"),
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn detect() -> Self {
+        CpuFeatures::default()
+    }
+}
+
+impl Display for CpuFeatures {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let names = [("popcnt", self.popcnt), ("bmi2", self.bmi2), ("fast-pext", self.fast_pext), ("avx2", self.avx2)];
+        let detected: Vec<&str> = names.iter().filter(|(_, has)| *has).map(|(name, _)| *name).collect();
+        if detected.is_empty() {
+            write!(f, "portable")
+        } else {
+            write!(f, "{}", detected.join(" "))
+        }
+    }
+}
+
+// AMD ran pext in microcode before Zen 3, family 0x19, taking hundreds of cycles where magics
+// take a few
+#[cfg(target_arch = "x86_64")]
+fn microcoded_pext() -> bool {
+    use core::arch::x86_64::__cpuid;
+
+    let vendor = __cpuid(0);
+    // AuthenticAMD
+    let amd = (vendor.ebx, vendor.edx, vendor.ecx) == (0x6874_7541, 0x6974_6e65, 0x444d_4163);
+    let signature = __cpuid(1).eax;
+    let base_family = signature >> 8 & 0xf;
+    let family = if base_family == 0xf { base_family + (signature >> 20 & 0xff) } else { base_family };
+    amd && family <= 0x17
+}
+
+pub fn features() -> &'static CpuFeatures {
+    FEATURES.call_once(CpuFeatures::detect)
+}
@@ -0,0 +1,101 @@
+// Field by field differences between two positions, for hunting make_move and incremental hash
+// bugs. Hash components are recomputed from scratch so a bad incremental key shows which part of
+// the position it went wrong on.
+
+use enum_iterator::all;
+
+use crate::prelude::*;
+use crate::{
+    position::{Colour::*, Piece, Position},
+    zobrist::ZOBRIST_CODES,
+};
+
+impl Position {
+    // one line per difference, empty when the positions are identical
+    pub fn diff(&self, other: &Position) -> Vec<String> {
+        let mut diffs = Vec::new();
+        let mut check = |name: &str, a: String, b: String| {
+            if a != b {
+                diffs.push(format!("{}: {} vs {}", name, a, b));
+            }
+        };
+
+        for piece in all::<Piece>() {
+            check(&format!("pieces {}", piece), hex(self.pieces[piece].0), hex(other.pieces[piece].0));
+        }
+        for side in [White, Black] {
+            check(&format!("occupancy {}", side), hex(self.occupancy[side].0), hex(other.occupancy[side].0));
+        }
+        check("turn", self.turn.to_string(), other.turn.to_string());
+        check("castling", format!("{:?}", self.castling), format!("{:?}", other.castling));
+        check("castling rooks", format!("{:?}", self.castling_rooks), format!("{:?}", other.castling_rooks));
+        check("en passant", format!("{:?}", self.en_passant), format!("{:?}", other.en_passant));
+        check("halfmove", self.halfmove.to_string(), other.halfmove.to_string());
+        check("ply", self.ply.to_string(), other.ply.to_string());
+        check(
+            "last irreversible ply",
+            self.last_irreversible_ply.to_string(),
+            other.last_irreversible_ply.to_string(),
+        );
+        check("hash", hex(self.hash), hex(other.hash));
+        check("pawn key", hex(self.pawn_key), hex(other.pawn_key));
+        check("material key", hex(self.material_key.0), hex(other.material_key.0));
+
+        for ((name, a), (_, b)) in self.hash_components().into_iter().zip(other.hash_components()) {
+            check(&format!("{} hash", name), hex(a), hex(b));
+        }
+
+        diffs
+    }
+
+    // the parts of the hash computed from scratch, which xor together to the full hash
+    fn hash_components(&self) -> [(&'static str, u64); 4] {
+        let mut pieces = 0;
+        for piece in all::<Piece>() {
+            for sq in self.pieces[piece] {
+                pieces ^= ZOBRIST_CODES.piece(piece, sq);
+            }
+        }
+        let en_passant = self.en_passant.map_or(0, |sq| ZOBRIST_CODES.en_passant(sq));
+        let turn = if self.turn == White { ZOBRIST_CODES.turn() } else { 0 };
+
+        [
+            ("piece", pieces),
+            ("castling", ZOBRIST_CODES.castling(self.castling)),
+            ("en passant", en_passant),
+            ("turn", turn),
+        ]
+    }
+}
+
+fn hex(x: u64) -> String {
+    format!("{:016X}", x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    #[test]
+    fn diff_test() {
+        let position = Position::from_fen(STARTING_FEN);
+        assert!(position.diff(&position).is_empty());
+
+        let mut moved = position;
+        let mv = moved.find_algebraic_move("e2e4").unwrap();
+        moved.make_move(mv);
+        let diffs = moved.diff(&position);
+        assert!(diffs.iter().any(|d| d.starts_with("pieces P: ")));
+        assert!(diffs.iter().any(|d| d.starts_with("turn: b vs w")));
+        assert!(diffs.iter().any(|d| d.starts_with("en passant: Some(E3) vs None")));
+        assert!(diffs.iter().any(|d| d.starts_with("pawn key: ")));
+        assert!(!diffs.iter().any(|d| d.starts_with("castling") || d.starts_with("material key")));
+
+        // a corrupted incremental hash shows up against a recomputed one
+        let mut fresh = moved;
+        moved.hash ^= 1;
+        fresh.gen_zobrist_hash();
+        assert_eq!(moved.diff(&fresh), [format!("hash: {} vs {}", hex(moved.hash), hex(fresh.hash))]);
+    }
+}
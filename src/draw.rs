@@ -0,0 +1,106 @@
+// Draw detection shared by the search and anyone adjudicating games. History is a ring buffer
+// indexed by ply modulo its length and holds the hash of the position at that ply, the way the
+// engine and search keep it, so only the last history.len() plies are looked at.
+
+use crate::{
+    bitboard::Bitboard,
+    position::{
+        Colour::{self, *},
+        Piece::{self, *},
+        Position,
+    },
+};
+
+pub const HALFMOVE_DRAW_COUNT: u8 = 100;
+
+impl Position {
+    pub fn is_draw(&self, history: &[u64]) -> bool {
+        self.is_fifty_move_draw() || self.repetitions(history) >= 2 || self.is_insufficient_material()
+    }
+
+    // doesn't look for mate on the hundredth halfmove, which takes precedence
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove >= HALFMOVE_DRAW_COUNT
+    }
+
+    // earlier occurrences of this position since the last irreversible move, so 2 is threefold
+    pub fn repetitions(&self, history: &[u64]) -> usize {
+        let mut ply = self.ply as usize;
+        let start = (self.last_irreversible_ply as usize).max(ply.saturating_sub(history.len()));
+        let mut count = 0;
+        while ply >= start + 2 {
+            ply -= 2;
+            if history[ply % history.len()] == self.hash {
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    // neither side can ever mate: no pawns or majors, and either one minor piece at most or only
+    // bishops that all stand on one colour
+    pub fn is_insufficient_material(&self) -> bool {
+        let count = |piece: fn(Colour) -> Piece| self.material_key.count(piece(White)) + self.material_key.count(piece(Black));
+        if count(Pawn) + count(Rook) + count(Queen) > 0 {
+            return false;
+        }
+
+        let bishops = self.pieces[Bishop(White)] | self.pieces[Bishop(Black)];
+        let one_colour_bishops = !bishops.intersects(Bitboard::LIGHT_SQUARES) || !bishops.intersects(Bitboard::DARK_SQUARES);
+
+        count(Knight) + count(Bishop) <= 1 || count(Knight) == 0 && one_colour_bishops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(position: &mut Position, history: &mut [u64], moves: &[&str]) {
+        for mv in moves {
+            history[position.ply as usize % history.len()] = position.hash;
+            let mv = position.find_algebraic_move(mv).unwrap();
+            position.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn repetition_test() {
+        let mut position = Position::from_fen(crate::fen::STARTING_FEN);
+        let mut history = [0; 32];
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        play(&mut position, &mut history, &shuffle);
+        assert_eq!(position.repetitions(&history), 1);
+        assert!(!position.is_draw(&history));
+
+        play(&mut position, &mut history, &shuffle);
+        assert_eq!(position.repetitions(&history), 2);
+        assert!(position.is_draw(&history));
+
+        // a pawn move resets the count
+        play(&mut position, &mut history, &["e2e3", "e7e6"]);
+        play(&mut position, &mut history, &shuffle);
+        assert_eq!(position.repetitions(&history), 1);
+    }
+
+    #[test]
+    fn fifty_move_test() {
+        assert!(Position::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").is_draw(&[]));
+        assert!(!Position::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").is_draw(&[]));
+    }
+
+    #[test]
+    fn insufficient_material_test() {
+        let insufficient = |fen| Position::from_fen(fen).is_insufficient_material();
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/4KB2 w - - 0 1"));
+        assert!(insufficient("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1"));
+        assert!(!insufficient("1b2k3/8/8/8/8/8/8/4KB2 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/4KNN1 w - - 0 1"));
+        assert!(!insufficient("4kn2/8/8/8/8/8/8/4KB2 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+    }
+}
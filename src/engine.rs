@@ -1,133 +1,503 @@
 use std::{
-    error::Error,
-    io,
-    thread::{self, JoinHandle},
-    time::Instant,
+    fmt::Display,
+    io::{self, BufRead, BufReader},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{after, bounded, never, select, unbounded, Receiver, RecvError, Sender};
+use log::{error, warn};
 
 use crate::{
-    eval::evaluate,
+    attacks,
+    benchmark::benchmark,
+    cpu,
+    eval::eval_terms,
     fen::STARTING_FEN,
+    game::{Game, MAX_GAME_PLY},
     interface::{
-        id, parse_command, write_currmove_info, write_full_info, Command::*, SearchControl,
+        id, parse_command, write_currmove_info, write_full_info, write_perft_divide, write_progress_info,
+        Command::{self, *},
+        EngineOption, SearchControl,
     },
-    perft::perft_divide,
+    json,
+    learning::Learning,
+    matchplay::play_match,
+    movegen::{self, MoveOrderer},
+    perft::perft_suite,
+    polyglot::Book,
     position::{Colour, Position},
-    search::{iterative_deepening, CurrMoveInfo, SearchCommand, SendInfo},
+    search::{
+        iterative_deepening, search_blocking, see, start_search, CurrMoveInfo, MvvLva, SearchCommand, SearchHandle,
+        SearchLimits, SearchResult, SendInfo,
+    },
+    logging::{self, received, Output},
+    selfplay::selfplay,
+    send,
+    tt::{score_from_tt, unpack_move, TranspositionTable, DEFAULT_HASH_MB},
 };
 
-pub const MAX_GAME_PLY: usize = 256;
 pub const CURRMOVE_WAIT_TIME: u32 = 3000;
+pub const MOVE_OVERHEAD: u32 = 30;
+
+// only losing the search worker ends the engine, anything else is logged and the next command read
+#[derive(Debug)]
+pub enum EngineError {
+    WorkerDisconnected,
+    File { path: String, error: io::Error },
+    IllegalMove(String),
+    // a command that uses files, with them not allowed
+    FilesNotAllowed,
+}
+
+impl EngineError {
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, EngineError::WorkerDisconnected)
+    }
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::WorkerDisconnected => write!(f, "search worker disconnected"),
+            EngineError::File { path, error } => write!(f, "reading {}: {}", path, error),
+            EngineError::IllegalMove(mv) => write!(f, "illegal move {}", mv),
+            EngineError::FilesNotAllowed => write!(f, "files and programs on this machine can't be used here"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<RecvError> for EngineError {
+    fn from(_: RecvError) -> Self {
+        EngineError::WorkerDisconnected
+    }
+}
 
 pub struct Engine {
     pub debug: bool,
-    pub position: Position,
-    pub search_handle: Option<JoinHandle<()>>,
+    pub game: Game,
+    // whether the worker is running a search, which always ends with SendInfo::Done
+    pub searching: bool,
+    pub job_tx: Sender<SearchJob>,
     pub max_time: u32,
     pub search_time: Instant,
     pub search_tx: Sender<SearchCommand>,
     pub search_rx: Receiver<SearchCommand>,
-    pub info_tx: Sender<SendInfo>,
     pub info_rx: Receiver<SendInfo>,
-    pub history: [u64; MAX_GAME_PLY],
     pub nodes: u32,
     pub currmove_buffer: Vec<CurrMoveInfo>,
+    // from the last finished search
+    pub best_move: Option<movegen::Move>,
+    pub own_book: bool,
+    pub book: Option<Book>,
+    pub json: bool,
+    // castling goes out king takes rook, as a Chess960 GUI expects. it always does from a
+    // Chess960 position, where the king's target can be a square it could step to
+    pub chess960: bool,
+    // where everything for the GUI goes
+    pub output: Output,
+    // commands and options that read or write files or start programs are refused without it,
+    // for clients that aren't on this machine
+    pub allow_files: bool,
+    // searches stop on depth, nodes or stop alone, never on the clock, and skip the book. with
+    // constant zobrist codes, one search thread, move ordering that breaks ties by generation
+    // order and an empty hash table of its own for each search, the same position and limits then
+    // always give the same nodes and pv
+    pub deterministic: bool,
+    // plays moves learned from deeper searches in earlier games and learns from its own
+    pub learning: bool,
+    pub learned: Learning,
+    // where the last go started and the deepest its search got, for learning from it
+    pub root: Position,
+    pub depth: u8,
+    pub score: i32,
+    // each search takes a share of the table it starts with. a Hash swaps in a new one rather than
+    // resizing this, so it never waits on a search, which keeps the old one until it's done
+    pub tt: Arc<TranspositionTable>,
 }
 
 impl Engine {
     pub fn init() -> Self {
+        Self::with_output(Output::stdout())
+    }
+
+    // protocol messages go to output instead of stdout, a socket or a buffer to check them in tests
+    pub fn with_output(output: Output) -> Self {
+        cpu::features();
+        attacks::init();
         let (search_tx, search_rx) = unbounded::<SearchCommand>();
         let (info_tx, info_rx) = unbounded::<SendInfo>();
+        let job_tx = spawn_search_worker(info_tx, search_rx.clone());
 
         Self {
             debug: false,
-            position: Position::from_fen(STARTING_FEN),
-            search_handle: None,
+            game: Game::default(),
+            searching: false,
+            job_tx,
             max_time: 0,
             search_time: Instant::now(),
             search_tx,
             search_rx,
-            info_tx,
             info_rx,
-            history: [0; MAX_GAME_PLY],
             nodes: 0,
             currmove_buffer: Vec::new(),
+            best_move: None,
+            own_book: false,
+            book: None,
+            json: false,
+            chess960: false,
+            output,
+            allow_files: true,
+            deterministic: false,
+            learning: false,
+            learned: Learning::default(),
+            root: Position::from_fen(STARTING_FEN),
+            depth: 0,
+            score: 0,
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
         }
     }
 
-    pub fn run(&mut self, cmd_args: String) -> Result<(), Box<dyn Error>> {
-        let (stdin_rx, stdin_tx) = spawn_reader();
-        stdin_tx.send(cmd_args)?;
-
-        'running: loop {
-            if let Ok(input) = stdin_rx.try_recv() {
-                if let Some(cmd) = parse_command(&input) {
-                    match cmd {
-                        Uci => id(),
-                        Debug(d) => self.debug = d,
-                        IsReady => println!("readyok"),
-                        _SetOption(_) => todo!("no options configured yet"),
-                        UCINewGame => self.position = Position::new(),
-                        Position(position, history) => {
-                            self.position = position;
-                            self.history = *history;
-                        }
-                        Go(control) => self.search(control),
-                        Stop => self.search_tx.send(SearchCommand::Stop)?,
-                        PonderHit => todo!("no pondering configured yet"),
-                        Quit => break 'running,
-                        Print => println!("{}", self.position),
-                        Perft(depth) => perft_divide(&mut self.position, depth),
-                        Evaluate => println!("{}", evaluate(&self.position)),
-                        Move(mv_str) => {
-                            if let Some(mv) = self.position.find_algebraic_move(&mv_str) {
-                                self.position.make_move(mv);
-                            }
-                        }
-                        Benchmark => self.benchmark(),
+    pub fn run(&mut self) -> Result<(), EngineError> {
+        self.run_with(spawn_reader(BufReader::new(io::stdin())), spawn_interrupt_handler())
+    }
+
+    // commands from input instead of stdin, for the server. ctrl-c is left to the caller
+    pub fn run_from(&mut self, input: impl BufRead + Send + 'static) -> Result<(), EngineError> {
+        self.run_with(spawn_reader(input), never())
+    }
+
+    fn run_with(&mut self, stdin_rx: Receiver<String>, interrupt_rx: Receiver<()>) -> Result<(), EngineError> {
+        logging::set_log_output(Some(self.output.clone()));
+        let info_rx = self.info_rx.clone();
+
+        // sleeps until there's input, search info or the search's time runs out
+        loop {
+            select! {
+                recv(stdin_rx) -> input => {
+                    let Ok(input) = input else {
+                        break;
+                    };
+                    received(input.trim_end());
+                    match parse_command(&input) {
+                        Some(Quit) => break,
+                        Some(cmd) => match self.execute(cmd) {
+                            Err(e) if e.is_fatal() => return Err(e),
+                            Err(e) => error!("{}", e),
+                            Ok(()) => (),
+                        },
+                        None => (),
+                    }
+                }
+                recv(info_rx) -> info => self.receive_info(info?),
+                recv(self.time_limit()) -> _ => self.stop_on_time(),
+                recv(interrupt_rx) -> _ => {
+                    // finished rather than dropped, so its bestmove still gets out
+                    if self.searching {
+                        _ = self.search_tx.send(SearchCommand::Stop);
+                        self.wait_for_search();
                     }
+                    break;
                 }
             }
+        }
+
+        self.stop_search();
+        Ok(())
+    }
 
-            self.receive_info();
+    // searches are left running, run and wait_for_search pick up their results
+    pub fn execute(&mut self, cmd: Command) -> Result<(), EngineError> {
+        if !self.allow_files && cmd.uses_files() {
+            return Err(EngineError::FilesNotAllowed);
+        }
 
-            if self.max_time != 0 && self.search_time.elapsed().as_millis() as u32 > self.max_time {
-                self.search_tx.send(SearchCommand::Stop)?;
-                self.max_time = 0;
+        match cmd {
+            Uci => id(&self.output),
+            Debug(d) => {
+                self.debug = d;
+                if d {
+                    send!(self.output, "info string cpu {}", cpu::features());
+                }
+            }
+            IsReady => send!(self.output, "readyok"),
+            SetOption(option) => self.set_option(option),
+            UCINewGame => self.new_game(),
+            Position(game) => self.game = *game,
+            Go(control) => self.search(control)?,
+            // a stop with nothing to stop would be left for the next search
+            Stop if self.searching => _ = self.search_tx.send(SearchCommand::Stop),
+            Stop => (),
+            // go ponder is searched like any other go, so there's nothing for a ponderhit to switch over
+            PonderHit => warn!("pondering isn't supported, ignoring ponderhit"),
+            // only run's loop has anything to quit
+            Quit => (),
+            // nothing needs registering or copy protecting
+            Register => {
+                send!(self.output, "registration checking");
+                send!(self.output, "registration ok");
+            }
+            CopyProtection => {
+                send!(self.output, "copyprotection checking");
+                send!(self.output, "copyprotection ok");
+            }
+            Print(style) => {
+                let position = self.game.position();
+                send!(self.output, "{}Eval: {} (side to move)", position.pretty(style), eval_terms(position).score(position.turn));
+            }
+            Perft(depth, hash_mb, position) => {
+                let mut position = position.map_or(*self.game.position(), |position| *position);
+                write_perft_divide(&self.output, &mut position, depth, hash_mb)
             }
+            PerftSuite(path, depth, hash_mb) => perft_suite(&self.output, &path, depth, hash_mb)?,
+            Evaluate => self.evaluate(),
+            See(mv_str) => self.see(&mv_str),
+            Probe => self.probe(),
+            Move(mv_str) => {
+                if self.game.push_algebraic(&mv_str).is_none() {
+                    return Err(EngineError::IllegalMove(mv_str));
+                }
+            }
+            Undo => _ = self.game.undo(),
+            Flip => self.flip(),
+            Moves => self.list_moves(),
+            Diff(other) => self.diff(other),
+            Benchmark(options) => _ = benchmark(&self.output, &options)?,
+            Solve(path, movetime) => self.solve(&path, movetime)?,
+            SelfPlay(options) => selfplay(&self.output, &options),
+            Match(options) => play_match(&self.output, &options),
         }
 
         Ok(())
     }
 
-    pub fn search(&mut self, control: SearchControl) {
-        if self.search_handle.is_some() {
-            return;
+    fn set_option(&mut self, option: EngineOption) {
+        match option {
+            EngineOption::OwnBook(own_book) => self.own_book = own_book,
+            EngineOption::JsonOutput(json) => self.json = json,
+            EngineOption::Chess960(chess960) => self.chess960 = chess960,
+            EngineOption::Deterministic(deterministic) => self.deterministic = deterministic,
+            EngineOption::Learning(learning) => self.learning = learning,
+            EngineOption::LearningFile(path) => {
+                // what's been learned so far belongs to the old file
+                self.save_learning();
+                self.learned = if path.is_empty() {
+                    Learning::default()
+                } else {
+                    Learning::open(&path).unwrap_or_else(|e| {
+                        error!("reading learning file {}: {}", path, e);
+                        Learning::default()
+                    })
+                };
+            }
+            EngineOption::Hash(mb) => self.tt = Arc::new(TranspositionTable::new(mb)),
+            EngineOption::ClearHash => self.clear_hash(),
+            EngineOption::DebugLogFile(path) => {
+                if let Err(e) = logging::set_log_file(&path) {
+                    error!("opening log file {}: {}", path, e);
+                }
+            }
+            EngineOption::LogLevel(level) => log::set_max_level(level),
+            EngineOption::LogTarget(target) => {
+                if let Err(e) = logging::set_log_target(&target) {
+                    error!("opening log target {}: {}", target, e);
+                }
+            }
+            EngineOption::BookFile(path) if path.is_empty() => self.book = None,
+            EngineOption::BookFile(path) => {
+                self.book = match Book::open(&path) {
+                    Ok(book) => Some(book),
+                    Err(e) => {
+                        error!("reading book {}: {}", path, e);
+                        None
+                    }
+                }
+            }
         }
+    }
 
-        let position = self.position;
-        let tx = self.info_tx.clone();
-        let rx = self.search_rx.clone();
+    // nothing from the last game should affect the next, except what was learned from it
+    fn new_game(&mut self) {
+        self.save_learning();
+        self.game = Game::default();
+        self.best_move = None;
+        self.nodes = 0;
+        self.currmove_buffer.clear();
+        self.clear_hash();
+    }
 
-        self.search_time = Instant::now();
-        let history = self.history;
-        let handle = thread::spawn(move || {
-            iterative_deepening(position, control.depth, control.nodes, history, tx, rx)
-        });
+    fn save_learning(&self) {
+        if let Err(e) = self.learned.save() {
+            error!("saving learning file: {}", e);
+        }
+    }
+
+    fn clear_hash(&mut self) {
+        self.tt.clear();
+    }
+
+    fn diff(&self, other: Option<Box<Position>>) {
+        let position = self.game.position();
+        let other = other.map_or_else(
+            || {
+                let mut fresh = *position;
+                fresh.gen_zobrist_hash();
+                fresh
+            },
+            |other| *other,
+        );
 
-        self.search_handle = Some(handle);
+        let diffs = position.diff(&other);
+        if diffs.is_empty() {
+            send!(self.output, "info string no differences");
+        }
+        for diff in diffs {
+            send!(self.output, "info string {}", diff);
+        }
+    }
+
+    // a go while searching stops the running search, which still reports its bestmove, so every
+    // go gets exactly one
+    pub fn search(&mut self, control: SearchControl) -> Result<(), EngineError> {
+        if self.searching {
+            _ = self.search_tx.send(SearchCommand::Stop);
+            self.wait_for_search();
+        }
+
+        self.root = *self.game.position();
+        // book moves are played straight away without searching
+        if self.own_book && !self.deterministic {
+            let book_move = self.book.as_ref().and_then(|book| book.pick(self.game.position(), &mut rand::thread_rng()));
+            if book_move.is_some() {
+                self.best_move = book_move;
+                self.write_bestmove(book_move);
+                return Ok(());
+            }
+        }
+
+        // a stop sent after the last search finished, on time or by the GUI. it's cleared here
+        // rather than by the worker, which could clear one sent for this search before it starts
+        self.search_rx.try_iter().for_each(drop);
+        // a new game clears the table, a new search only ages what's in it
+        if self.deterministic {
+            self.tt = self.fresh_table();
+        }
+        self.tt.new_search();
+        self.search_time = Instant::now();
+        self.depth = 0;
+        self.score = 0;
+        self.job_tx
+            .send(SearchJob {
+                position: *self.game.position(),
+                depth: control.depth,
+                nodes: control.nodes,
+                history: self.game.hash_history(),
+                tt: self.tt.clone(),
+                debug: self.debug,
+            })
+            .map_err(|_| EngineError::WorkerDisconnected)?;
+        self.searching = true;
 
         self.set_search_limit(control);
+        Ok(())
+    }
+
+    // searches on the calling thread instead of the worker, for library users who just want a
+    // result. it's independent of any search the worker is running, and of the book, though it
+    // shares the worker's table unless it's deterministic
+    pub fn search_blocking(&self, game: &Game, mut limits: SearchLimits) -> SearchResult {
+        let tt = if self.deterministic {
+            limits.movetime = 0;
+            self.fresh_table()
+        } else {
+            self.tt.clone()
+        };
+        search_blocking(game, limits, &tt, self.debug)
+    }
+
+    // searches the current game on a thread of its own, handing back a handle to stop it and
+    // collect its result. like search_blocking it leaves the worker and the book alone
+    pub fn start_search(&self, mut limits: SearchLimits) -> SearchHandle {
+        let tt = if self.deterministic {
+            limits.movetime = 0;
+            self.fresh_table()
+        } else {
+            self.tt.clone()
+        };
+        start_search(&self.game, limits, tt, self.debug)
+    }
+
+    // an empty table the size of the engine's that nothing else is searching with, so a
+    // deterministic search starts from the same nothing whatever else is running
+    fn fresh_table(&self) -> Arc<TranspositionTable> {
+        Arc::new(TranspositionTable::new(self.tt.mb()))
+    }
+
+    // stops any running search without reporting its result, leaving the channels empty
+    pub fn stop_search(&mut self) {
+        if self.searching {
+            _ = self.search_tx.send(SearchCommand::Stop);
+            while let Ok(info) = self.info_rx.recv() {
+                if let SendInfo::Done(_) = info {
+                    break;
+                }
+            }
+            self.searching = false;
+        }
+        self.max_time = 0;
+        self.info_rx.try_iter().for_each(drop);
+        self.search_rx.try_iter().for_each(drop);
+    }
+
+    // blocks until the running search finishes, stopping it when its time is up
+    pub fn wait_for_search(&mut self) {
+        let info_rx = self.info_rx.clone();
+        while self.searching {
+            select! {
+                recv(info_rx) -> info => match info {
+                    Ok(info) => self.receive_info(info),
+                    // the worker died, there's no search to wait for
+                    Err(e) => {
+                        error!("search worker: {}", e);
+                        self.searching = false;
+                    }
+                },
+                recv(self.time_limit()) -> _ => self.stop_on_time(),
+            }
+        }
+    }
+
+    // fires when the running search is out of time, never when it has no limit
+    fn time_limit(&self) -> Receiver<Instant> {
+        if self.max_time == 0 {
+            return never();
+        }
+        let elapsed = self.search_time.elapsed();
+        after(Duration::from_millis(self.max_time as u64).saturating_sub(elapsed))
+    }
+
+    fn stop_on_time(&mut self) {
+        _ = self.search_tx.send(SearchCommand::Stop);
+        self.max_time = 0;
     }
 
     pub fn set_search_limit(&mut self, control: SearchControl) {
+        // no limit unless this search sets one, whatever the last search had
+        self.max_time = 0;
         if control.infinite {
             return;
         }
+        if self.deterministic {
+            if control.movetime != 0 || control.wtime != 0 || control.btime != 0 {
+                warn!("deterministic search ignores time limits");
+            }
+            return;
+        }
 
-        self.max_time = match self.position.turn {
+        self.max_time = match self.game.position().turn {
             Colour::White => calculate_allowed_time(control.wtime, control.winc, control.movestogo),
             Colour::Black => calculate_allowed_time(control.btime, control.binc, control.movestogo),
         };
@@ -137,54 +507,523 @@ impl Engine {
         }
     }
 
-    pub fn receive_info(&mut self) {
-        for info in self.info_rx.try_iter() {
-            match info {
-                SendInfo::Full(info) => {
-                    self.nodes += info.nodes;
-                    write_full_info(info);
-                    self.currmove_buffer.drain(..);
-                }
-                SendInfo::CurrMove(info) => {
-                    if self.search_time.elapsed().as_millis() > CURRMOVE_WAIT_TIME.into() {
-                        for info in self.currmove_buffer.drain(..) {
-                            write_currmove_info(info);
-                        }
-                        write_currmove_info(info)
-                    } else {
-                        self.currmove_buffer.push(info);
-                    }
+    pub fn receive_info(&mut self, info: SendInfo) {
+        match info {
+            SendInfo::Full(info) => {
+                self.nodes += info.nodes;
+                self.depth = info.depth;
+                self.score = info.score;
+                if self.json {
+                    send!(self.output, "{}", json::full_info(&info, self.chess960()));
+                } else {
+                    write_full_info(&self.output, *info, self.chess960());
                 }
-                SendInfo::Done(mv) => {
-                    if let Some(mv) = mv {
-                        println!("bestmove {}", mv);
-                    } else {
-                        println!("bestmove None");
+                self.currmove_buffer.drain(..);
+            }
+            SendInfo::CurrMove(info) => {
+                if self.search_time.elapsed().as_millis() > CURRMOVE_WAIT_TIME.into() {
+                    for info in std::mem::take(&mut self.currmove_buffer) {
+                        self.write_currmove(info);
                     }
-                    let handle = self.search_handle.take().unwrap();
-                    handle.join().unwrap();
+                    self.write_currmove(info)
+                } else {
+                    self.currmove_buffer.push(info);
                 }
             }
+            SendInfo::Progress(info) if self.json => send!(self.output, "{}", json::progress(&info)),
+            SendInfo::Progress(info) => write_progress_info(&self.output, info),
+            SendInfo::Stats(info) if self.json => send!(self.output, "{}", json::stats(&info)),
+            SendInfo::Stats(info) => send!(self.output, "info string {}", info),
+            SendInfo::Done(mv) => {
+                let mv = self.learn_from_search(mv);
+                self.best_move = mv;
+                self.write_bestmove(mv);
+                self.searching = false;
+            }
+        }
+    }
+
+    // a move learned from a deeper search of the root is played instead of the search's own,
+    // otherwise the search's is learned. deterministic searches neither learn nor use learning
+    fn learn_from_search(&mut self, mv: Option<movegen::Move>) -> Option<movegen::Move> {
+        if !self.learning || self.deterministic {
+            return mv;
+        }
+
+        if let Some(learned) = self.learned.better_move(&self.root, self.depth) {
+            if self.debug {
+                send!(self.output, "info string playing learned move {}", learned);
+            }
+            return Some(learned);
+        }
+        if let Some(mv) = mv.filter(|_| self.depth > 0) {
+            self.learned.learn(&self.root, mv, self.score, self.depth);
+        }
+        mv
+    }
+
+    fn write_currmove(&self, info: CurrMoveInfo) {
+        if self.json {
+            send!(self.output, "{}", json::currmove(&info, self.chess960()));
+        } else {
+            write_currmove_info(&self.output, info, self.chess960());
         }
     }
+
+    // moves are written for the position the last go started from
+    fn chess960(&self) -> bool {
+        self.chess960 || self.root.is_chess960()
+    }
+
+    fn write_bestmove(&self, mv: Option<movegen::Move>) {
+        match mv {
+            _ if self.json => send!(self.output, "{}", json::bestmove(mv, self.chess960())),
+            Some(mv) => send!(self.output, "bestmove {}", mv.to_uci(self.chess960())),
+            None => send!(self.output, "bestmove None"),
+        }
+    }
+
+    // scored as at the root of a search, without a PV to put first
+    fn list_moves(&self) {
+        let position = self.game.position();
+        let mut moves = position.gen_moves();
+        moves.score(0, &MvvLva);
+
+        let mut count = 0;
+        for mv in moves.filter(|&mv| position.is_legal(mv)) {
+            send!(self.output, "{} {} {}", mv, position.san(mv), MvvLva.score(&mv, 0));
+            count += 1;
+        }
+        send!(self.output, "{} moves", count);
+    }
+
+    // the game restarts from the flipped position, so there's nothing to undo past it
+    fn flip(&mut self) {
+        let mut position = *self.game.position();
+        if !position.checkers().is_empty() {
+            warn!("can't pass the turn in check");
+            return;
+        }
+        position.make_null_move();
+        self.game = Game::new(position);
+    }
+
+    // the full swap off is only worth showing when debugging
+    fn see(&self, mv_str: &str) {
+        let position = self.game.position();
+        let Some(mv) = position.find_algebraic_move(mv_str) else {
+            warn!("no move {} in this position", mv_str);
+            return;
+        };
+
+        let (gain, sequence) = see(position, mv);
+        send!(self.output, "{}", gain);
+        if self.debug {
+            let sequence: Vec<String> = sequence.iter().map(|(piece, sq)| format!("{}{}", piece, sq)).collect();
+            send!(self.output, "info string swap off {}", sequence.join(" "));
+        }
+    }
+
+    // scores are from the side to move's point of view, as the search stored them
+    fn probe(&self) {
+        let position = self.game.position();
+        let Some((entry, age)) = self.tt.probe_with_age(position.hash) else {
+            send!(self.output, "no entry");
+            return;
+        };
+
+        let mv = unpack_move(position, entry.mv).map_or("none".to_string(), |mv| mv.to_string());
+        send!(
+            self.output,
+            "depth {} score {} bound {} move {} age {}",
+            entry.depth,
+            score_from_tt(entry.score, 0),
+            entry.bound,
+            mv,
+            age
+        );
+    }
+
+    fn evaluate(&self) {
+        let position = self.game.position();
+        let terms = eval_terms(position);
+        let score = terms.score(position.turn);
+        if self.json {
+            send!(self.output, "{}", json::eval(&terms, score));
+        } else {
+            send!(self.output, "{}", score);
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop_search();
+        self.save_learning();
+    }
 }
 
-fn calculate_allowed_time(time: u32, _inc: u32, mut movestogo: u8) -> u32 {
-    if movestogo == 0 {
-        movestogo = 40;
+// 0 without a clock, which means no limit, otherwise at least 1ms so the limit still applies.
+// the search always finishes depth 1 so there's a move to play however short the time is
+pub fn calculate_allowed_time(time: u32, inc: u32, movestogo: u8) -> u32 {
+    if time == 0 && inc == 0 {
+        return 0;
     }
 
-    time / (movestogo + 2) as u32
+    // kept back for lag between us and the GUI
+    let available = time.saturating_sub(MOVE_OVERHEAD);
+    let allowed = match movestogo {
+        0 => available / 40 + inc * 3 / 4,
+        // the clock is topped up after this move, so most of it can go
+        1 => available * 9 / 10,
+        n => available / n as u32 + inc * 3 / 4,
+    };
+
+    // never more than half of what's left, except on the move before the time control
+    let cap = if movestogo == 1 { available * 9 / 10 } else { available / 2 };
+    allowed.min(cap).max(1)
 }
 
-fn spawn_reader() -> (Receiver<String>, Sender<String>) {
+// the end of piped input reads as a quit, so scripts run to the end and exit
+fn spawn_reader(mut input: impl BufRead + Send + 'static) -> Receiver<String> {
     let (tx, rx) = unbounded::<String>();
-    let tx_clone = tx.clone();
     thread::spawn(move || loop {
         let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-        tx_clone.send(buffer).unwrap();
+        if let Ok(0) | Err(_) = input.read_line(&mut buffer) {
+            _ = tx.send("quit".to_string());
+            break;
+        }
+        // the engine has quit and dropped the receiver
+        if tx.send(buffer).is_err() {
+            break;
+        }
+    });
+
+    rx
+}
+
+pub struct SearchJob {
+    position: Position,
+    depth: u8,
+    nodes: u32,
+    history: [u64; MAX_GAME_PLY],
+    tt: Arc<TranspositionTable>,
+    debug: bool,
+}
+
+// searches run one after another on a single thread that lives as long as the engine, ending when
+// the engine drops its job sender. each one starts with what the last left in the table
+fn spawn_search_worker(info_tx: Sender<SendInfo>, stop_rx: Receiver<SearchCommand>) -> Sender<SearchJob> {
+    let (job_tx, job_rx) = unbounded::<SearchJob>();
+    thread::spawn(move || {
+        for job in job_rx {
+            let tx = info_tx.clone();
+            let result = iterative_deepening(
+                job.position,
+                job.depth,
+                job.nodes,
+                job.history,
+                &job.tt,
+                tx,
+                stop_rx.clone(),
+                job.debug,
+            );
+            // the engine is gone
+            if result.is_err() {
+                break;
+            }
+        }
     });
 
-    (rx, tx)
+    job_tx
+}
+
+// ctrl-c ends the engine like quit does, instead of killing it mid-search
+fn spawn_interrupt_handler() -> Receiver<()> {
+    let (tx, rx) = bounded(1);
+    if let Err(e) = ctrlc::set_handler(move || _ = tx.try_send(())) {
+        warn!("installing ctrl-c handler: {}", e);
+        return never();
+    }
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{CHECKMATE, MAX_DEPTH};
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    // keeps what the engine sends for the test to read back
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+        }
+    }
+
+    #[test]
+    fn allowed_time() {
+        assert_eq!(calculate_allowed_time(0, 0, 0), 0);
+        assert_eq!(calculate_allowed_time(60_000, 0, 0), (60_000 - MOVE_OVERHEAD) / 40);
+        assert_eq!(calculate_allowed_time(60_000, 1000, 0), (60_000 - MOVE_OVERHEAD) / 40 + 750);
+        assert_eq!(calculate_allowed_time(10_000, 0, 1), (10_000 - MOVE_OVERHEAD) * 9 / 10);
+
+        // increments can't push past half the clock, and a near empty clock still searches
+        assert_eq!(calculate_allowed_time(1000, 5000, 0), (1000 - MOVE_OVERHEAD) / 2);
+        assert_eq!(calculate_allowed_time(50, 0, 0), 1);
+        assert_eq!(calculate_allowed_time(10, 0, 0), 1);
+    }
+
+    #[test]
+    fn uci_output() {
+        let captured = Captured::default();
+        let mut engine = Engine::with_output(Output::new(captured.clone()));
+
+        engine.execute(parse_command("uci").unwrap()).unwrap();
+        let output = captured.take();
+        assert!(output.starts_with("id name Blunderbuss\nid author Felix Berman\noption name OwnBook type check default false\n"));
+        assert!(output.ends_with("\nuciok\n"));
+
+        engine.execute(parse_command("isready").unwrap()).unwrap();
+        assert_eq!(captured.take(), "readyok\n");
+
+        engine.execute(parse_command("position fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap()).unwrap();
+        engine.execute(parse_command("go depth 2").unwrap()).unwrap();
+        engine.wait_for_search();
+        let output = captured.take();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("info depth 1 "));
+        assert!(lines.iter().any(|line| line.starts_with("info depth 2 seldepth 2 score mate 1 ")));
+        assert_eq!(lines.last(), Some(&"bestmove d1d8"));
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let captured = Captured::default();
+        let mut engine = Engine::with_output(Output::new(captured.clone()));
+
+        // a king step to the square queenside castling would also put it on
+        engine.execute(parse_command("position fen 2r3kr/8/8/8/8/8/2P5/RK5R w KQkq - 0 1 moves b1c1").unwrap()).unwrap();
+        assert_eq!(engine.game.position().write_fen(), "2r3kr/8/8/8/8/8/2P5/R1K4R b kq - 1 1");
+
+        // castling mates, written king takes rook
+        engine.execute(parse_command("position fen 2rkr3/2p1p3/8/8/8/8/8/RK6 w A - 0 1").unwrap()).unwrap();
+        engine.execute(parse_command("go depth 2").unwrap()).unwrap();
+        engine.wait_for_search();
+        let output = captured.take();
+        assert!(output.contains("score mate 1 nodes") && output.contains(" pv b1a1"));
+        assert!(output.ends_with("bestmove b1a1\n"));
+
+        // a Chess960 GUI castles king takes rook from the standard setup too
+        engine.execute(parse_command("setoption name UCI_Chess960 value true").unwrap()).unwrap();
+        assert!(engine.chess960);
+        engine.execute(parse_command("position fen 3k4/8/8/8/8/8/8/4K2R w K - 0 1 moves e1h1").unwrap()).unwrap();
+        assert_eq!(engine.game.position().write_fen(), "3k4/8/8/8/8/8/8/5RK1 b - - 1 1");
+    }
+
+    #[test]
+    fn blocking_search() {
+        let engine = Engine::init();
+        let game = Game::new(Position::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"));
+        let result = engine.search_blocking(&game, SearchLimits { depth: 3, nodes: u32::MAX, movetime: 0 });
+        assert_eq!(result.best_move.unwrap().to_string(), "d1d8");
+        assert_eq!(result.score, CHECKMATE - 1);
+        assert_eq!(result.depth, 2);
+        assert_eq!(result.pv, [result.best_move.unwrap()]);
+        assert_eq!(result.ponder_move, None);
+
+        let result = engine.search_blocking(&Game::default(), SearchLimits { depth: 3, nodes: u32::MAX, movetime: 0 });
+        assert_eq!(result.pv.len(), 3);
+        assert_eq!(result.ponder_move, Some(result.pv[1]));
+    }
+
+    #[test]
+    fn search_handle() {
+        let mut engine = Engine::init();
+        let handle = engine.start_search(SearchLimits::new());
+        assert!(handle.is_running());
+        // doesn't wait for the handle's search, which has a table of its own
+        engine.execute(parse_command("setoption name Hash value 1").unwrap()).unwrap();
+        assert!(handle.is_running());
+        thread::sleep(Duration::from_millis(50));
+        handle.stop();
+        let result = handle.join();
+        assert!(result.best_move.is_some());
+        assert!(result.depth > 1 && result.depth < MAX_DEPTH as u8);
+    }
+
+    #[test]
+    fn bad_input_is_recoverable() {
+        let captured = Captured::default();
+        let mut engine = Engine::with_output(Output::new(captured.clone()));
+
+        let err = engine.execute(parse_command("move e2e5").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "illegal move e2e5");
+        assert!(!err.is_fatal());
+        let err = engine.execute(parse_command("bench file missing.epd").unwrap()).unwrap_err();
+        assert!(matches!(err, EngineError::File { .. }) && !err.is_fatal());
+        engine.execute(parse_command("ponderhit").unwrap()).unwrap();
+
+        // the loop carries on past them
+        engine.run_from(io::Cursor::new("move e2e5\nponderhit\nisready\n")).unwrap();
+        assert!(captured.take().ends_with("readyok\n"));
+
+        // a stop straight after go still stops the search
+        engine.execute(parse_command("go").unwrap()).unwrap();
+        engine.execute(parse_command("stop").unwrap()).unwrap();
+        engine.wait_for_search();
+        assert!(captured.take().contains("\nbestmove "));
+    }
+
+    #[test]
+    fn files_not_allowed() {
+        let mut engine = Engine::with_output(Output::new(Captured::default()));
+        engine.allow_files = false;
+        for cmd in [
+            "setoption name Learning File value /tmp/clobbered",
+            "setoption name Log Target value /tmp/clobbered",
+            "bench file /etc/passwd",
+            "solve /etc/passwd",
+            "selfplay games 1",
+            "match engine /bin/sh",
+        ] {
+            let result = engine.execute(parse_command(cmd).unwrap());
+            assert!(matches!(result, Err(EngineError::FilesNotAllowed)), "{}", cmd);
+        }
+        assert!(!parse_command("setoption name Log Target value stderr").unwrap().uses_files());
+        engine.execute(parse_command("setoption name Hash value 2").unwrap()).unwrap();
+    }
+
+    #[test]
+    fn learning_file() {
+        let path = std::env::temp_dir().join(format!("blunderbuss-learn-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let captured = Captured::default();
+        let mut engine = Engine::with_output(Output::new(captured.clone()));
+        for cmd in ["setoption name Learning value true", &format!("setoption name Learning File value {}", path), "go depth 2"] {
+            engine.execute(parse_command(cmd).unwrap()).unwrap();
+        }
+        engine.wait_for_search();
+        let learned = engine.learned.get(&Position::from_fen(STARTING_FEN)).unwrap().clone();
+        assert_eq!(learned.depth, 2);
+        assert_eq!(Some(learned.mv.as_str()), engine.best_move.map(|mv| mv.to_string()).as_deref());
+        engine.execute(parse_command("ucinewgame").unwrap()).unwrap();
+        assert!(std::fs::read_to_string(path).unwrap().starts_with(&format!("463b96181691fc9c {} ", learned.mv)));
+
+        // a deeper search from an earlier game beats a shallower one now
+        std::fs::write(path, "463b96181691fc9c b1c3 10 20\n").unwrap();
+        let mut engine = Engine::with_output(Output::new(captured.clone()));
+        for cmd in ["setoption name Learning value true", &format!("setoption name Learning File value {}", path), "go depth 2"] {
+            engine.execute(parse_command(cmd).unwrap()).unwrap();
+        }
+        engine.wait_for_search();
+        drop(engine);
+        std::fs::remove_file(path).unwrap();
+        assert!(captured.take().ends_with("bestmove b1c3\n"));
+    }
+
+    #[test]
+    fn probe_command() {
+        let captured = Captured::default();
+        let mut engine = Engine::with_output(Output::new(captured.clone()));
+        let mate = "position fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+        engine.execute(parse_command(mate).unwrap()).unwrap();
+        engine.execute(parse_command("probe").unwrap()).unwrap();
+        assert_eq!(captured.take(), "no entry\n");
+
+        engine.execute(parse_command("go depth 2").unwrap()).unwrap();
+        engine.wait_for_search();
+        captured.take();
+        engine.execute(parse_command("probe").unwrap()).unwrap();
+        assert_eq!(captured.take(), format!("depth 2 score {} bound exact move d1d8 age 0\n", CHECKMATE - 1));
+
+        // a search elsewhere ages it
+        engine.execute(parse_command("position startpos").unwrap()).unwrap();
+        engine.execute(parse_command("go depth 1").unwrap()).unwrap();
+        engine.wait_for_search();
+        engine.execute(parse_command(mate).unwrap()).unwrap();
+        engine.execute(parse_command("probe").unwrap()).unwrap();
+        assert!(captured.take().ends_with(" move d1d8 age 1\n"));
+    }
+
+    #[test]
+    fn hash_table() {
+        let mut engine = Engine::init();
+        engine.execute(parse_command("setoption name Hash value 1").unwrap()).unwrap();
+        assert_eq!(engine.tt.capacity(), 1024 * 1024 / 16);
+
+        // the table outlives the search, until it's cleared
+        engine.execute(parse_command("go depth 3").unwrap()).unwrap();
+        engine.wait_for_search();
+        let root = engine.game.position().hash;
+        assert!(engine.tt.probe(root).is_some());
+        assert_eq!(engine.tt.generation(), 1);
+        engine.execute(parse_command("setoption name Clear Hash").unwrap()).unwrap();
+        assert!(engine.tt.probe(root).is_none());
+
+        // resizing swaps in a new table straight away, the running search keeps the old one
+        engine.execute(parse_command("go infinite").unwrap()).unwrap();
+        engine.execute(parse_command("setoption name Hash value 2").unwrap()).unwrap();
+        assert_eq!(engine.tt.capacity(), 2 * 1024 * 1024 / 16);
+        engine.execute(parse_command("stop").unwrap()).unwrap();
+        engine.wait_for_search();
+        assert_eq!(engine.tt.capacity(), 2 * 1024 * 1024 / 16);
+
+        // a deterministic search starts from an empty table, so the same one searches the same nodes
+        engine.deterministic = true;
+        let mut nodes = Vec::new();
+        for _ in 0..2 {
+            let before = engine.nodes;
+            engine.execute(parse_command("go depth 4").unwrap()).unwrap();
+            engine.wait_for_search();
+            nodes.push(engine.nodes - before);
+        }
+        assert_eq!(nodes[0], nodes[1]);
+        assert_eq!(engine.tt.capacity(), 2 * 1024 * 1024 / 16);
+    }
+
+    #[test]
+    fn deterministic_library_searches() {
+        let mut engine = Engine::init();
+        engine.execute(parse_command("go depth 3").unwrap()).unwrap();
+        engine.wait_for_search();
+        let root = engine.game.position().hash;
+
+        // each has a table of its own, leaving the shared one and any other search's alone
+        engine.deterministic = true;
+        let limits = SearchLimits { depth: 5, ..SearchLimits::new() };
+        let handle = engine.start_search(limits);
+        let blocking = engine.search_blocking(&engine.game, limits);
+        assert!(engine.tt.probe(root).is_some());
+        assert_eq!(handle.join().nodes, blocking.nodes);
+    }
+
+    #[test]
+    fn deterministic_ignores_clock() {
+        let mut engine = Engine::init();
+        let control = || SearchControl { wtime: 60_000, movetime: 1000, ..SearchControl::new() };
+        engine.set_search_limit(control());
+        assert_eq!(engine.max_time, 1000);
+
+        engine.deterministic = true;
+        engine.set_search_limit(control());
+        assert_eq!(engine.max_time, 0);
+    }
 }
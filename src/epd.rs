@@ -0,0 +1,218 @@
+// EPD records, the four FEN board fields followed by semicolon terminated operations such as
+// `bm Qb6; id "arasan2023.36";`. Suites that append the FEN clocks before the operations, like
+// perftsuite.epd, are accepted too.
+
+use core::fmt::Display;
+
+use crate::prelude::*;
+use crate::{
+    fen::FenError,
+    position::Position,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum EpdError {
+    Fen(FenError),
+    MissingFields,
+    UnterminatedString,
+    InvalidOperand(String),
+}
+
+impl Display for EpdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EpdError::Fen(e) => write!(f, "{}", e),
+            EpdError::MissingFields => write!(f, "Expected at least 4 fields"),
+            EpdError::UnterminatedString => write!(f, "Unterminated string operand"),
+            EpdError::InvalidOperand(opcode) => write!(f, "Invalid operand for {}", opcode),
+        }
+    }
+}
+
+impl core::error::Error for EpdError {}
+
+impl From<FenError> for EpdError {
+    fn from(e: FenError) -> Self {
+        EpdError::Fen(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub opcode: String,
+    pub operands: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpdRecord {
+    pub position: Position,
+    // moves are kept in SAN as written
+    pub best_moves: Vec<String>,
+    pub avoid_moves: Vec<String>,
+    pub id: Option<String>,
+    // mate in this many moves
+    pub direct_mate: Option<u32>,
+    // centipawns from the side to move's point of view
+    pub centipawn_eval: Option<i32>,
+    // every operation in the order written, including the ones above
+    pub operations: Vec<Operation>,
+}
+
+impl EpdRecord {
+    pub fn parse(line: &str) -> Result<EpdRecord, EpdError> {
+        let mut rest = line;
+        let mut fields = Vec::new();
+        for _ in 0..4 {
+            fields.push(next_token(&mut rest).ok_or(EpdError::MissingFields)?);
+        }
+
+        // clocks written straight after the board fields, as in a full FEN
+        let mut clocks = ["0", "1"];
+        for clock in clocks.iter_mut() {
+            let mut after = rest;
+            match next_token(&mut after) {
+                Some(token) if token.bytes().all(|b| b.is_ascii_digit()) => {
+                    *clock = token;
+                    rest = after;
+                }
+                _ => break,
+            }
+        }
+
+        let operations = parse_operations(rest)?;
+
+        let mut record = EpdRecord {
+            position: Position::new(),
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            id: None,
+            direct_mate: None,
+            centipawn_eval: None,
+            operations: Vec::new(),
+        };
+
+        for op in &operations {
+            let invalid = || EpdError::InvalidOperand(op.opcode.clone());
+            let first = op.operands.first();
+            match op.opcode.as_str() {
+                "bm" => record.best_moves = op.operands.clone(),
+                "am" => record.avoid_moves = op.operands.clone(),
+                "id" => record.id = first.cloned(),
+                "dm" => record.direct_mate = Some(first.and_then(|x| x.parse().ok()).ok_or_else(invalid)?),
+                "ce" => record.centipawn_eval = Some(first.and_then(|x| x.parse().ok()).ok_or_else(invalid)?),
+                "hmvc" => clocks[0] = first.ok_or_else(invalid)?.as_str(),
+                "fmvn" => clocks[1] = first.ok_or_else(invalid)?.as_str(),
+                _ => (),
+            }
+        }
+
+        let fen = format!("{} {} {} {} {} {}", fields[0], fields[1], fields[2], fields[3], clocks[0], clocks[1]);
+        record.position = Position::try_from_fen(&fen)?;
+        record.operations = operations;
+
+        Ok(record)
+    }
+
+    pub fn operation(&self, opcode: &str) -> Option<&Operation> {
+        self.operations.iter().find(|op| op.opcode == opcode)
+    }
+}
+
+fn next_token<'a>(text: &mut &'a str) -> Option<&'a str> {
+    let trimmed = text.trim_start();
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    *text = &trimmed[end..];
+    (end > 0).then(|| &trimmed[..end])
+}
+
+// operations are an opcode and whitespace separated operands up to a ';', where string operands
+// are quoted and may contain spaces and semicolons. a trailing operation without ';' is accepted
+fn parse_operations(text: &str) -> Result<Vec<Operation>, EpdError> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !tokens.is_empty() {
+                    operations.push(make_operation(&mut tokens));
+                }
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+                tokens.push(string);
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut token = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+
+    if !tokens.is_empty() {
+        operations.push(make_operation(&mut tokens));
+    }
+
+    Ok(operations)
+}
+
+fn make_operation(tokens: &mut Vec<String>) -> Operation {
+    let mut tokens = tokens.drain(..);
+    Operation {
+        opcode: tokens.next().unwrap(),
+        operands: tokens.collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epd_opcodes() {
+        let line = r#"8/6pk/p3p3/2Q1P3/1p2P3/5P1P/5KP1/1q6 w - - bm Qb6 Qc7; am Qxb4; id "arasan; 36"; dm 5; ce -120; c0 "Arasan-Granddroid, FICS 2020";"#;
+        let record = EpdRecord::parse(line).unwrap();
+        assert_eq!(record.position.write_fen(), "8/6pk/p3p3/2Q1P3/1p2P3/5P1P/5KP1/1q6 w - - 0 1");
+        assert_eq!(record.best_moves, ["Qb6", "Qc7"]);
+        assert_eq!(record.avoid_moves, ["Qxb4"]);
+        assert_eq!(record.id.as_deref(), Some("arasan; 36"));
+        assert_eq!(record.direct_mate, Some(5));
+        assert_eq!(record.centipawn_eval, Some(-120));
+        assert_eq!(record.operation("c0").unwrap().operands, ["Arasan-Granddroid, FICS 2020"]);
+        assert_eq!(record.operations.len(), 6);
+    }
+
+    #[test]
+    fn epd_clocks() {
+        let record = EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 b - - 12 40 ;D1 5 ;D2 25").unwrap();
+        assert_eq!(record.position.write_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 12 40");
+        assert_eq!(record.operation("D2").unwrap().operands, ["25"]);
+
+        let record = EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 w - - hmvc 3; fmvn 7;").unwrap();
+        assert_eq!(record.position.write_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 3 7");
+    }
+
+    #[test]
+    fn epd_errors() {
+        assert_eq!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 w -"), Err(EpdError::MissingFields));
+        assert_eq!(EpdRecord::parse(r#"4k3/8/8/8/8/8/8/4K3 w - - id "open"#), Err(EpdError::UnterminatedString));
+        assert_eq!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 w - - dm x;"), Err(EpdError::InvalidOperand("dm".to_string())));
+        assert_eq!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 x - - bm Kd1;"), Err(EpdError::Fen(FenError::Turn("x".to_string()))));
+    }
+}
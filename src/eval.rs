@@ -1,7 +1,28 @@
-use crate::position::{Piece, Position};
+use crate::{bitboard::Square, position::{Colour, Piece, Position}};
 use piece_sq_tables::*;
 
+// the piece square totals for each side, white first, and the middlegame phase out of 24
+#[derive(Debug)]
+pub struct EvalTerms {
+    pub mg: [i32; 2],
+    pub eg: [i32; 2],
+    pub phase: i32,
+}
+
+impl EvalTerms {
+    // tapered between middlegame and endgame, from the point of view of turn
+    pub fn score(&self, turn: Colour) -> i32 {
+        let mg_score = self.mg[turn] - self.mg[!turn];
+        let eg_score = self.eg[turn] - self.eg[!turn];
+        (mg_score * self.phase + eg_score * (24 - self.phase)) / 24
+    }
+}
+
 pub fn evaluate(pos: &Position) -> i32 {
+    eval_terms(pos).score(pos.turn)
+}
+
+pub fn eval_terms(pos: &Position) -> EvalTerms {
     let mut mg = [0; 2];
     let mut eg = [0; 2];
     let mut mg_phase = 0;
@@ -15,12 +36,8 @@ pub fn evaluate(pos: &Position) -> i32 {
         }
     }
 
-    let mg_score = mg[pos.turn] - mg[!pos.turn];
-    let eg_score = eg[pos.turn] - eg[!pos.turn];
-
     if mg_phase > 24 { mg_phase = 24;}
-    let eg_phase = 24 - mg_phase;
-    (mg_score * mg_phase + eg_score * eg_phase) / 24
+    EvalTerms { mg, eg, phase: mg_phase }
 }
 
 const TABLES: ([[i32; 64]; 12], [[i32; 64]; 12]) = gen_pesto_tables();
@@ -38,8 +55,9 @@ const fn gen_pesto_tables() -> ([[i32; 64]; 12], [[i32; 64]; 12]) {
         while sq < 64 {
             mg_table[p][sq] = MG_PIECE_VALUES[p] + MG_PESTO_TABLES[p][sq];
             eg_table[p][sq] = EG_PIECE_VALUES[p] + EG_PESTO_TABLES[p][sq];
-            mg_table[p+6][sq] = MG_PIECE_VALUES[p] + MG_PESTO_TABLES[p][sq ^ 56];
-            eg_table[p+6][sq] = EG_PIECE_VALUES[p] + EG_PESTO_TABLES[p][sq ^ 56];
+            let relative_sq = Square::ALL[sq].relative_to(Colour::Black) as usize;
+            mg_table[p+6][sq] = MG_PIECE_VALUES[p] + MG_PESTO_TABLES[p][relative_sq];
+            eg_table[p+6][sq] = EG_PIECE_VALUES[p] + EG_PESTO_TABLES[p][relative_sq];
             sq += 1;
         }
 
@@ -1,36 +1,48 @@
-use std::num::ParseIntError;
+use core::num::ParseIntError;
 
 use num::FromPrimitive;
 
-use crate::bitboard::{Bitboard, Square};
+use crate::prelude::*;
+use crate::bitboard::{Bitboard, File, Rank, Square::{self, A1}};
 use crate::position::{CastlingFlags, Piece, Position};
+use crate::validate::PositionError;
 use crate::position::{Piece::*, Colour::*};
 
 const N_FIELDS: usize = 6;
+// the halfmove and fullmove clocks may be left off, as in EPD
+const MIN_FIELDS: usize = 4;
 pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 type FenResult = Result<(), FenError>;
 type Parser = fn(&mut Position, &str) -> FenResult;
 
-#[derive(Debug)]
-enum FenError {
+#[derive(Debug, PartialEq)]
+pub enum FenError {
+    FieldCount(usize),
     InvalidBoardSize,
     InvalidBoardChar(char),
+    RankLength(Rank),
     Turn(String),
     Castling(char),
     EnPassant(String),
+    Position(PositionError),
     HalfMove(ParseIntError),
     FullMove(ParseIntError),
 }
 
-impl std::fmt::Display for FenError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::error::Error for FenError {}
+
+impl core::fmt::Display for FenError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
+            FenError::FieldCount(n) => write!(f, "Expected {} to {} fields, found {}", MIN_FIELDS, N_FIELDS, n),
             FenError::InvalidBoardSize => write!(f, "Invalid board size"),
             FenError::InvalidBoardChar(c) => write!(f, "Invalid character in board: {}", c),
+            FenError::RankLength(r) => write!(f, "Rank {} doesn't have 8 squares", r),
             FenError::Turn(s) => write!(f, "Invalid turn: {}", s),
             FenError::Castling(c) => write!(f, "Invalid castling: {}", c),
             FenError::EnPassant(s) => write!(f, "Invalid en passant: {}", s),
+            FenError::Position(e) => write!(f, "{}", e),
             FenError::HalfMove(e) => write!(f, "Invalid halfmove: {}", e),
             FenError::FullMove(e) => write!(f, "Invalid fullmove: {}", e),
         }
@@ -38,35 +50,51 @@ impl std::fmt::Display for FenError {
 }
 
 impl Position {
-    pub fn read_fen(&mut self, fen: &str) {
-        *self = Position::new();
+    pub fn try_from_fen(fen: &str) -> Result<Position, FenError> {
+        let mut position = Position::new();
         let fen = fen.replace('\"', "");
         let fields: Vec<&str> = fen.split_whitespace().collect();
+        if !(MIN_FIELDS..=N_FIELDS).contains(&fields.len()) {
+            return Err(FenError::FieldCount(fields.len()));
+        }
 
         let parsers: [Parser; N_FIELDS] = [board, turn, castling, ep, halfmove, fullmove];
+        for (parser, field) in parsers.iter().zip(fields) {
+            parser(&mut position, field)?;
+        }
 
-        fields.iter().enumerate().for_each(|(i, x)| {
-            match parsers[i](self, x) {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("info string Error parsing FEN: {}", e);
-                }
-            }
-        });
-        
-        self.occupancy[White] = self.pieces[White].iter().fold(Bitboard(0), |acc, x| acc | *x);
-        self.occupancy[Black] = self.pieces[Black].iter().fold(Bitboard(0), |acc, x| acc | *x);
-        self.gen_zobrist_hash();
+        position.occupancy[White] = position.pieces[White].iter().fold(Bitboard(0), |acc, x| acc | *x);
+        position.occupancy[Black] = position.pieces[Black].iter().fold(Bitboard(0), |acc, x| acc | *x);
+        position.validate().map_err(FenError::Position)?;
+        position.gen_zobrist_hash();
+
+        Ok(position)
+    }
+
+    // leaves the position untouched if the FEN is invalid
+    pub fn read_fen(&mut self, fen: &str) -> Result<(), FenError> {
+        *self = Position::try_from_fen(fen)?;
+        Ok(())
     }
 
+    // castling is written as KQkq where that's unambiguous, so standard positions give plain FEN
     pub fn write_fen(&self) -> String {
+        self.format_fen(false)
+    }
+
+    // castling is written as rook files, AHah for the standard start position
+    pub fn write_shredder_fen(&self) -> String {
+        self.format_fen(true)
+    }
+
+    fn format_fen(&self, shredder: bool) -> String {
         let mut fen = String::new();
 
         // board
-        for r in 0..8 {
+        for rank in enum_iterator::all::<Rank>() {
             let mut empty_count: u8 = 0;
-            for f in 0..8 {
-                let sq = Square::from_u8(r*8 + f).unwrap();
+            for file in enum_iterator::all::<File>() {
+                let sq = Square::make(file, rank);
                 if let Some(p) = enum_iterator::all::<Piece>().find(|p| self.pieces[*p].is_set(sq)) {
                     if empty_count != 0 {
                         fen.push((b'0' + empty_count) as char);
@@ -81,7 +109,7 @@ impl Position {
             if empty_count != 0 {
                 fen.push((b'0' + empty_count) as char);
             }
-            if r < 7 { 
+            if rank != Rank::R1 { 
                 fen.push('/') 
             };
         }
@@ -92,7 +120,7 @@ impl Position {
             Black => fen.push('b'),
         }
 
-        fen.push_str(&format!(" {}", self.castling));
+        fen.push_str(&format!(" {}", self.write_castling(shredder)));
 
         if let Some(sq) = self.en_passant {
             fen.push_str(&format!(" {}", sq));
@@ -104,44 +132,80 @@ impl Position {
 
         let fullmove = match self.turn {
             White => (self.ply + 2) / 2,
-            Black => (self.ply + 1) / 2,
+            Black => self.ply.div_ceil(2),
         };
         fen.push_str(&format!(" {}", fullmove));
 
         fen
     }
+
+    pub(crate) fn write_castling(&self, shredder: bool) -> String {
+        if self.castling.is_empty() {
+            return "-".to_string();
+        }
+
+        let mut castling = String::new();
+        for right in self.castling.iter() {
+            let kingside = right.intersects(CastlingFlags::WK | CastlingFlags::BK);
+            let side = if right.intersects(CastlingFlags::WK | CastlingFlags::WQ) { White } else { Black };
+            let rook = self.castling_rooks[right.index()];
+            let rooks = self.pieces[Rook(side)] & Bitboard::rank(rook.rank());
+            let outermost = if kingside { rooks.msb() } else { rooks.get_lsb() };
+
+            let char = match (shredder || outermost != Some(rook), kingside) {
+                (true, _) => (b'a' + rook.file() as u8) as char,
+                (false, true) => 'k',
+                (false, false) => 'q',
+            };
+            castling.push(if side == White { char.to_ascii_uppercase() } else { char });
+        }
+
+        castling
+    }
 }
 
 fn board(position: &mut Position, board: &str) -> FenResult {
-    let mut i = 0;
-    let mut j = 0;
-    for char in board.chars() {
-        let sq = Square::A8.add(8*j + i).ok_or(FenError::InvalidBoardSize)?;
-        i+= 1;
-        match char {
-            'K' => position.pieces[King(White)].set(sq),
-            'Q' => position.pieces[Queen(White)].set(sq),
-            'R' => position.pieces[Rook(White)].set(sq),
-            'B' => position.pieces[Bishop(White)].set(sq),
-            'N' => position.pieces[Knight(White)].set(sq),
-            'P' => position.pieces[Pawn(White)].set(sq),
-            'k' => position.pieces[King(Black)].set(sq),
-            'q' => position.pieces[Queen(Black)].set(sq),
-            'r' => position.pieces[Rook(Black)].set(sq),
-            'b' => position.pieces[Bishop(Black)].set(sq),
-            'n' => position.pieces[Knight(Black)].set(sq),
-            'p' => position.pieces[Pawn(Black)].set(sq),
-            '1'..='7' => i += char.to_digit(10).unwrap() as i8 - 1,
-            '8' => (),
-            '/' => {
-                j += 1;
-                i = 0
-            },
-            _=> return Err(FenError::InvalidBoardChar(char))
+    let rows: Vec<&str> = board.split('/').collect();
+    if rows.len() != 8 {
+        return Err(FenError::InvalidBoardSize);
+    }
+
+    for (rank, row) in enum_iterator::all::<Rank>().zip(rows) {
+        let mut file = 0;
+        for char in row.chars() {
+            let piece = match char {
+                'K' => King(White),
+                'Q' => Queen(White),
+                'R' => Rook(White),
+                'B' => Bishop(White),
+                'N' => Knight(White),
+                'P' => Pawn(White),
+                'k' => King(Black),
+                'q' => Queen(Black),
+                'r' => Rook(Black),
+                'b' => Bishop(Black),
+                'n' => Knight(Black),
+                'p' => Pawn(Black),
+                '1'..='8' => {
+                    file += char.to_digit(10).unwrap() as u8;
+                    continue;
+                }
+                _=> return Err(FenError::InvalidBoardChar(char))
+            };
+
+            let Some(f) = File::from_u8(file) else {
+                return Err(FenError::RankLength(rank));
+            };
+            position.pieces[piece].set(Square::make(f, rank));
+            file += 1;
+        }
+
+        if file != 8 {
+            return Err(FenError::RankLength(rank));
         }
     }
 
-Ok(())
+    Ok(())
 }
 
 fn turn(position: &mut Position, turn: &str) -> FenResult {
@@ -154,16 +218,40 @@ fn turn(position: &mut Position, turn: &str) -> FenResult {
     Ok(())
 }
 
+// KQkq picks the outermost rook on that side of the king (X-FEN), a file letter names the rook
+// directly (Shredder-FEN), which Chess960 needs when there are two rooks on one side
 fn castling(position: &mut Position, castling: &str) -> FenResult {
+    if castling == "-" {
+        return Ok(());
+    }
+
     for char in castling.chars() {
-        position.castling |= match char {
-            'K' => CastlingFlags::WK,
-            'k' => CastlingFlags::BK,
-            'Q' => CastlingFlags::WQ,
-            'q' => CastlingFlags::BQ,
-            '-' => CastlingFlags::empty(),
-            _=> return Err(FenError::Castling(char))
-        }
+        let side = if char.is_ascii_uppercase() { White } else { Black };
+        let back_rank = A1.relative_to(side).rank();
+        let Some(king) = (position.pieces[King(side)] & Bitboard::rank(back_rank)).get_lsb() else {
+            return Err(FenError::Castling(char));
+        };
+
+        let rooks = position.pieces[Rook(side)] & Bitboard::rank(back_rank);
+        let rook = match char.to_ascii_lowercase() {
+            'k' => rooks.filter(|sq| sq.file() > king.file()).last(),
+            'q' => rooks.into_iter().find(|sq| sq.file() < king.file()),
+            c @ 'a'..='h' => File::from_u8(c as u8 - b'a')
+                .map(|file| Square::make(file, back_rank))
+                .filter(|sq| rooks.is_set(*sq)),
+            _ => None,
+        };
+        let Some(rook) = rook else {
+            return Err(FenError::Castling(char));
+        };
+
+        let right = if rook.file() > king.file() {
+            CastlingFlags::kingside(side)
+        } else {
+            CastlingFlags::queenside(side)
+        };
+        position.castling |= right;
+        position.castling_rooks[right.index()] = rook;
     }
 
     Ok(())
@@ -188,15 +276,92 @@ fn halfmove(position: &mut Position, halfmove: &str) -> FenResult {
 }
 
 fn fullmove(position: &mut Position, fullmove: &str) -> FenResult {
-    let fullmove: u8 = match fullmove.parse() {
+    let fullmove: u16 = match fullmove.parse() {
         Ok(x) => x,
         Err(e) => return Err(FenError::FullMove(e))
     };
 
+    // some GUIs send 0, treat it as the first move, and the ply has to fit in a u16
+    let fullmove = fullmove.clamp(1, u16::MAX / 2);
     match position.turn {
         White => position.ply = fullmove * 2 - 2,
         Black => position.ply = fullmove * 2 - 1,
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::Colour;
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/8/2k5/8/8/8/4K3 b - - 12 200",
+            "8/8/8/2k5/8/8/8/4K3 w - - 0 32767",
+        ] {
+            assert_eq!(Position::try_from_fen(fen).unwrap().write_fen(), fen);
+        }
+
+        let position = Position::try_from_fen("8/8/8/2k5/8/8/8/4K3 w - -").unwrap();
+        assert_eq!(position.write_fen(), "8/8/8/2k5/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn fen_errors() {
+        let fen = |fen| Position::try_from_fen(fen).unwrap_err();
+        assert_eq!(fen("8/8/8/8/8/8/8/8 w"), FenError::FieldCount(2));
+        assert_eq!(fen("8/8/8/8/8/8/8/8 w - - 0 1 extra"), FenError::FieldCount(7));
+        assert_eq!(fen("8/8/8/8/8/8/8 w - - 0 1"), FenError::InvalidBoardSize);
+        assert_eq!(fen("8/8/8/8/8/8/8/8/8 w - - 0 1"), FenError::InvalidBoardSize);
+        assert_eq!(fen("8/8/8/8/8/8/8/7x w - - 0 1"), FenError::InvalidBoardChar('x'));
+        assert_eq!(fen("8/8/8/8/8/8/8/9 w - - 0 1"), FenError::InvalidBoardChar('9'));
+        assert_eq!(fen("8/8/8/4k3/8/8/8/4K4 w - - 0 1"), FenError::RankLength(Rank::R1));
+        assert_eq!(fen("8/8/8/4k2/8/8/8/4K3 w - - 0 1"), FenError::RankLength(Rank::R5));
+        assert_eq!(fen("4k3/8/8/8/8/8/8/4K3 x - - 0 1"), FenError::Turn("x".to_string()));
+        assert_eq!(fen("4k3/8/8/8/8/8/8/4K2R w KX - 0 1"), FenError::Castling('X'));
+        assert_eq!(fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), FenError::Castling('K'));
+        assert_eq!(fen("4k3/8/8/8/8/8/8/R3K2R w G - 0 1"), FenError::Castling('G'));
+        assert_eq!(fen("4k3/8/8/8/8/8/8/4K3 w - e9 0 1"), FenError::EnPassant("e9".to_string()));
+        assert!(matches!(fen("4k3/8/8/8/8/8/8/4K3 w - - x 1"), FenError::HalfMove(_)));
+        assert!(matches!(fen("4k3/8/8/8/8/8/8/4K3 w - - 0 x"), FenError::FullMove(_)));
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // KQkq takes the outermost rooks, the inner c-file ones need their file written out
+        let fen = "rkr4r/8/8/8/8/8/8/RKR4R w KQkq - 0 1";
+        let position = Position::try_from_fen(fen).unwrap();
+        assert_eq!(position.castling_rooks, [Square::H1, Square::A1, Square::H8, Square::A8]);
+        assert_eq!(position.write_fen(), fen);
+
+        let position = Position::try_from_fen("rkr4r/8/8/8/8/8/8/RKR4R w CAca - 0 1").unwrap();
+        assert_eq!(position.castling_rooks, [Square::C1, Square::A1, Square::C8, Square::A8]);
+        assert_eq!(position.write_fen(), "rkr4r/8/8/8/8/8/8/RKR4R w CQcq - 0 1");
+        assert_eq!(position.write_shredder_fen(), "rkr4r/8/8/8/8/8/8/RKR4R w CAca - 0 1");
+
+        let position = Position::try_from_fen(STARTING_FEN).unwrap();
+        assert_eq!(position.write_shredder_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1");
+        assert_eq!(Position::try_from_fen(&position.write_shredder_fen()).unwrap(), position);
+    }
+
+    #[test]
+    fn fen_validates_position() {
+        let fen = |fen| Position::try_from_fen(fen).unwrap_err();
+        assert_eq!(fen("4k3/8/8/8/8/8/8/4K2P w - - 0 1"), FenError::Position(PositionError::PawnOnBackRank(Square::H1)));
+        assert_eq!(fen("8/8/8/8/8/8/8/4K3 w - - 0 1"), FenError::Position(PositionError::KingCount(Colour::Black, 0)));
+
+        // wrong rank for the side to move
+        assert_eq!(fen("4k3/8/8/8/3Pp3/8/8/4K3 w - d3 0 1"), FenError::Position(PositionError::EnPassant(Square::D3)));
+        // no pawn in front of the square
+        assert_eq!(fen("4k3/8/8/8/4p3/8/8/4K3 b - d3 0 1"), FenError::Position(PositionError::EnPassant(Square::D3)));
+        // starting square occupied
+        assert_eq!(fen("4k3/8/8/8/3Pp3/8/3N4/4K3 b - d3 0 1"), FenError::Position(PositionError::EnPassant(Square::D3)));
+    }
+}
@@ -0,0 +1,235 @@
+// A game from some start position, keeping everything needed to take moves back and to spot
+// repetitions, so callers don't have to maintain their own hash history.
+
+use core::fmt::Display;
+
+use crate::prelude::*;
+use crate::{
+    fen::STARTING_FEN,
+    movegen::{Move, MoveKind},
+    outcome::Outcome,
+    position::{Piece, Position},
+};
+
+// length of the hash history ring buffer, comfortably more than the fifty-move window plus search depth
+pub const MAX_GAME_PLY: usize = 256;
+
+// the first move that couldn't be played and where it was in the list
+#[derive(Debug, PartialEq)]
+pub struct IllegalMove {
+    pub index: usize,
+    pub mv: String,
+}
+
+impl Display for IllegalMove {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "illegal move {} at index {}", self.mv, self.index)
+    }
+}
+
+impl core::error::Error for IllegalMove {}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    start: Position,
+    position: Position,
+    moves: Vec<Move>,
+    // the position before each move, so undo is a copy
+    previous: Vec<Position>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new(Position::from_fen(STARTING_FEN))
+    }
+}
+
+impl Game {
+    pub fn new(start: Position) -> Game {
+        Game {
+            start,
+            position: start,
+            moves: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn start(&self) -> &Position {
+        &self.start
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    // the move must be pseudo-legal in the current position
+    pub fn push(&mut self, mv: Move) {
+        self.previous.push(self.position.make_move(mv));
+        self.moves.push(mv);
+    }
+
+    // plays a move in the from-to notation used by UCI, returning it if it's legal
+    pub fn push_algebraic(&mut self, mv_str: &str) -> Option<Move> {
+        let mv = self.position.find_algebraic_move(mv_str)?;
+        if !self.position.is_legal(mv) {
+            return None;
+        }
+
+        self.push(mv);
+        Some(mv)
+    }
+
+    // plays UCI moves until one isn't legal, leaving the game just before it
+    pub fn push_uci_moves<'a>(&mut self, moves: impl IntoIterator<Item = &'a str>) -> Result<(), IllegalMove> {
+        for (index, mv) in moves.into_iter().enumerate() {
+            if self.push_algebraic(mv).is_none() {
+                return Err(IllegalMove { index, mv: mv.to_string() });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Option<Move> {
+        self.position = self.previous.pop()?;
+        self.moves.pop()
+    }
+
+    // every piece taken so far, in the order they were captured
+    pub fn captured(&self) -> impl Iterator<Item = Piece> + '_ {
+        self.moves.iter().filter_map(|mv| match mv.kind {
+            MoveKind::Capture(p) | MoveKind::PromotionCapture(_, p) => Some(p),
+            MoveKind::EnPassant => Some(Piece::Pawn(!mv.piece.colour())),
+            _ => None,
+        })
+    }
+
+    // hashes of the positions played so far indexed by ply, the ring buffer search and draw
+    // detection use
+    pub fn hash_history(&self) -> [u64; MAX_GAME_PLY] {
+        let mut history = [0; MAX_GAME_PLY];
+        for position in &self.previous {
+            history[position.ply as usize % MAX_GAME_PLY] = position.hash;
+        }
+
+        history
+    }
+
+    pub fn repetitions(&self) -> usize {
+        self.position.repetitions(&self.hash_history())
+    }
+
+    pub fn is_draw(&self) -> bool {
+        self.position.is_draw(&self.hash_history())
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        self.position.outcome(&self.hash_history())
+    }
+}
+
+// plays UCI moves from start, checking each is legal, as the position command does
+pub fn apply_uci_moves<'a>(start: Position, moves: impl IntoIterator<Item = &'a str>) -> Result<Game, IllegalMove> {
+    let mut game = Game::new(start);
+    game.push_uci_moves(moves)?;
+
+    Ok(game)
+}
+
+impl Position {
+    // the position after the moves and the hash history leading to it, for callers not keeping a Game
+    pub fn from_moves<'a>(
+        start: Position,
+        moves: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(Position, [u64; MAX_GAME_PLY]), IllegalMove> {
+        let game = apply_uci_moves(start, moves)?;
+        Ok((*game.position(), game.hash_history()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::{Colour::*, Piece::*};
+
+    #[test]
+    fn push_and_undo() {
+        let mut game = Game::default();
+        for mv in ["e2e4", "d7d5", "e4d5", "d8d5"] {
+            assert!(game.push_algebraic(mv).is_some());
+        }
+        assert!(game.push_algebraic("e1e3").is_none());
+        assert_eq!(game.moves().len(), 4);
+        assert_eq!(game.captured().collect::<Vec<_>>(), [Pawn(Black), Pawn(White)]);
+
+        while game.undo().is_some() {}
+        assert_eq!(game.position(), game.start());
+        assert!(game.undo().is_none());
+    }
+
+    #[test]
+    fn game_repetitions() {
+        let mut game = Game::default();
+        for _ in 0..2 {
+            for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                game.push_algebraic(mv).unwrap();
+            }
+        }
+        assert_eq!(game.repetitions(), 2);
+        assert!(game.is_draw());
+        assert_eq!(game.outcome(), Outcome::Draw(crate::outcome::DrawReason::Repetition));
+
+        game.undo();
+        assert!(!game.is_draw());
+    }
+
+    #[test]
+    fn apply_moves() {
+        let start = Position::from_fen(STARTING_FEN);
+        let game = apply_uci_moves(start, ["e2e4", "e7e5", "g1f3"]).unwrap();
+        assert_eq!(game.moves().len(), 3);
+        assert_eq!(game.position().write_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        let (position, history) = Position::from_moves(start, "g1f3 g8f6 f3g1 f6g8".split_whitespace()).unwrap();
+        assert_eq!(position.hash, start.hash);
+        assert_eq!(position.repetitions(&history), 1);
+
+        let err = apply_uci_moves(start, ["e2e4", "e2e4"]).unwrap_err();
+        assert_eq!(err, IllegalMove { index: 1, mv: "e2e4".to_string() });
+        assert!(Position::from_moves(start, ["e1e2"]).is_err());
+
+        let mut game = Game::new(start);
+        let err = game.push_uci_moves(["e2e4", "e7e5", "e1e3", "g1f3"]).unwrap_err();
+        assert_eq!(err.to_string(), "illegal move e1e3 at index 2");
+        assert_eq!(game.moves().len(), 2);
+    }
+
+    #[test]
+    fn long_game() {
+        let mut game = Game::default();
+        for _ in 0..80 {
+            for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                game.push_algebraic(mv).unwrap();
+            }
+        }
+        assert_eq!(game.position().ply, 320);
+        assert!(game.position().write_fen().ends_with(" 161"));
+        // only the positions still in the hash history ring buffer count
+        assert_eq!(game.repetitions(), MAX_GAME_PLY / 4);
+
+        // stale hashes from before the pawn moves wrap around to the same slots
+        game.push_algebraic("e2e3").unwrap();
+        game.push_algebraic("e7e6").unwrap();
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            game.push_algebraic(mv).unwrap();
+        }
+        assert_eq!(game.repetitions(), 1);
+
+        while game.undo().is_some() {}
+        assert_eq!(game.position(), game.start());
+    }
+}
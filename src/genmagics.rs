@@ -0,0 +1,138 @@
+// `blunderbuss genmagics [--seed <n> | --find-seed <candidates>] [--min-bits] [--attempts <n>]`
+// searches for rook and bishop magics and renders them as constants ready to paste into magic.rs
+
+use std::time::Instant;
+
+use crate::{
+    magic::{find_best_seed, find_smallest_magic, format_bits, format_magics, par_squares, Slider, SEED},
+};
+
+const DEFAULT_ATTEMPTS: u64 = 1_000_000;
+
+#[derive(Debug, PartialEq)]
+pub struct GenMagicsOptions {
+    pub seed: u64,
+    // score this many candidate seeds and use the best instead of seed
+    pub find_seed: Option<usize>,
+    // try to shrink each square's table below the hardcoded bit count
+    pub min_bits: bool,
+    // candidates tried per square for each reduced bit count
+    pub attempts: u64,
+}
+
+impl Default for GenMagicsOptions {
+    fn default() -> Self {
+        Self {
+            seed: SEED,
+            find_seed: None,
+            min_bits: false,
+            attempts: DEFAULT_ATTEMPTS,
+        }
+    }
+}
+
+impl GenMagicsOptions {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = GenMagicsOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = parse_value(arg, args.next())?,
+                "--find-seed" => options.find_seed = Some(parse_value(arg, args.next())? as usize),
+                "--attempts" => options.attempts = parse_value(arg, args.next())?,
+                "--min-bits" => options.min_bits = true,
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_value(arg: &str, value: Option<&String>) -> Result<u64, String> {
+    let value = value.ok_or(format!("missing value for {}", arg))?;
+    value.parse().map_err(|e| format!("invalid value for {}: {}", arg, e))
+}
+
+struct SliderMagics {
+    magics: [u64; 64],
+    bits: [u8; 64],
+    millis: u128,
+}
+
+fn search(slider: Slider, seed: u64, min_bits_attempts: Option<u64>) -> SliderMagics {
+    let timer = Instant::now();
+    let found = par_squares(|sq| find_smallest_magic(sq, slider, seed, min_bits_attempts));
+
+    let mut magics = [0; 64];
+    let mut bits = [0; 64];
+    for (i, found) in found.into_iter().enumerate() {
+        magics[i] = found.magic;
+        bits[i] = found.bits;
+    }
+
+    SliderMagics { magics, bits, millis: timer.elapsed().as_millis() }
+}
+
+fn table_size(bits: &[u8; 64]) -> usize {
+    bits.iter().map(|bits| 1 << bits).sum()
+}
+
+pub fn generate(options: &GenMagicsOptions) -> String {
+    let min_bits_attempts = options.min_bits.then_some(options.attempts);
+    let seed = match options.find_seed {
+        Some(candidates) => find_best_seed(candidates, min_bits_attempts).0,
+        None => options.seed,
+    };
+
+    let rook = search(Slider::Rook, seed, min_bits_attempts);
+    let bishop = search(Slider::Bishop, seed, min_bits_attempts);
+
+    let mut out = format!("// seed {}\n", seed);
+    out.push_str(&format!(
+        "// rooks: {} ms, {} entries\n",
+        rook.millis,
+        table_size(&rook.bits)
+    ));
+    out.push_str(&format!(
+        "// bishops: {} ms, {} entries\n\n",
+        bishop.millis,
+        table_size(&bishop.bits)
+    ));
+    out.push_str(&format_magics("ROOK_MAGICS", &rook.magics));
+    out.push('\n');
+    out.push_str(&format_magics("BISHOP_MAGICS", &bishop.magics));
+    out.push('\n');
+    out.push_str(&format_bits("ROOK_BITS", &rook.bits));
+    out.push('\n');
+    out.push_str(&format_bits("BISHOP_BITS", &bishop.bits));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::{BISHOP_MAGICS, ROOK_MAGICS};
+
+    #[test]
+    fn genmagics_options_test() {
+        assert_eq!(GenMagicsOptions::parse(&[]), Ok(GenMagicsOptions::default()));
+
+        let args: Vec<String> = ["--seed", "42", "--min-bits", "--attempts", "10"].map(String::from).to_vec();
+        let options = GenMagicsOptions::parse(&args).unwrap();
+        assert_eq!(options, GenMagicsOptions { seed: 42, find_seed: None, min_bits: true, attempts: 10 });
+
+        let args: Vec<String> = ["--find-seed", "8"].map(String::from).to_vec();
+        assert_eq!(GenMagicsOptions::parse(&args).unwrap().find_seed, Some(8));
+
+        assert!(GenMagicsOptions::parse(&["--seed".to_string()]).is_err());
+        assert!(GenMagicsOptions::parse(&["--bogus".to_string()]).is_err());
+    }
+
+    #[test]
+    fn genmagics_reproduces_hardcoded_tables() {
+        let out = generate(&GenMagicsOptions::default());
+        assert!(out.contains(&format_magics("ROOK_MAGICS", &ROOK_MAGICS)));
+        assert!(out.contains(&format_magics("BISHOP_MAGICS", &BISHOP_MAGICS)));
+    }
+}
@@ -1,14 +1,26 @@
 // uci protocol https://www.wbec-ridderkerk.nl/html/UCIProtocol.html
 
-use std::str::SplitWhitespace;
+use std::{
+    str::{FromStr, SplitWhitespace},
+    time::Instant,
+};
 
 use itertools::Itertools;
+use log::{warn, LevelFilter};
 
 use crate::{
-    engine::MAX_GAME_PLY, 
+    benchmark::BenchOptions,
     fen::STARTING_FEN,  
-    position::Position, 
-    search::{CurrMoveInfo, FullInfo, CHECKMATE, MAX_DEPTH}
+    game::Game,
+    logging::Output,
+    position::{BoardStyle, Position}, 
+    search::{CurrMoveInfo, FullInfo, ProgressInfo, CHECKMATE, MAX_DEPTH},
+    matchplay::{MatchOptions, Sprt, TimeControl},
+    options::{find_option, OptionValue, OPTIONS},
+    perft::perft_divide,
+    selfplay::{Adjudication, SelfPlayOptions},
+    send,
+    solve::DEFAULT_SOLVE_TIME,
 };
 
 #[derive(Debug)]
@@ -16,18 +28,52 @@ pub enum Command {
     Uci,
     Debug(bool),
     IsReady,
-    _SetOption(EngineOption),
+    SetOption(EngineOption),
     UCINewGame,
-    Position(Position, Box<[u64; MAX_GAME_PLY]>),
+    Position(Box<Game>),
     Go(SearchControl),
     Stop,
     PonderHit,
     Quit,
-    Print,
-    Perft(u8),
+    Register,
+    CopyProtection,
+    Print(BoardStyle),
+    // with a hash table of some mb, from a FEN or from the current position when there's none
+    Perft(u8, usize, Option<Box<Position>>),
+    // an EPD suite of D<depth> <nodes> operations, checked up to the depth
+    PerftSuite(String, u8, usize),
     Evaluate,
+    // static exchange evaluation of a move in the current position
+    See(String),
+    // the hash table's entry for the current position
+    Probe,
     Move(String),
-    Benchmark,
+    Undo,
+    // passes the turn
+    Flip,
+    // the legal moves in the order the search would try them
+    Moves,
+    // against a FEN, or against the current position with its keys recomputed when there's none
+    Diff(Option<Box<Position>>),
+    Benchmark(BenchOptions),
+    Solve(String, u32),
+    SelfPlay(SelfPlayOptions),
+    Match(MatchOptions),
+}
+
+impl Command {
+    // reads or writes files on the engine's machine, or starts a program there
+    pub fn uses_files(&self) -> bool {
+        match self {
+            Command::SetOption(option) => matches!(
+                option,
+                EngineOption::BookFile(_) | EngineOption::DebugLogFile(_) | EngineOption::LearningFile(_)
+            ) || matches!(option, EngineOption::LogTarget(target) if target != "uci" && target != "stderr"),
+            Command::Benchmark(options) => options.file.is_some(),
+            Command::PerftSuite(..) | Command::Solve(..) | Command::SelfPlay(_) | Command::Match(_) => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Default, Debug)]
@@ -64,54 +110,137 @@ impl SearchControl {
 }
 
 #[derive(Debug)]
-pub enum EngineOption {}
+pub enum EngineOption {
+    OwnBook(bool),
+    // an empty path unloads the book
+    BookFile(String),
+    // and stops logging
+    DebugLogFile(String),
+    JsonOutput(bool),
+    // in MB
+    Hash(usize),
+    ClearHash,
+    LogLevel(LevelFilter),
+    // uci, stderr or a file path
+    LogTarget(String),
+    // castling is sent and written king takes rook
+    Chess960(bool),
+    Deterministic(bool),
+    Learning(bool),
+    // an empty path learns for this session only
+    LearningFile(String),
+}
 
 pub fn parse_command(line: &str) -> Option<Command> {
     let mut tokens = line.split_whitespace();
 
-    let cmd = match tokens.next()? {
-        "uci" => Command::Uci,
-        "debug" => Command::Debug(tokens.next()? == "on"),
-        "isready" => Command::IsReady,
-        "setoption" => todo!(),
-        "ucinewgame" => Command::UCINewGame,
-        "position" => position(tokens)?,
-        "go" => go(tokens)?,
-        "stop" => Command::Stop,
-        "ponderhit" => Command::PonderHit,
-        "quit" => Command::Quit,
-        "print" => Command::Print,
-        "perft" => Command::Perft(tokens.next()?.parse().ok()?),
-        "eval" => Command::Evaluate,
-        "move" => Command::Move(tokens.next()?.to_string()),
-        "bench" => Command::Benchmark,
-        _ => return None
+    // the UCI spec asks for unknown tokens to be skipped and the rest of the line parsed
+    let cmd = loop {
+        break match tokens.next()? {
+            "uci" => Command::Uci,
+            "debug" => Command::Debug(tokens.next()? == "on"),
+            "isready" => Command::IsReady,
+            "setoption" => setoption(tokens)?,
+            "ucinewgame" => Command::UCINewGame,
+            "position" => position(tokens)?,
+            "go" => go(tokens)?,
+            "stop" => Command::Stop,
+            "ponderhit" => Command::PonderHit,
+            "quit" => Command::Quit,
+            "register" => Command::Register,
+            "copyprotection" => Command::CopyProtection,
+            "print" | "d" => Command::Print(BoardStyle {
+                unicode: tokens.clone().any(|t| t == "unicode"),
+                from_black: tokens.any(|t| t == "flip"),
+            }),
+            "perft" => perft(tokens)?,
+            "eval" => Command::Evaluate,
+            "see" => Command::See(tokens.next()?.to_string()),
+            "probe" => Command::Probe,
+            "move" => Command::Move(tokens.next()?.to_string()),
+            "undo" => Command::Undo,
+            "flip" => Command::Flip,
+            "moves" => Command::Moves,
+            "diff" => match tokens.next() {
+                Some("fen") => Command::Diff(Some(Box::new(Position::try_from_fen(&tokens.join(" ")).ok()?))),
+                _ => Command::Diff(None),
+            },
+            "bench" => bench(tokens)?,
+            "solve" => Command::Solve(
+                tokens.next()?.to_string(),
+                tokens.next().map_or(Some(DEFAULT_SOLVE_TIME), |t| t.parse().ok())?,
+            ),
+            "selfplay" => selfplay(tokens)?,
+            "match" => match_options(tokens)?,
+            _ => continue
+        };
     };
 
     Some(cmd)
 }
 
 fn position(mut tokens: SplitWhitespace) -> Option<Command> {
-    let mut position = match tokens.next()? {
+    let position = match tokens.next()? {
         "startpos" => Position::from_fen(STARTING_FEN),
-        "fen" => Position::from_fen(&tokens.clone().take_while(|s| *s != "moves").join(" ")),
+        "frc" => Position::chess960(tokens.next()?.parse().ok()?)?,
+        "dfrc" => Position::dfrc(tokens.next()?.parse().ok()?, tokens.next()?.parse().ok()?)?,
+        "fen" => match Position::try_from_fen(&tokens.clone().take_while(|s| *s != "moves").join(" ")) {
+            Ok(position) => position,
+            Err(e) => {
+                warn!("parsing FEN: {}", e);
+                return None;
+            }
+        },
         _ => return None
     };
 
-    let mut history = [0; MAX_GAME_PLY];
-    while let Some(str) = tokens.next() {
-        if str != "moves" {
-            continue
-        }
+    // play up to a bad move rather than drop the command and leave the old position searched
+    let mut game = Game::new(position);
+    if let Err(e) = game.push_uci_moves(tokens.skip_while(|s| *s != "moves").skip(1)) {
+        warn!("{}, using the position before it", e);
+    }
 
-        for mv_str in tokens.by_ref() {
-            let mv = position.find_algebraic_move(mv_str)?;
-            history[position.ply as usize] = position.hash;
-            position.make_move(mv);
-        }
+    Some(Command::Position(Box::new(game)))
+}
+
+// setoption name <id> [value <x>], where both the name and value may contain spaces
+fn setoption(tokens: SplitWhitespace) -> Option<Command> {
+    let tokens: Vec<&str> = tokens.collect();
+    if tokens.first() != Some(&"name") {
+        return None;
     }
+    let value_idx = tokens.iter().position(|t| *t == "value").unwrap_or(tokens.len());
+    let name = tokens[1..value_idx].join(" ");
+    let value = tokens.get(value_idx + 1..).unwrap_or_default().join(" ");
+    let Some(option) = find_option(&name) else {
+        warn!("no option named {}", name);
+        return None;
+    };
+    let value = match option.parse(&value) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
+
+    let option = match (option.name, value) {
+        ("OwnBook", OptionValue::Check(own_book)) => EngineOption::OwnBook(own_book),
+        ("Book File", OptionValue::Text(path)) => EngineOption::BookFile(path),
+        ("Debug Log File", OptionValue::Text(path)) => EngineOption::DebugLogFile(path),
+        ("JSON Output", OptionValue::Check(json)) => EngineOption::JsonOutput(json),
+        ("Hash", OptionValue::Spin(mb)) => EngineOption::Hash(mb as usize),
+        ("Clear Hash", _) => EngineOption::ClearHash,
+        ("Log Level", OptionValue::Text(level)) => EngineOption::LogLevel(level.parse().ok()?),
+        ("Log Target", OptionValue::Text(target)) => EngineOption::LogTarget(target),
+        ("UCI_Chess960", OptionValue::Check(chess960)) => EngineOption::Chess960(chess960),
+        ("Deterministic", OptionValue::Check(deterministic)) => EngineOption::Deterministic(deterministic),
+        ("Learning", OptionValue::Check(learning)) => EngineOption::Learning(learning),
+        ("Learning File", OptionValue::Text(path)) => EngineOption::LearningFile(path),
+        _ => return None
+    };
 
-    Some(Command::Position(position, Box::new(history)))
+    Some(Command::SetOption(option))
 }
 
 fn go(mut tokens: SplitWhitespace) -> Option<Command> {
@@ -119,20 +248,21 @@ fn go(mut tokens: SplitWhitespace) -> Option<Command> {
 
     let mut control = SearchControl::new();
 
+    // unknown tokens and bad values are skipped, keeping the rest of the command
     while let Some(token) = next {
         match token {
-            "nodes" => control.nodes = tokens.next()?.parse().ok()?,
-            "depth" => control.depth = tokens.next()?.parse().ok()?,
-            "movetime" => control.movetime = tokens.next()?.parse().ok()?,
-            "wtime" => control.wtime = tokens.next()?.parse().ok()?,
-            "btime" => control.btime = tokens.next()?.parse().ok()?,
-            "winc" => control.winc = tokens.next()?.parse().ok()?,
-            "binc" => control.binc = tokens.next()?.parse().ok()?,
-            "movestogo" => control.movestogo = tokens.next()?.parse().ok()?,
-            "mate" => control.mate = tokens.next()?.parse().ok()?,
+            "nodes" => parse_value(&mut tokens, &mut control.nodes),
+            "depth" => parse_value(&mut tokens, &mut control.depth),
+            "movetime" => parse_value(&mut tokens, &mut control.movetime),
+            "wtime" => parse_value(&mut tokens, &mut control.wtime),
+            "btime" => parse_value(&mut tokens, &mut control.btime),
+            "winc" => parse_value(&mut tokens, &mut control.winc),
+            "binc" => parse_value(&mut tokens, &mut control.binc),
+            "movestogo" => parse_value(&mut tokens, &mut control.movestogo),
+            "mate" => parse_value(&mut tokens, &mut control.mate),
             "infinite" => control.infinite = true,
             "ponder" => control.ponder = true,
-            _ => return None
+            _ => (),
         }
 
         next = tokens.next();
@@ -141,38 +271,269 @@ fn go(mut tokens: SplitWhitespace) -> Option<Command> {
     Some(Command::Go(control))
 }
 
-pub fn id() {
-    println!("id name Blunderbuss");
-    println!("id author Felix Berman");
-    println!("uciok");
+// only takes the next token when it parses, it could be the next keyword
+fn parse_value<T: FromStr>(tokens: &mut SplitWhitespace, field: &mut T) {
+    if let Some(value) = tokens.clone().next().and_then(|t| t.parse().ok()) {
+        *field = value;
+        tokens.next();
+    }
 }
 
-pub fn write_full_info(info: FullInfo) {
-    let time = if info.time > 0 {
-        info.time
-    } else {
-        1
+// perft <depth> [hash <mb>] [fen <fen>] or perft suite <file> [depth] [hash <mb>]
+fn perft(mut tokens: SplitWhitespace) -> Option<Command> {
+    let (suite, mut depth) = match tokens.next()? {
+        "suite" => (Some(tokens.next()?.to_string()), u8::MAX),
+        depth => (None, depth.parse().ok()?),
     };
-    let nps = info.nodes / time * 1000;
+    let mut hash_mb = 0;
+    let mut position = None;
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "hash" => hash_mb = tokens.next()?.parse().ok()?,
+            "fen" if suite.is_none() => {
+                position = Some(Box::new(Position::try_from_fen(&tokens.join(" ")).ok()?));
+                break;
+            }
+            _ if suite.is_some() => depth = token.parse().ok()?,
+            _ => return None
+        }
+    }
+
+    match suite {
+        Some(path) => Some(Command::PerftSuite(path, depth, hash_mb)),
+        None => Some(Command::Perft(depth, hash_mb, position)),
+    }
+}
+
+// a bare number is the depth, as the old bench <depth> took it
+fn bench(mut tokens: SplitWhitespace) -> Option<Command> {
+    let mut options = BenchOptions::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => options.depth = tokens.next()?.parse().ok()?,
+            "movetime" => options.movetime = tokens.next()?.parse().ok()?,
+            "file" => options.file = Some(tokens.next()?.to_string()),
+            "count" => options.count = Some(tokens.next()?.parse().ok()?),
+            depth => options.depth = depth.parse().ok()?,
+        }
+    }
+
+    Some(Command::Benchmark(options))
+}
+
+fn selfplay(mut tokens: SplitWhitespace) -> Option<Command> {
+    let mut options = SelfPlayOptions::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "games" => options.games = tokens.next()?.parse().ok()?,
+            "nodes" => options.limits.nodes = tokens.next()?.parse().ok()?,
+            "depth" => options.limits.depth = tokens.next()?.parse().ok()?,
+            "movetime" => options.limits.movetime = tokens.next()?.parse().ok()?,
+            "openings" => options.openings = Some(tokens.next()?.to_string()),
+            "out" => options.output = tokens.next()?.to_string(),
+            "resign" | "draw" | "maxplies" => adjudication(token, &mut tokens, &mut options.adjudication)?,
+            _ => return None
+        }
+    }
 
-    let distance_from_mate = CHECKMATE - info.score.abs();
-    let score = if distance_from_mate <= info.depth as i32 {
-        format!("mate {}", distance_from_mate * info.score.signum())
-    } else {
-        format!("cp {}", info.score)
+    Some(Command::SelfPlay(options))
+}
+
+// resign <moves> <cp>, draw <after move> <moves> <cp> and maxplies <plies>, 0 moves turning
+// resigning or drawing off
+fn adjudication(token: &str, tokens: &mut SplitWhitespace, adjudication: &mut Adjudication) -> Option<()> {
+    match token {
+        "resign" => {
+            adjudication.resign_moves = tokens.next()?.parse().ok()?;
+            adjudication.resign_score = tokens.next()?.parse().ok()?;
+        }
+        "draw" => {
+            adjudication.draw_after = tokens.next()?.parse().ok()?;
+            adjudication.draw_moves = tokens.next()?.parse().ok()?;
+            adjudication.draw_score = tokens.next()?.parse().ok()?;
+        }
+        _ => adjudication.max_plies = tokens.next()?.parse().ok()?,
+    }
+
+    Some(())
+}
+
+// limits and options before a or b apply to both players, after to that one. options are given
+// as name=value, like option Hash=64. an engine path plays as b
+fn match_options(mut tokens: SplitWhitespace) -> Option<Command> {
+    let mut options = MatchOptions::new();
+    let mut players = 0..2;
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "games" => options.games = tokens.next()?.parse().ok()?,
+            "tc" => {
+                options.time_control = match tokens.next()? {
+                    "none" => None,
+                    tc => {
+                        let (base, inc) = tc.split_once('+').unwrap_or((tc, "0"));
+                        Some(TimeControl { base: base.parse().ok()?, inc: inc.parse().ok()? })
+                    }
+                }
+            }
+            "openings" => options.openings = Some(tokens.next()?.to_string()),
+            "out" => options.output = tokens.next()?.to_string(),
+            "engine" => options.engine = Some(tokens.next()?.to_string()),
+            "sprt" => options.sprt = Some(Sprt::new(tokens.next()?.parse().ok()?, tokens.next()?.parse().ok()?)),
+            "alpha" => options.sprt.as_mut()?.alpha = tokens.next()?.parse().ok()?,
+            "beta" => options.sprt.as_mut()?.beta = tokens.next()?.parse().ok()?,
+            "resign" | "draw" | "maxplies" => adjudication(token, &mut tokens, &mut options.adjudication)?,
+            "option" => {
+                let (name, value) = tokens.next()?.split_once('=')?;
+                for settings in &mut options.settings[players.clone()] {
+                    settings.push((name.to_string(), value.to_string()));
+                }
+            }
+            "a" => players = 0..1,
+            "b" => players = 1..2,
+            "nodes" | "depth" | "movetime" => {
+                let value = tokens.next()?;
+                for limits in &mut options.players[players.clone()] {
+                    match token {
+                        "nodes" => limits.nodes = value.parse().ok()?,
+                        "depth" => limits.depth = value.parse().ok()?,
+                        _ => limits.movetime = value.parse().ok()?,
+                    }
+                }
+            }
+            _ => return None
+        }
+    }
+
+    Some(Command::Match(options))
+}
+
+pub fn id(out: &Output) {
+    send!(out, "id name Blunderbuss");
+    send!(out, "id author Felix Berman");
+    for option in &OPTIONS {
+        send!(out, "{}", option);
+    }
+    send!(out, "uciok");
+}
+
+// castling is written king takes rook when chess960
+pub fn write_full_info(out: &Output, info: FullInfo, chess960: bool) {
+    let nps = info.nodes as u64 * 1000 / info.time.max(1) as u64;
+
+    let score = match mate_distance(info.score, info.depth) {
+        Some(distance) => format!("mate {}", distance),
+        None => format!("cp {}", info.score),
     };
 
-    let pv = info.pv.iter().filter_map(|mv| *mv);
+    let pv = info.pv.iter().filter_map(|mv| *mv).map(|mv| mv.to_uci(chess960));
 
-    println!(
+    send!(
+        out,
         "info depth {} seldepth {} score {} nodes {} nps {} time {} pv {}",
         info.depth, info.seldepth, score, info.nodes, nps, info.time, pv.format(" ")
     );
 }
 
-pub fn write_currmove_info(info: CurrMoveInfo) {
-    println!(
+// signed like the score when it's a mate found within the depth searched
+pub fn mate_distance(score: i32, depth: u8) -> Option<i32> {
+    let distance_from_mate = CHECKMATE - score.abs();
+    (distance_from_mate <= depth as i32).then_some(distance_from_mate * score.signum())
+}
+
+// sorted and laid out like stockfish's so the two can be diffed
+pub fn write_perft_divide(out: &Output, pos: &mut Position, depth: u8, hash_mb: usize) {
+    let start_time = Instant::now();
+    let mut results: Vec<(String, u64)> =
+        perft_divide(pos, depth, hash_mb).into_iter().map(|(mv, nodes)| (mv.to_string(), nodes)).collect();
+
+    results.sort();
+    for (mv, nodes) in &results {
+        send!(out, "{}: {}", mv, nodes);
+    }
+
+    let total_nodes: u64 = results.iter().map(|(_, nodes)| nodes).sum();
+    let time = start_time.elapsed().as_millis().max(1) as u64;
+    send!(out, "\nNodes searched: {}", total_nodes);
+    send!(out, "Time: {} ms", time);
+    send!(out, "Nodes/second: {}", total_nodes * 1000 / time);
+}
+
+pub fn write_progress_info(out: &Output, info: ProgressInfo) {
+    let nps = info.nodes as u64 * 1000 / info.time.max(1) as u64;
+    send!(out, "info nodes {} nps {} time {} hashfull {}", info.nodes, nps, info.time, info.hashfull);
+}
+
+pub fn write_currmove_info(out: &Output, info: CurrMoveInfo, chess960: bool) {
+    send!(
+        out,
         "info depth {} currmove {} currmovenumber {}",
-        info.depth, info.mv, info.mv_num
+        info.depth,
+        info.mv.to_uci(chess960),
+        info.mv_num
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_unknown_tokens() {
+        assert!(matches!(parse_command("joho debug on"), Some(Command::Debug(true))));
+        assert!(parse_command("joho").is_none());
+        // skipped without recursing, so a long line can't overflow the stack
+        assert!(matches!(parse_command(&format!("{}isready", "x ".repeat(100_000))), Some(Command::IsReady)));
+
+        let Some(Command::Go(control)) = parse_command("go wtime 1000 foo btime x winc 10 depth") else {
+            panic!("go not parsed");
+        };
+        assert_eq!((control.wtime, control.btime, control.winc, control.depth), (1000, 0, 10, MAX_DEPTH as u8));
+    }
+
+    #[test]
+    fn log_options() {
+        let Some(Command::SetOption(EngineOption::LogLevel(level))) = parse_command("setoption name Log Level value Debug") else {
+            panic!("log level not parsed");
+        };
+        assert_eq!(level, LevelFilter::Debug);
+        assert!(parse_command("setoption name Log Level value loud").is_none());
+    }
+
+    #[test]
+    fn adjudication_options() {
+        let Some(Command::SelfPlay(options)) = parse_command("selfplay games 2 resign 3 800 draw 30 6 15 maxplies 300") else {
+            panic!("selfplay not parsed");
+        };
+        let expected = Adjudication {
+            resign_moves: 3,
+            resign_score: 800,
+            draw_after: 30,
+            draw_moves: 6,
+            draw_score: 15,
+            max_plies: 300,
+        };
+        assert_eq!(options.adjudication, expected);
+
+        let Some(Command::Match(options)) = parse_command("match resign 0 0") else {
+            panic!("match not parsed");
+        };
+        assert_eq!(options.adjudication.resign_moves, 0);
+        assert!(parse_command("selfplay draw 30 6").is_none());
+    }
+
+    #[test]
+    fn match_player_options() {
+        let Some(Command::Match(options)) = parse_command("match option Hash=8 nodes 1000 a option Hash=64 b depth 4") else {
+            panic!("match not parsed");
+        };
+        let setting = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(options.settings, [vec![setting("Hash", "8"), setting("Hash", "64")], vec![setting("Hash", "8")]]);
+        assert_eq!((options.players[0].nodes, options.players[0].depth), (1000, MAX_DEPTH as u8));
+        assert_eq!((options.players[1].nodes, options.players[1].depth), (1000, 4));
+        assert!(parse_command("match a option Hash").is_none());
+    }
+}
@@ -0,0 +1,103 @@
+// JSON lines in place of UCI text for search info, best moves and evaluations, for frontends and
+// pipelines that would rather not parse UCI. One object per line, each with a "type" field.
+
+use itertools::Itertools;
+
+use crate::{
+    eval::EvalTerms,
+    interface::mate_distance,
+    movegen::Move,
+    position::Colour::*,
+    search::{CurrMoveInfo, FullInfo, ProgressInfo, StatsInfo},
+};
+
+pub fn full_info(info: &FullInfo, chess960: bool) -> String {
+    let nps = info.nodes as u64 * 1000 / info.time.max(1) as u64;
+    let score = match mate_distance(info.score, info.depth) {
+        Some(distance) => format!(r#"{{"mate":{}}}"#, distance),
+        None => format!(r#"{{"cp":{}}}"#, info.score),
+    };
+    let pv = info.pv.iter().filter_map(|mv| *mv).map(|mv| format!(r#""{}""#, mv.to_uci(chess960)));
+
+    format!(
+        r#"{{"type":"info","depth":{},"seldepth":{},"score":{},"nodes":{},"nps":{},"time":{},"pv":[{}]}}"#,
+        info.depth,
+        info.seldepth,
+        score,
+        info.nodes,
+        nps,
+        info.time,
+        pv.format(",")
+    )
+}
+
+pub fn currmove(info: &CurrMoveInfo, chess960: bool) -> String {
+    format!(
+        r#"{{"type":"currmove","depth":{},"move":"{}","number":{}}}"#,
+        info.depth,
+        info.mv.to_uci(chess960),
+        info.mv_num
+    )
+}
+
+pub fn progress(info: &ProgressInfo) -> String {
+    let nps = info.nodes as u64 * 1000 / info.time.max(1) as u64;
+    format!(
+        r#"{{"type":"progress","nodes":{},"nps":{},"time":{},"hashfull":{}}}"#,
+        info.nodes, nps, info.time, info.hashfull
+    )
+}
+
+pub fn stats(info: &StatsInfo) -> String {
+    format!(
+        r#"{{"type":"stats","depth":{},"ebf":{:.2},"tt_hits":{:.3},"first_move_cutoffs":{:.3},"qnodes":{:.3},"re_searches":{}}}"#,
+        info.depth,
+        info.branching_factor(),
+        info.tt_hit_rate(),
+        info.first_move_cutoff_rate(),
+        info.qnode_share(),
+        info.stats.re_searches
+    )
+}
+
+pub fn bestmove(mv: Option<Move>, chess960: bool) -> String {
+    let mv = mv.map_or("null".to_string(), |mv| format!(r#""{}""#, mv.to_uci(chess960)));
+    format!(r#"{{"type":"bestmove","move":{}}}"#, mv)
+}
+
+// score is from the side to move's point of view, the terms are per side
+pub fn eval(terms: &EvalTerms, score: i32) -> String {
+    format!(
+        r#"{{"type":"eval","score":{},"phase":{},"mg":{{"white":{},"black":{}}},"eg":{{"white":{},"black":{}}}}}"#,
+        score, terms.phase, terms.mg[White], terms.mg[Black], terms.eg[White], terms.eg[Black]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::eval_terms, fen::STARTING_FEN, position::Position, search::MAX_DEPTH};
+
+    #[test]
+    fn json_test() {
+        let position = Position::from_fen(STARTING_FEN);
+        let mv = position.find_algebraic_move("e2e4").unwrap();
+        assert_eq!(bestmove(Some(mv), false), r#"{"type":"bestmove","move":"e2e4"}"#);
+        assert_eq!(bestmove(None, false), r#"{"type":"bestmove","move":null}"#);
+
+        let castle = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").find_algebraic_move("e1g1").unwrap();
+        assert_eq!(bestmove(Some(castle), true), r#"{"type":"bestmove","move":"e1h1"}"#);
+
+        let mut pv = [None; MAX_DEPTH];
+        pv[0] = Some(mv);
+        let info = FullInfo { depth: 3, seldepth: 5, score: -20, nodes: 500, time: 0, pv };
+        assert_eq!(
+            full_info(&info, false),
+            r#"{"type":"info","depth":3,"seldepth":5,"score":{"cp":-20},"nodes":500,"nps":500000,"time":0,"pv":["e2e4"]}"#
+        );
+
+        let terms = eval_terms(&position);
+        assert_eq!(terms.mg[White], terms.mg[Black]);
+        assert!(eval(&terms, 0).starts_with(r#"{"type":"eval","score":0,"phase":24,"#));
+    }
+}
@@ -0,0 +1,73 @@
+// Kogge-Stone sliding attacks: the slider set is flood filled through empty squares in each
+// direction using parallel prefix shifts, so no attack tables are needed at all.
+
+use crate::bitboard::{Bitboard, Square};
+
+const NOT_A_FILE: u64 = !Bitboard::A_FILE.0;
+const NOT_H_FILE: u64 = !Bitboard::H_FILE.0;
+
+// a8 is bit 0, so moving north is a right shift and moving east is a left shift
+const fn fill_left(mut gen: u64, mut pro: u64, shift: u32, wrap: u64) -> u64 {
+    pro &= wrap;
+    gen |= pro & (gen << shift);
+    pro &= pro << shift;
+    gen |= pro & (gen << (2 * shift));
+    pro &= pro << (2 * shift);
+    gen |= pro & (gen << (4 * shift));
+    (gen << shift) & wrap
+}
+
+const fn fill_right(mut gen: u64, mut pro: u64, shift: u32, wrap: u64) -> u64 {
+    pro &= wrap;
+    gen |= pro & (gen >> shift);
+    pro &= pro >> shift;
+    gen |= pro & (gen >> (2 * shift));
+    pro &= pro >> (2 * shift);
+    gen |= pro & (gen >> (4 * shift));
+    (gen >> shift) & wrap
+}
+
+pub fn rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    let gen = Bitboard::from(sq).0;
+    let empty = !occ.0;
+
+    Bitboard(
+        fill_right(gen, empty, 8, !0)
+            | fill_left(gen, empty, 8, !0)
+            | fill_left(gen, empty, 1, NOT_A_FILE)
+            | fill_right(gen, empty, 1, NOT_H_FILE),
+    )
+}
+
+pub fn bishop_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    let gen = Bitboard::from(sq).0;
+    let empty = !occ.0;
+
+    Bitboard(
+        fill_right(gen, empty, 7, NOT_A_FILE)
+            | fill_right(gen, empty, 9, NOT_H_FILE)
+            | fill_left(gen, empty, 9, NOT_A_FILE)
+            | fill_left(gen, empty, 7, NOT_H_FILE),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use num::FromPrimitive;
+
+    use super::*;
+    use crate::magic::XorShift;
+
+    #[test]
+    fn kogge_stone_matches_ray_attacks() {
+        let mut prng = XorShift::new();
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+            for _ in 0..1000 {
+                let occ = Bitboard(prng.gen_next() & prng.gen_next());
+                assert_eq!(rook_attacks(sq, occ), crate::magic::rook_attacks(sq, occ));
+                assert_eq!(bishop_attacks(sq, occ), crate::magic::bishop_attacks(sq, occ));
+            }
+        }
+    }
+}
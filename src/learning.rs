@@ -0,0 +1,149 @@
+// What the engine has learned about positions it searched in earlier games: the move it chose,
+// its score and how deep the search got. Kept in a text file, one position per line, so it
+// lasts between sessions and can be read or trimmed by hand. Positions are keyed by their
+// polyglot key, which unlike the zobrist hash doesn't change with the engine's version.
+//
+// A deeper search of the same position is trusted over a shallower one, so when a search
+// doesn't get as deep as one already learned, the learned move is played instead.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use log::warn;
+
+use crate::{movegen::Move, position::Position};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LearnEntry {
+    // in UCI notation, found again in the position when it's used
+    pub mv: String,
+    pub score: i32,
+    pub depth: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct Learning {
+    // none learns for this session only
+    path: Option<String>,
+    entries: HashMap<u64, LearnEntry>,
+}
+
+impl Learning {
+    // a file that doesn't exist yet is an empty one, it's created on the first save
+    pub fn open(path: &str) -> io::Result<Learning> {
+        let contents = if Path::new(path).exists() { fs::read_to_string(path)? } else { String::new() };
+        let mut learning = Learning::from_text(&contents);
+        learning.path = Some(path.to_string());
+        Ok(learning)
+    }
+
+    // lines that don't parse are skipped, as are shallower duplicates
+    pub fn from_text(contents: &str) -> Learning {
+        let mut learning = Learning::default();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match parse_line(line) {
+                Some((key, entry)) => learning.insert(key, entry),
+                None => warn!("skipping learning entry {}", line),
+            }
+        }
+        learning
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, position: &Position) -> Option<&LearnEntry> {
+        self.entries.get(&position.polyglot_key())
+    }
+
+    // the learned move if it's legal here and came from a search deeper than depth
+    pub fn better_move(&self, position: &Position, depth: u8) -> Option<Move> {
+        let entry = self.get(position).filter(|entry| entry.depth > depth)?;
+        let mv = position.find_algebraic_move(&entry.mv)?;
+        position.is_legal(mv).then_some(mv)
+    }
+
+    pub fn learn(&mut self, position: &Position, mv: Move, score: i32, depth: u8) {
+        self.insert(position.polyglot_key(), LearnEntry { mv: mv.to_string(), score, depth });
+    }
+
+    fn insert(&mut self, key: u64, entry: LearnEntry) {
+        if self.entries.get(&key).is_none_or(|learned| entry.depth >= learned.depth) {
+            self.entries.insert(key, entry);
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        // sorted so the file doesn't churn between saves
+        let mut keys: Vec<&u64> = self.entries.keys().collect();
+        keys.sort();
+
+        let mut text = String::new();
+        for key in keys {
+            let entry = &self.entries[key];
+            text += &format!("{:016x} {} {} {}\n", key, entry.mv, entry.score, entry.depth);
+        }
+        text
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => fs::write(path, self.to_text()),
+            None => Ok(()),
+        }
+    }
+}
+
+// <polyglot key in hex> <move> <score> <depth>
+fn parse_line(line: &str) -> Option<(u64, LearnEntry)> {
+    let mut fields = line.split_whitespace();
+    let key = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let mv = fields.next()?.to_string();
+    let score = fields.next()?.parse().ok()?;
+    let depth = fields.next()?.parse().ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some((key, LearnEntry { mv, score, depth }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    #[test]
+    fn learn_and_reload() {
+        let position = Position::from_fen(STARTING_FEN);
+        let e4 = position.find_algebraic_move("e2e4").unwrap();
+        let d4 = position.find_algebraic_move("d2d4").unwrap();
+
+        let mut learning = Learning::default();
+        learning.learn(&position, e4, 30, 8);
+        // shallower searches don't replace deeper ones
+        learning.learn(&position, d4, 25, 6);
+        assert_eq!(learning.get(&position), Some(&LearnEntry { mv: "e2e4".to_string(), score: 30, depth: 8 }));
+
+        assert_eq!(learning.better_move(&position, 7), Some(e4));
+        assert_eq!(learning.better_move(&position, 8), None);
+
+        let text = learning.to_text();
+        assert_eq!(text, "463b96181691fc9c e2e4 30 8\n");
+        let reloaded = Learning::from_text(&format!("{}not an entry\n", text));
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded.get(&position), learning.get(&position));
+    }
+
+    #[test]
+    fn illegal_learned_move() {
+        let position = Position::from_fen(STARTING_FEN);
+        let learning = Learning::from_text("463b96181691fc9c e2e5 30 8\n");
+        assert!(learning.get(&position).is_some());
+        assert_eq!(learning.better_move(&position, 1), None);
+    }
+}
@@ -1,13 +1,109 @@
+//! Blunderbuss is a UCI chess engine, usable as a library too.
+//!
+//! The core is the board and move generation: [`Position`] with [`Position::gen_moves`] and
+//! [`Position::make_move`], FEN, SAN and EPD in and out, and [`perft()`]. [`Game`] adds the moves
+//! played from a start position, for repetitions and outcomes. The search and evaluation build
+//! on that, and [`Engine`] drives them for the UCI front end in `main.rs`, which does nothing but
+//! pick a mode and hand it stdin. To run a search directly, [`Engine::search_blocking`] returns
+//! its result, [`Engine::start_search`] runs it in the background, and
+//! [`search::iterative_deepening`] reports to a [`search::SearchListener`] as it goes.
+//!
+//! The types most callers need are re-exported here.
+//!
+//! Features decide how much of that gets built. The default `uci` is everything, the engine and
+//! the tools built on it. `search` is the search without the engine, for running it directly.
+//! `std` is only the core, without the channels and randomness the rest needs, and with no
+//! features at all the core builds on `no_std` with `alloc`. `lichess` adds a bot that plays on
+//! lichess.org, which needs an HTTPS client, so it's left out by default.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+// the parts of std's prelude the core uses, so it reads the same with or without std
+mod prelude {
+    pub use alloc::{
+        boxed::Box,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+}
+
+pub mod attacks;
 pub mod bitboard;
+pub mod cpu;
 pub mod movegen;
 pub mod position;
+pub mod draw;
+pub mod material;
+pub mod outcome;
+pub mod validate;
+pub mod diff;
 pub mod fen;
+pub mod chess960;
+pub mod epd;
+pub mod san;
 pub mod make_move;
 pub mod perft;
+#[cfg(feature = "uci")]
+pub mod logging;
+#[cfg(feature = "uci")]
+pub mod json;
+#[cfg(feature = "uci")]
 pub mod interface;
+#[cfg(feature = "uci")]
+pub mod options;
 pub mod eval;
+#[cfg(feature = "search")]
+pub mod phases;
+#[cfg(feature = "search")]
 pub mod search;
+#[cfg(feature = "search")]
+pub mod tt;
+#[cfg(feature = "uci")]
 pub mod engine;
+pub mod game;
 pub mod zobrist;
+#[cfg(any(test, feature = "proptest"))]
+pub mod arbitrary;
+#[cfg(feature = "uci")]
 pub mod benchmark;
+#[cfg(feature = "uci")]
+pub mod solve;
+#[cfg(feature = "std")]
+pub mod pgn;
+#[cfg(feature = "uci")]
+pub mod polyglot;
+#[cfg(feature = "uci")]
+pub mod learning;
+#[cfg(feature = "lichess")]
+pub mod lichess;
+#[cfg(feature = "uci")]
+pub mod selfplay;
+#[cfg(feature = "uci")]
+pub mod matchplay;
+#[cfg(feature = "uci")]
+pub mod uci_engine;
+#[cfg(feature = "uci")]
+pub mod server;
 pub mod magic;
+pub mod simd;
+#[cfg(feature = "std")]
+pub mod genmagics;
+#[cfg(feature = "black-magic")]
+pub mod black_magic;
+#[cfg(feature = "kogge-stone")]
+pub mod kogge_stone;
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+pub mod pext;
+
+pub use bitboard::{Bitboard, Square};
+#[cfg(feature = "uci")]
+pub use engine::Engine;
+pub use fen::STARTING_FEN;
+pub use game::Game;
+pub use movegen::Move;
+pub use perft::{perft, PerftResult};
+pub use position::{Colour, Piece, Position};
@@ -0,0 +1,316 @@
+// Plays on lichess.org as a bot account through its Bot API, so the engine can play online without
+// a bridge program. Challenges are accepted when they're a variant and time control the engine can
+// play and there's a game free, and each game is played on its own thread, searching with the time
+// its clock allows. The account's API token is read from LICHESS_TOKEN rather than the command
+// line, where other users could see it.
+// https://lichess.org/api#tag/Bot
+
+use std::{
+    collections::HashSet,
+    error::Error,
+    io::{BufRead, BufReader},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use log::{error, info, warn};
+use serde_json::Value;
+
+use crate::{
+    engine::calculate_allowed_time,
+    fen::STARTING_FEN,
+    game::{apply_uci_moves, Game},
+    position::{Colour, Position},
+    search::{search_blocking, SearchLimits, MAX_DEPTH},
+    tt::{TranspositionTable, DEFAULT_HASH_MB},
+};
+
+pub const DEFAULT_URL: &str = "https://lichess.org";
+pub const TOKEN_VAR: &str = "LICHESS_TOKEN";
+
+const VARIANTS: [&str; 3] = ["standard", "chess960", "fromPosition"];
+
+#[derive(Debug)]
+pub struct LichessOptions {
+    pub token: String,
+    pub url: String,
+    // games played at once, challenges past it are declined until one finishes
+    pub max_games: usize,
+}
+
+impl LichessOptions {
+    pub fn new(token: String) -> Self {
+        Self { token, url: DEFAULT_URL.to_string(), max_games: 1 }
+    }
+
+    // --games <n> and --url <url>, for a lichess instance other than lichess.org
+    pub fn parse(token: String, args: &[String]) -> Result<Self, String> {
+        let mut options = LichessOptions::new(token);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--games" => options.max_games = value?.parse().map_err(|e| format!("invalid value for {}: {}", arg, e))?,
+                "--url" => options.url = value?.to_string(),
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+#[derive(Clone)]
+struct Client {
+    agent: ureq::Agent,
+    url: String,
+    token: String,
+}
+
+impl Client {
+    fn new(options: &LichessOptions) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new().build(),
+            url: options.url.trim_end_matches('/').to_string(),
+            token: options.token.clone(),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<ureq::Response, Box<dyn Error + Send + Sync>> {
+        let request = self.agent.get(&format!("{}{}", self.url, path));
+        Ok(request.set("Authorization", &format!("Bearer {}", self.token)).call()?)
+    }
+
+    fn post(&self, path: &str, form: &[(&str, &str)]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let request = self.agent.post(&format!("{}{}", self.url, path));
+        request.set("Authorization", &format!("Bearer {}", self.token)).send_form(form)?;
+        Ok(())
+    }
+
+    // newline delimited JSON, with the empty lines lichess sends to keep the connection open
+    // skipped. it ends when lichess closes the stream
+    fn stream(&self, path: &str) -> Result<impl Iterator<Item = Value>, Box<dyn Error + Send + Sync>> {
+        let reader = BufReader::new(self.get(path)?.into_reader());
+        Ok(reader.lines().map_while(Result::ok).filter(|line| !line.trim().is_empty()).filter_map(|line| {
+            serde_json::from_str(&line).map_err(|e| warn!("parsing {}: {}", line, e)).ok()
+        }))
+    }
+}
+
+// streams events until lichess closes the connection, playing every game that starts, then
+// finishes the games still going
+pub fn run(options: &LichessOptions) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let client = Client::new(options);
+    let account: Value = serde_json::from_reader(client.get("/api/account")?.into_reader())?;
+    let bot = account["id"].as_str().ok_or("no account id")?.to_string();
+    info!("playing on {} as {}", client.url, bot);
+
+    let playing = Arc::new(AtomicUsize::new(0));
+    // accepted challenges whose games haven't started, which count towards max_games too. a
+    // challenge's game has the challenge's id
+    let mut accepted = HashSet::new();
+    let mut games = Vec::new();
+    for event in client.stream("/api/stream/event")? {
+        match event["type"].as_str() {
+            Some("challenge") => {
+                let challenge = &event["challenge"];
+                let id = challenge["id"].as_str().unwrap_or_default();
+                let busy = playing.load(Ordering::SeqCst) + accepted.len();
+                let result = match respond(challenge, &bot, busy, options.max_games) {
+                    Response::Accept => {
+                        accepted.insert(id.to_string());
+                        client.post(&format!("/api/challenge/{}/accept", id), &[])
+                    }
+                    Response::Decline(reason) => {
+                        client.post(&format!("/api/challenge/{}/decline", id), &[("reason", reason)])
+                    }
+                    Response::Ignore => Ok(()),
+                };
+                if let Err(e) = result {
+                    error!("answering challenge {}: {}", id, e);
+                    accepted.remove(id);
+                }
+            }
+            Some("challengeCanceled") => _ = accepted.remove(event["challenge"]["id"].as_str().unwrap_or_default()),
+            // also sent for games still going when the stream opens, so they're picked up again
+            Some("gameStart") => {
+                let Some(id) = event["game"]["gameId"].as_str().map(str::to_string) else {
+                    continue;
+                };
+                accepted.remove(&id);
+                let (client, bot, playing) = (client.clone(), bot.clone(), playing.clone());
+                playing.fetch_add(1, Ordering::SeqCst);
+                games.push(thread::spawn(move || {
+                    if let Err(e) = play(&client, &bot, &id) {
+                        error!("game {}: {}", id, e);
+                    }
+                    playing.fetch_sub(1, Ordering::SeqCst);
+                }));
+            }
+            _ => (),
+        }
+    }
+
+    for game in games {
+        _ = game.join();
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Response {
+    Accept,
+    // with one of lichess's decline reasons
+    Decline(&'static str),
+    // our own challenges to others come through the stream too
+    Ignore,
+}
+
+fn respond(challenge: &Value, bot: &str, playing: usize, max_games: usize) -> Response {
+    if challenge["challenger"]["id"].as_str() == Some(bot) {
+        return Response::Ignore;
+    }
+    if !VARIANTS.contains(&challenge["variant"]["key"].as_str().unwrap_or_default()) {
+        return Response::Decline("variant");
+    }
+    // games without a clock could go on forever
+    if challenge["timeControl"]["type"].as_str() != Some("clock") {
+        return Response::Decline("timeControl");
+    }
+    if playing >= max_games {
+        return Response::Decline("later");
+    }
+
+    Response::Accept
+}
+
+// everything needed to move from the start of a game, taken from its gameFull event
+#[derive(Debug)]
+struct GameInfo {
+    start: Position,
+    colour: Colour,
+    chess960: bool,
+}
+
+impl GameInfo {
+    fn parse(full: &Value, bot: &str) -> Result<GameInfo, Box<dyn Error + Send + Sync>> {
+        let start = match full["initialFen"].as_str() {
+            Some("startpos") | None => Position::from_fen(STARTING_FEN),
+            Some(fen) => Position::try_from_fen(fen)?,
+        };
+        let colour = if full["white"]["id"].as_str() == Some(bot) { Colour::White } else { Colour::Black };
+        let chess960 = full["variant"]["key"].as_str() == Some("chess960");
+        Ok(GameInfo { start, colour, chess960 })
+    }
+
+    // the game so far from a gameState event, none once it's over
+    fn game(&self, state: &Value) -> Result<Option<Game>, Box<dyn Error + Send + Sync>> {
+        if state["status"].as_str() != Some("started") {
+            return Ok(None);
+        }
+        let moves = state["moves"].as_str().unwrap_or_default();
+        Ok(Some(apply_uci_moves(self.start, moves.split_whitespace())?))
+    }
+
+    // a search limited to the time this move can have, on clocks lichess gives in milliseconds
+    fn limits(&self, state: &Value) -> SearchLimits {
+        let clock = |field: &str| state[field].as_u64().unwrap_or_default().min(u32::MAX as u64) as u32;
+        let (time, inc) = match self.colour {
+            Colour::White => (clock("wtime"), clock("winc")),
+            Colour::Black => (clock("btime"), clock("binc")),
+        };
+        SearchLimits { nodes: u32::MAX, depth: MAX_DEPTH as u8, movetime: calculate_allowed_time(time, inc, 0).max(1) }
+    }
+}
+
+fn play(client: &Client, bot: &str, id: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    info!("game {} started", id);
+    // kept for the whole game, each move's search starting from what the last found
+    let tt = TranspositionTable::new(DEFAULT_HASH_MB);
+    let mut info = None;
+    for event in client.stream(&format!("/api/bot/game/stream/{}", id))? {
+        let state = match event["type"].as_str() {
+            Some("gameFull") => {
+                info = Some(GameInfo::parse(&event, bot)?);
+                &event["state"]
+            }
+            Some("gameState") => &event,
+            _ => continue,
+        };
+        let Some(info) = &info else {
+            continue;
+        };
+
+        let Some(game) = info.game(state)? else {
+            break;
+        };
+        if game.position().turn != info.colour {
+            continue;
+        }
+        let result = search_blocking(&game, info.limits(state), &tt, false);
+        let Some(mv) = result.best_move else {
+            continue;
+        };
+        // lichess rejects a move sent again for a repeated state, like one sent with a draw offer,
+        // and the game goes on either way
+        if let Err(e) = client.post(&format!("/api/bot/game/{}/move/{}", id, mv.to_uci(info.chess960)), &[]) {
+            warn!("game {}: sending move {}: {}", id, mv, e);
+        }
+    }
+
+    info!("game {} over", id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn challenges() {
+        let challenge = |challenger: &str, variant: &str, time_control: &str| {
+            json!({
+                "challenger": { "id": challenger },
+                "variant": { "key": variant },
+                "timeControl": { "type": time_control },
+            })
+        };
+        assert_eq!(respond(&challenge("human", "standard", "clock"), "bot", 0, 1), Response::Accept);
+        assert_eq!(respond(&challenge("human", "chess960", "clock"), "bot", 1, 2), Response::Accept);
+        assert_eq!(respond(&challenge("bot", "standard", "clock"), "bot", 0, 1), Response::Ignore);
+        assert_eq!(respond(&challenge("human", "atomic", "clock"), "bot", 0, 1), Response::Decline("variant"));
+        assert_eq!(respond(&challenge("human", "standard", "unlimited"), "bot", 0, 1), Response::Decline("timeControl"));
+        assert_eq!(respond(&challenge("human", "standard", "clock"), "bot", 1, 1), Response::Decline("later"));
+    }
+
+    #[test]
+    fn game_state() {
+        let full = json!({
+            "type": "gameFull",
+            "variant": { "key": "chess960" },
+            "white": { "id": "human" },
+            "black": { "id": "bot" },
+            "initialFen": "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            "state": { "moves": "e2e4", "wtime": 60000, "btime": 30000, "winc": 0, "binc": 1000, "status": "started" },
+        });
+        let info = GameInfo::parse(&full, "bot").unwrap();
+        assert_eq!(info.colour, Colour::Black);
+        assert!(info.chess960);
+
+        let game = info.game(&full["state"]).unwrap().unwrap();
+        assert_eq!(game.position().turn, Colour::Black);
+        assert_eq!(info.limits(&full["state"]).movetime, calculate_allowed_time(30000, 1000, 0));
+        assert!(info.game(&json!({ "moves": "e2e4", "status": "mate" })).unwrap().is_none());
+        assert!(info.game(&json!({ "moves": "e2e5", "status": "started" })).is_err());
+
+        // black castles short, king takes rook
+        let position = Position::from_fen("bqnbrk1r/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR b HFh - 3 9");
+        let castle = position.find_algebraic_move("f8h8").unwrap();
+        assert_eq!(castle.to_uci(true), "f8h8");
+        assert_eq!(castle.to_uci(false), "f8g8");
+    }
+}
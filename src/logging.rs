@@ -0,0 +1,200 @@
+// Everything sent to the GUI goes through send! to the engine's Output, stdout unless it was given
+// another writer, and is mirrored to the Debug Log File along with everything received, so
+// communication problems can be looked at after the fact.
+//
+// Diagnostics go through the log crate instead. The binary installs the logger here, which
+// writes them to the GUI as info strings, to stderr or to a file. Library users can install their
+// own logger to capture them, or none to keep quiet.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+// where the logger sends info strings to, the running engine's output
+static LOG_OUTPUT: Mutex<Option<Output>> = Mutex::new(None);
+static LOG_TARGET: Mutex<LogTarget> = Mutex::new(LogTarget::Uci);
+static LOGGER: Logger = Logger;
+
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Warn;
+
+enum LogTarget {
+    Uci,
+    Stderr,
+    File(File),
+}
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let level = record.level().to_string().to_lowercase();
+        match &mut *LOG_TARGET.lock().unwrap() {
+            LogTarget::Uci => {
+                let output = LOG_OUTPUT.lock().unwrap().clone().unwrap_or_default();
+                output.send(&format!("info string {}: {}", level, record.args()));
+            }
+            LogTarget::Stderr => eprintln!("{} {} {}", timestamp(), level, record.args()),
+            LogTarget::File(file) => _ = writeln!(file, "{} {} {}", timestamp(), level, record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// only the first call installs the logger, later ones just change the level
+pub fn init_logger(level: LevelFilter) {
+    _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
+
+// uci for info strings, stderr, or a file path to append to
+pub fn set_log_target(target: &str) -> io::Result<()> {
+    let target = match target {
+        "uci" => LogTarget::Uci,
+        "stderr" => LogTarget::Stderr,
+        path => LogTarget::File(OpenOptions::new().create(true).append(true).open(path)?),
+    };
+    *LOG_TARGET.lock().unwrap() = target;
+    Ok(())
+}
+
+// where info strings logged with the uci target go, none for stdout
+pub fn set_log_output(output: Option<Output>) {
+    *LOG_OUTPUT.lock().unwrap() = output;
+}
+
+#[macro_export]
+macro_rules! send {
+    ($output:expr, $($arg:tt)*) => {
+        $output.send(&format!($($arg)*))
+    };
+}
+
+// where an engine's protocol messages go. clones share the writer, so the search's reports and
+// everything else for one GUI arrive through the same one in order
+#[derive(Clone)]
+pub struct Output {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Output {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self { writer: Arc::new(Mutex::new(Box::new(writer))) }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    // each message goes out in a single write and is flushed straight away, so a GUI reading a
+    // pipe never sees half of one. a closed stdout isn't worth panicking over, the engine quits at
+    // EOF
+    pub fn send(&self, text: &str) {
+        let mut message = String::with_capacity(text.len() + 1);
+        message.push_str(text);
+        message.push('\n');
+
+        let mut writer = self.writer.lock().unwrap();
+        _ = writer.write_all(message.as_bytes());
+        _ = writer.flush();
+        drop(writer);
+
+        log(">>", text);
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::stdout()
+    }
+}
+
+// appends to the file, an empty path stops logging
+pub fn set_log_file(path: &str) -> io::Result<()> {
+    let file = match path {
+        "" => None,
+        _ => Some(OpenOptions::new().create(true).append(true).open(path)?),
+    };
+    *LOG_FILE.lock().unwrap() = file;
+    Ok(())
+}
+
+pub fn received(text: &str) {
+    log("<<", text);
+}
+
+fn log(direction: &str, text: &str) {
+    let mut file = LOG_FILE.lock().unwrap();
+    let Some(file) = file.as_mut() else {
+        return;
+    };
+
+    let time = timestamp();
+    for line in text.lines() {
+        // a failed write isn't worth interrupting the engine for
+        _ = writeln!(file, "{} {} {}", time, direction, line);
+    }
+}
+
+// seconds since the epoch, to the millisecond
+fn timestamp() -> String {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:03}", time.as_secs(), time.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    // other tests send through the same log while this one runs, so it only looks for its own lines
+    #[test]
+    fn log_test() {
+        let id = format!("log-test-{}", std::process::id());
+        let path = env::temp_dir().join(format!("blunderbuss-{}.txt", id));
+        let path = path.to_str().unwrap();
+        set_log_file(path).unwrap();
+        let output = Output::new(io::sink());
+        received(&format!("isready {}", id));
+        send!(output, "readyok {}", id);
+        set_log_file("").unwrap();
+        send!(output, "not logged {}", id);
+
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let ours: Vec<&str> = contents.lines().filter(|line| line.ends_with(&format!(" {}", id))).collect();
+        assert_eq!(ours.len(), 2);
+        assert!(ours[0].ends_with(&format!(" << isready {}", id)));
+        assert!(ours[1].ends_with(&format!(" >> readyok {}", id)));
+    }
+
+    #[test]
+    fn logger_test() {
+        let path = env::temp_dir().join(format!("blunderbuss-logger-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        init_logger(LevelFilter::Info);
+        set_log_target(path).unwrap();
+        log::info!("logged");
+        log::debug!("filtered out");
+        set_log_target("uci").unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(contents.lines().any(|line| line.ends_with(" info logged")));
+        assert!(!contents.contains("filtered out"));
+    }
+}
@@ -1,81 +1,148 @@
-use std::time::Instant;
+use spin::Once;
+#[cfg(feature = "std")]
+use std::thread;
 
-use num::{signum, FromPrimitive};
-use lazy_static::lazy_static;
-use crate::bitboard::{Bitboard, Square};
+use num::FromPrimitive;
+use crate::prelude::*;
+use crate::bitboard::{Bitboard, File, Rank, Square};
 
-lazy_static! {
-    pub static ref MAGICS: Magics = Magics::init();
+static MAGICS: Once<Magics> = Once::new();
+
+pub fn magics() -> &'static Magics {
+    MAGICS.call_once(Magics::init)
 }
 
-const SEED: u64 = 18401105770426537108;
-const MAX_ROOK_BITS: usize = 1 << 12;
-const MAX_BISHOP_BITS: usize = 1 << 9;
+pub(crate) const SEED: u64 = 18401105770426537108;
+const EDGE_RANKS: [Rank; 2] = [Rank::R8, Rank::R1];
+const EDGE_FILES: [File; 2] = [File::A, File::H];
 
 pub struct Magics {
     pub rook_magics: Box<[Magic]>,
     pub bishop_magics: Box<[Magic]>,
-    pub rook_attacks: Vec<Vec<Bitboard>>,
-    pub bishop_attacks: Vec<Vec<Bitboard>>,
+    // rook and bishop attacks for every square packed together, indexed from Magic::offset
+    pub attacks: Vec<Bitboard>,
 }
 
 impl Magics {
+    // builds the attack tables from the hardcoded magics, regenerate these with gen_rook_magics
+    // and gen_bishop_magics if the table layout changes
     fn init() -> Self {
-        let (rook_attacks, rook_magics) = gen_rook_magics();
-        let (bishop_attacks, bishop_magics) = gen_bishop_magics();
+        let mut rook_magics = [Magic::new(); 64];
+        let mut bishop_magics = [Magic::new(); 64];
+
+        let table_size: usize = ROOK_BITS.iter().chain(BISHOP_BITS.iter()).map(|bits| 1 << bits).sum();
+        let mut attacks = vec![Bitboard(0); table_size];
+        let mut offset = 0;
+
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+
+            let rook = Magic { mask: rook_attacks_mask(sq), magic: ROOK_MAGICS[i], offset };
+            offset += 1 << ROOK_BITS[sq];
+            fill_attacks(sq, rook, ROOK_BITS[sq], rook_attacks, &mut attacks[rook.offset..offset]);
+            rook_magics[i] = rook;
+
+            let bishop = Magic { mask: bishop_attacks_mask(sq), magic: BISHOP_MAGICS[i], offset };
+            offset += 1 << BISHOP_BITS[sq];
+            fill_attacks(sq, bishop, BISHOP_BITS[sq], bishop_attacks, &mut attacks[bishop.offset..offset]);
+            bishop_magics[i] = bishop;
+        }
+
         Magics {
             rook_magics: Box::new(rook_magics),
             bishop_magics: Box::new(bishop_magics),
-            rook_attacks,
-            bishop_attacks
+            attacks,
         }
     }
 }
 
+fn fill_attacks(
+    sq: Square,
+    magic: Magic,
+    bits: u8,
+    attacks: fn(Square, Bitboard) -> Bitboard,
+    table: &mut [Bitboard],
+) {
+    for occupancy in magic.mask.subsets() {
+        let (mul, _) = occupancy.0.overflowing_mul(magic.magic);
+        table[(mul >> (64 - bits)) as usize] = attacks(sq, occupancy);
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Magic {
     pub mask: Bitboard,
     pub magic: u64,
+    pub offset: usize,
 }
 
 impl Magic {
     fn new() -> Self {
-        Magic {mask: Bitboard(0), magic: 0}
+        Magic {mask: Bitboard(0), magic: 0, offset: 0}
     }
 }
 
-fn gen_rook_magics() -> (Vec<Vec<Bitboard>>, [Magic; 64]) {
-    let mut magics = [Magic::new(); 64];
-    let mut attacks = vec![vec![Bitboard(0); MAX_ROOK_BITS]; 64];
-    for i in 0..64 {
-        let sq = Square::from_usize(i).unwrap();
-        (attacks[i], magics[i]) = find_magic_number_rook(sq, SEED);
-    }
+#[cfg(feature = "std")]
+pub fn gen_rook_magics() -> (Vec<Vec<Bitboard>>, [Magic; 64]) {
+    gen_magics(find_magic_number_rook)
+}
 
-    (attacks, magics)
+#[cfg(feature = "std")]
+pub fn gen_bishop_magics() -> (Vec<Vec<Bitboard>>, [Magic; 64]) {
+    gen_magics(find_magic_number_bishop)
 }
 
-fn gen_bishop_magics() -> (Vec<Vec<Bitboard>>, [Magic; 64]) {
+#[cfg(feature = "std")]
+fn gen_magics(find_magic: fn(Square, u64) -> (Vec<Bitboard>, Magic)) -> (Vec<Vec<Bitboard>>, [Magic; 64]) {
     let mut magics = [Magic::new(); 64];
-    let mut attacks = vec![vec![Bitboard(0); MAX_BISHOP_BITS]; 64];
-    for i in 0..64 {
-        let sq = Square::from_usize(i).unwrap();
-        (attacks[i], magics[i]) = find_magic_number_bishop(sq, SEED);
+    let mut attacks = vec![Vec::new(); 64];
+
+    for (i, found) in par_squares(|sq| find_magic(sq, SEED)).into_iter().enumerate() {
+        (attacks[i], magics[i]) = found;
     }
 
     (attacks, magics)
 }
 
-struct XorShift {
+// runs f for every square in parallel, each worker thread taking an interleaved share of the board
+#[cfg(feature = "std")]
+pub fn par_squares<T: Send>(f: impl Fn(Square) -> T + Sync) -> Vec<T> {
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get()).min(64);
+    let mut results: Vec<Option<T>> = (0..64).map(|_| None).collect();
+
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..n_threads)
+            .map(|t| {
+                let f = &f;
+                s.spawn(move || {
+                    (t..64)
+                        .step_by(n_threads)
+                        .map(|i| (i, f(Square::from_usize(i).unwrap())))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for (i, found) in worker.join().unwrap() {
+                results[i] = Some(found);
+            }
+        }
+    });
+
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+pub(crate) struct XorShift {
     pub state: u64
 }
 
 impl XorShift {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         XorShift { state: SEED }
     }
 
-    fn gen_next(&mut self) -> u64 {
+    pub(crate) fn gen_next(&mut self) -> u64 {
         let mut x = self.state;
         x ^= x << 13;
         x ^= x >> 7;
@@ -84,7 +151,7 @@ impl XorShift {
         x
     }
 
-    fn gen_magic(&mut self) -> u64 {
+    pub(crate) fn gen_magic(&mut self) -> u64 {
         let mut magic = self.gen_next();
         // magic numbers with few 1 bits are more likely to be successful
         for _ in 0..2 {
@@ -101,15 +168,15 @@ pub fn bishop_attacks(from_sq: Square, blockers: Bitboard) -> Bitboard {
     let directions = [9, 7, -9, -7];
     for direction in directions {
         let mut to_sq = from_sq.add(direction);
-        let mut prev_rank = from_sq.rank();
+        let mut prev_sq = from_sq;
         while let Some(sq) = to_sq {
-            // break if rank hasn't changed by 1 to handle edge wraps
-            if sq.rank() - prev_rank != signum(direction) {
+            // break if not a single diagonal step to handle edge wraps
+            if Square::distance(prev_sq, sq) != 1 {
                 break;
             }
             attacks.set(sq);
             to_sq = sq.add(direction);
-            prev_rank = sq.rank();
+            prev_sq = sq;
 
             if blockers.is_set(sq) {
                 break;
@@ -148,18 +215,18 @@ pub fn bishop_attacks_mask(from_sq: Square) -> Bitboard {
     let directions = [9, 7, -9, -7];
     for direction in directions {
         let mut to_sq = from_sq.add(direction);
-        let mut prev_rank = from_sq.rank();
+        let mut prev_sq = from_sq;
         while let Some(sq) = to_sq {
-            // break if rank hasn't changed by 1 to handle edge wraps
-            if sq.rank() - prev_rank != signum(direction) {
+            // break if not a single diagonal step to handle edge wraps
+            if Square::distance(prev_sq, sq) != 1 {
                 break;
             }
-            if [0, 7].contains(&sq.rank()) || [0, 7].contains(&sq.file()) {
+            if EDGE_RANKS.contains(&sq.rank()) || EDGE_FILES.contains(&sq.file()) {
                 break;
             }
             attacks.set(sq);
             to_sq = sq.add(direction);
-            prev_rank = sq.rank();
+            prev_sq = sq;
         }
     }
 
@@ -178,8 +245,8 @@ pub fn rook_attacks_mask(from_sq: Square) -> Bitboard {
                 break;
             }
             // break if on eadge
-            if ([8, -8].contains(&direction) && [0, 7].contains(&sq.rank())) 
-            || ([1, -1].contains(&direction) && [0, 7].contains(&sq.file())) {
+            if ([8, -8].contains(&direction) && EDGE_RANKS.contains(&sq.rank()))
+            || ([1, -1].contains(&direction) && EDGE_FILES.contains(&sq.file())) {
                 break;
             }
             attacks.set(sq);
@@ -190,32 +257,80 @@ pub fn rook_attacks_mask(from_sq: Square) -> Bitboard {
     attacks
 }
 
-fn gen_occupancy(index: usize, mask: Bitboard) -> Bitboard {
-    let mut occupancy = Bitboard(0);
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Slider {
+    Rook,
+    Bishop,
+}
 
-    for (n, sq) in mask.enumerate() {
-        if index & 1 << n != 0 {
-            occupancy.set(sq);
-        } 
+impl Slider {
+    pub fn mask(self, sq: Square) -> Bitboard {
+        match self {
+            Slider::Rook => rook_attacks_mask(sq),
+            Slider::Bishop => bishop_attacks_mask(sq),
+        }
     }
 
-    occupancy
+    pub fn attacks(self, sq: Square, blockers: Bitboard) -> Bitboard {
+        match self {
+            Slider::Rook => rook_attacks(sq, blockers),
+            Slider::Bishop => bishop_attacks(sq, blockers),
+        }
+    }
+
+    // index bits used by the hardcoded magics
+    pub fn bits(self, sq: Square) -> u8 {
+        match self {
+            Slider::Rook => ROOK_BITS[sq],
+            Slider::Bishop => BISHOP_BITS[sq],
+        }
+    }
 }
 
 pub fn find_magic_number_rook(sq: Square, seed: u64) -> (Vec<Bitboard>, Magic) {
-    let mask = rook_attacks_mask(sq);
-    let mut occupancies = [Bitboard(0); MAX_ROOK_BITS];
-    let mut attacks_by_occupancy = [Bitboard(0); MAX_ROOK_BITS];
-
-    let num_bit_combinations = 1 << mask.count_ones();
-    for i in 0..num_bit_combinations {
-        occupancies[i] = gen_occupancy(i, mask);
-        attacks_by_occupancy[i] = rook_attacks(sq, occupancies[i]);
-    }
+    let found = find_magic(sq, Slider::Rook, ROOK_BITS[sq], seed, None).unwrap();
+    (found.attacks, Magic { mask: rook_attacks_mask(sq), magic: found.magic, offset: 0 })
+}
+
+pub fn find_magic_number_bishop(sq: Square, seed: u64) -> (Vec<Bitboard>, Magic) {
+    let found = find_magic(sq, Slider::Bishop, BISHOP_BITS[sq], seed, None).unwrap();
+    (found.attacks, Magic { mask: bishop_attacks_mask(sq), magic: found.magic, offset: 0 })
+}
+
+pub struct FoundMagic {
+    pub attacks: Vec<Bitboard>,
+    pub magic: u64,
+    pub bits: u8,
+    // candidates tried before this one succeeded
+    pub attempts: u64,
+}
+
+// searches for a magic indexing every occupancy of the mask into a table of 2^bits entries,
+// giving up after max_attempts candidates if a limit is set
+pub fn find_magic(
+    sq: Square,
+    slider: Slider,
+    bits: u8,
+    seed: u64,
+    max_attempts: Option<u64>,
+) -> Option<FoundMagic> {
+    let mask = slider.mask(sq);
+    let occupancies: Vec<Bitboard> = mask.subsets().collect();
+    let attacks_by_occupancy: Vec<Bitboard> = occupancies.iter().map(|occ| slider.attacks(sq, *occ)).collect();
+    // a full sized table gives every occupancy its own slot, only smaller tables rely on
+    // occupancies with the same attacks sharing one
+    let allow_shared = (bits as u32) < mask.count_ones();
 
     let mut prng = XorShift::new();
     prng.state = seed;
+    let mut attacks_by_magic = vec![Bitboard(0); 1 << bits];
+    let mut attempts = 0;
     'magic_search: loop {
+        if max_attempts.is_some_and(|max| attempts >= max) {
+            return None;
+        }
+        attempts += 1;
+
         let magic = prng.gen_magic();
 
         let (mul, _) = mask.0.overflowing_mul(magic);
@@ -223,84 +338,150 @@ pub fn find_magic_number_rook(sq: Square, seed: u64) -> (Vec<Bitboard>, Magic) {
             continue 'magic_search
         }
 
-        let mut attacks_by_magic = [Bitboard(0); MAX_ROOK_BITS];
+        attacks_by_magic.fill(Bitboard(0));
 
-        for i in 0..num_bit_combinations {
-            let (mul, _) = occupancies[i].0.overflowing_mul(magic);
-            let magic_idx = (mul >> (64 - ROOK_BITS[sq])) as usize;
+        for (occupancy, attacks) in occupancies.iter().zip(&attacks_by_occupancy) {
+            let (mul, _) = occupancy.0.overflowing_mul(magic);
+            let magic_idx = (mul >> (64 - bits)) as usize;
 
-            if !attacks_by_magic[magic_idx].is_empty() {
+            let entry = attacks_by_magic[magic_idx];
+            let fits = entry.is_empty() || (allow_shared && entry == *attacks);
+            if !fits {
                 continue 'magic_search // magic number failed to uniquely index attacks by occupancy
             }
-            attacks_by_magic[magic_idx] = attacks_by_occupancy[i];
+            attacks_by_magic[magic_idx] = *attacks;
         }
 
-        return (attacks_by_magic.to_vec(), Magic { mask, magic })
+        return Some(FoundMagic { attacks: attacks_by_magic, magic, bits, attempts })
     }
 }
 
-pub fn find_magic_number_bishop(sq: Square, seed: u64) -> (Vec<Bitboard>, Magic) {
-    let mask = bishop_attacks_mask(sq);
-    let mut occupancies = [Bitboard(0); MAX_BISHOP_BITS];
-    let mut attacks_by_occupancy = [Bitboard(0); MAX_BISHOP_BITS];
-
-    let num_bit_combinations = 1 << mask.count_ones();
-    for i in 0..num_bit_combinations {
-        occupancies[i] = gen_occupancy(i, mask);
-        attacks_by_occupancy[i] = bishop_attacks(sq, occupancies[i]);
-    }
+// finds the magic for the hardcoded bit count, then keeps shrinking the table while a magic
+// for one bit fewer turns up within the attempt budget
+pub fn find_smallest_magic(sq: Square, slider: Slider, seed: u64, min_bits_attempts: Option<u64>) -> FoundMagic {
+    let mut found = find_magic(sq, slider, slider.bits(sq), seed, None).unwrap();
 
-    let mut prng = XorShift::new();
-    prng.state = seed;
-    'magic_search: loop {
-        let magic = prng.gen_magic();
-
-        let (mul, _) = mask.0.overflowing_mul(magic);
-        if (mul & 0xFF00000000000000).count_ones() < 6 {
-            continue 'magic_search
+    if let Some(budget) = min_bits_attempts {
+        while let Some(smaller) = find_magic(sq, slider, found.bits - 1, seed, Some(budget)) {
+            found = FoundMagic { attempts: found.attempts + smaller.attempts, ..smaller };
         }
+    }
 
-        let mut attacks_by_magic = [Bitboard(0); MAX_BISHOP_BITS];
+    found
+}
 
-        for i in 0..num_bit_combinations {
-            let (mul, _) = occupancies[i].0.overflowing_mul(magic);
-            let magic_idx = (mul >> (64 - BISHOP_BITS[sq])) as usize;
+// ordered so the smallest tables win, with ties broken by how hard the magics were to find
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct SeedScore {
+    // rook and bishop table entries combined
+    pub table_size: usize,
+    // total candidates tried, a deterministic stand-in for generation time
+    pub attempts: u64,
+}
 
-            if !attacks_by_magic[magic_idx].is_empty() {
-                continue 'magic_search
-            }
-            attacks_by_magic[magic_idx] = attacks_by_occupancy[i];
+#[cfg(feature = "std")]
+pub fn score_seed(seed: u64, min_bits_attempts: Option<u64>) -> SeedScore {
+    let mut score = SeedScore { table_size: 0, attempts: 0 };
+    for i in 0..64 {
+        let sq = Square::from_usize(i).unwrap();
+        for slider in [Slider::Rook, Slider::Bishop] {
+            let found = find_smallest_magic(sq, slider, seed, min_bits_attempts);
+            score.table_size += 1 << found.bits;
+            score.attempts += found.attempts;
         }
-
-        return (attacks_by_magic.to_vec(), Magic { mask, magic })
     }
-}
 
+    score
+}
 
-pub fn find_best_seed() -> u64 {
+// scores candidates seeds from the default generator in parallel and returns the best one,
+// set min_bits_attempts to also reward seeds that shrink the tables
+#[cfg(feature = "std")]
+pub fn find_best_seed(candidates: usize, min_bits_attempts: Option<u64>) -> (u64, SeedScore) {
     let mut prng = XorShift::new();
-    let mut best_time = u128::MAX;
-    let mut best_seed = 0;
-
-    for _ in 0..20 {
-        let seed = prng.gen_next();
-        let timer = Instant::now();
-        for sq in 0..64 {
-            let (_, rook) = find_magic_number_rook(Square::from_u8(sq).unwrap(), seed);
-            let (_, bishop) = find_magic_number_bishop(Square::from_u8(sq).unwrap(), seed);
-            println!("sq {} rook {} bishop {}", sq, rook.magic, bishop.magic);
-        }
+    let seeds: Vec<u64> = (0..candidates.max(1)).map(|_| prng.gen_next()).collect();
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get()).min(seeds.len());
+
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..n_threads)
+            .map(|t| {
+                let seeds = &seeds;
+                s.spawn(move || {
+                    seeds
+                        .iter()
+                        .skip(t)
+                        .step_by(n_threads)
+                        .map(|&seed| (seed, score_seed(seed, min_bits_attempts)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .min_by_key(|(_, score)| *score)
+            .unwrap()
+    })
+}
 
-        let elapsed = timer.elapsed().as_millis();
-        if elapsed < best_time {
-            best_time = elapsed;
-            best_seed = seed;
-        }
-    } 
+// formats a table as a Rust constant in the same layout as the hardcoded tables below
+pub fn format_magics(name: &str, magics: &[u64; 64]) -> String {
+    let mut out = format!("pub const {}: [u64; 64] = [\n", name);
+    for row in magics.chunks(4) {
+        let row: Vec<String> = row.iter().map(|magic| format!("{:#018x}", magic)).collect();
+        out.push_str(&format!("    {},\n", row.join(", ")));
+    }
+    out.push_str("];\n");
+    out
+}
 
-    best_seed
+pub fn format_bits(name: &str, bits: &[u8; 64]) -> String {
+    let rows: Vec<String> = bits
+        .chunks(8)
+        .map(|row| format!("  {}", row.iter().map(u8::to_string).collect::<Vec<_>>().join(", ")))
+        .collect();
+    format!("pub const {}: [u8; 64] = [\n{}\n];\n", name, rows.join(",\n"))
 }
 
+pub const ROOK_MAGICS: [u64; 64] = [
+    0x0480004008208010, 0x10c000200040b004, 0x2100102000084100, 0x0180100048008105,
+    0x8100100801000402, 0x4200011002004844, 0x0200120008008134, 0x4100009543000022,
+    0x0028800020400080, 0x0000402000401000, 0x0022001446008020, 0x7002808008001000,
+    0xc840800800040280, 0x3020808002000400, 0x0800800100800200, 0x8042000200804411,
+    0x0404228000400080, 0x80008d0040010020, 0x2600410020041100, 0x0081030021100108,
+    0x2128004004020040, 0x3020808002000400, 0x0800040008020190, 0x10200a0003228454,
+    0x2080802280004000, 0x0000c00140201004, 0x14084022001a0080, 0x8004400a00122200,
+    0x0400040280080080, 0x0000020080040080, 0x4051022400388110, 0x0001800080004100,
+    0x1a00400020800088, 0x0000402000401000, 0x2000802002801002, 0x4401000821001004,
+    0x0848800800800400, 0x0000800200800400, 0x0ca0900204000801, 0x8c01000041000082,
+    0x8401c00280248001, 0x2040201000414000, 0x0050001020008080, 0x4083002010050008,
+    0x0020080004008080, 0x0cc0040002008080, 0x0000821028240041, 0x82201041208a0014,
+    0x1000402080011100, 0x0083822001400b80, 0x0050001020008080, 0x3006004010082200,
+    0xc840800800040280, 0x0000020080040080, 0x6000060810691400, 0x000004105b008600,
+    0x0001802110410a02, 0x0182018640210016, 0x3020200090c20901, 0x004c090090002045,
+    0x1002010810042002, 0x801a000108108402, 0x01003602a8031004, 0x0800040080410022,
+];
+
+pub const BISHOP_MAGICS: [u64; 64] = [
+    0x0482020411020203, 0x0008104122002012, 0x0004480210400048, 0x0014042281006000,
+    0x0904504000200800, 0x0028221010010425, 0x001a440a29400500, 0x400104010d280250,
+    0x0800202001210900, 0x2001601401022820, 0x0008080094008200, 0x000b044100211880,
+    0x2018011041020b00, 0x0005010421040002, 0x0040004808049006, 0x003c01008a112041,
+    0x0020001020024082, 0x001004208404a680, 0x10c0804403081100, 0x6501020804110400,
+    0x0c06000400940210, 0x0001020809080200, 0xc4041004442e0800, 0x2400a90180a80800,
+    0x0050400085480280, 0x3a10088224089880, 0x10140200010a0400, 0x0226020020088008,
+    0x0001010008104000, 0x1110008021082100, 0x80210c1402008400, 0x2040408004540400,
+    0x4050480800a08280, 0x0042021090200158, 0x0204004810890209, 0x0211200800228820,
+    0x0440020200882080, 0x003c290a000c0880, 0x020c988210028800, 0x00160c0104085240,
+    0x0042500404002043, 0x3044010482051010, 0x0001028050014100, 0x2049904208002080,
+    0x000002020a001c00, 0x0002009011000880, 0x0409504080800200, 0x0408020040441200,
+    0x001a440a29400500, 0x0220241a02108010, 0x0005010421040002, 0x0500050104090802,
+    0x8008210861010000, 0x0620881004082400, 0x8004111001010000, 0x0008104122002012,
+    0x400104010d280250, 0x003c01008a112041, 0x0004470205041610, 0x3590001242050402,
+    0x8003000050120a00, 0x40000b0408102100, 0x0800202001210900, 0x0482020411020203,
+];
+
 pub const ROOK_BITS: [u8; 64] = [
   12, 11, 11, 11, 11, 11, 11, 12,
   11, 10, 10, 10, 10, 10, 10, 11,
@@ -356,6 +537,34 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn hardcoded_magics_match_generator() {
+        let (rook_attacks, rook) = gen_rook_magics();
+        let (bishop_attacks, bishop) = gen_bishop_magics();
+        for sq in 0..64 {
+            assert_eq!(rook[sq].magic, ROOK_MAGICS[sq]);
+            assert_eq!(bishop[sq].magic, BISHOP_MAGICS[sq]);
+
+            let rook_offset = magics().rook_magics[sq].offset;
+            let rook_size = 1 << ROOK_BITS[sq];
+            assert_eq!(rook_attacks[sq][..rook_size], magics().attacks[rook_offset..rook_offset + rook_size]);
+
+            let bishop_offset = magics().bishop_magics[sq].offset;
+            let bishop_size = 1 << BISHOP_BITS[sq];
+            assert_eq!(bishop_attacks[sq][..bishop_size], magics().attacks[bishop_offset..bishop_offset + bishop_size]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn best_seed_scoring() {
+        let standard_size: usize = ROOK_BITS.iter().chain(BISHOP_BITS.iter()).map(|bits| 1 << bits).sum();
+        let (seed, score) = find_best_seed(4, None);
+        assert_eq!(score, score_seed(seed, None));
+        assert_eq!(score.table_size, standard_size);
+    }
+
     #[test]
     fn magic_rook_generation() {
         for sq in 0..64 {
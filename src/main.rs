@@ -1,12 +1,112 @@
-use blunderbuss::engine::Engine;
-use blunderbuss::zobrist::ZobristCodes;
+use std::process;
 
+use blunderbuss::{attacks, Engine, Position, STARTING_FEN};
+use blunderbuss::genmagics::{self, GenMagicsOptions};
+use blunderbuss::interface::{parse_command, write_perft_divide};
+use blunderbuss::logging::{self, Output, DEFAULT_LOG_LEVEL};
+use blunderbuss::server;
+
+const USAGE: &str = "usage: blunderbuss [bench [options] | perft <depth> [fen] | perft suite <epd> [depth] [hash mb] | solve <epd> [movetime] | \
+                     selfplay|datagen [options] | match [options] | serve [address] [--allow-files] | lichess [options] | genmagics [options]]";
+
+// with no arguments the engine reads UCI commands from stdin, otherwise it runs the given mode and exits
 fn main() {
-    let args: String = std::env::args().collect();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    logging::init_logger(DEFAULT_LOG_LEVEL);
 
-    ZobristCodes::init();
-    let mut engine = Engine::init();
-    if let Err(e) = engine.run(args) {
-        println!("{:?}", e);
+    let Some(mode) = args.first() else {
+        let mut engine = Engine::init();
+        if let Err(e) = engine.run() {
+            log::error!("{}", e);
+        }
+        return;
+    };
+
+    match mode.as_str() {
+        "genmagics" => match GenMagicsOptions::parse(&args[1..]) {
+            Ok(options) => print!("{}", genmagics::generate(&options)),
+            Err(e) => {
+                eprintln!("{}", e);
+                eprintln!("usage: blunderbuss genmagics [--seed <n> | --find-seed <candidates>] [--min-bits] [--attempts <n>]");
+                process::exit(1);
+            }
+        },
+        "serve" => {
+            let allow_files = args[1..].iter().any(|arg| arg == "--allow-files");
+            let address = args[1..].iter().find(|arg| *arg != "--allow-files");
+            let address = address.map_or(server::DEFAULT_ADDRESS, |address| address);
+            if let Err(e) = server::serve(address, allow_files) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        "lichess" => lichess(&args[1..]),
+        "perft" if args.get(1).is_none_or(|arg| arg != "suite") => perft(&args[1..]),
+        // these take the same arguments as the UCI loop commands of the same name
+        "perft" | "bench" | "solve" | "selfplay" | "datagen" | "match" => {
+            let name = if mode == "datagen" { "selfplay" } else { mode };
+            let Some(command) = parse_command(&format!("{} {}", name, args[1..].join(" "))) else {
+                usage_error();
+            };
+            let mut engine = Engine::init();
+            if let Err(e) = engine.execute(command) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        _ => usage_error(),
     }
 }
+
+fn perft(args: &[String]) {
+    let Some(depth) = args.first().and_then(|depth| depth.parse().ok()) else {
+        usage_error();
+    };
+    let fen = if args.len() > 1 { args[1..].join(" ") } else { STARTING_FEN.to_string() };
+    let mut position = match Position::try_from_fen(&fen) {
+        Ok(position) => position,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    attacks::init();
+    write_perft_divide(&Output::stdout(), &mut position, depth, 0);
+}
+
+// the bot's token comes from the environment, never the command line
+#[cfg(feature = "lichess")]
+fn lichess(args: &[String]) {
+    use blunderbuss::lichess::{self, LichessOptions, TOKEN_VAR};
+
+    let Ok(token) = std::env::var(TOKEN_VAR) else {
+        eprintln!("set {} to the bot account's API token", TOKEN_VAR);
+        process::exit(1);
+    };
+    let options = match LichessOptions::parse(token, args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("usage: blunderbuss lichess [--games <n>] [--url <url>]");
+            process::exit(1);
+        }
+    };
+
+    attacks::init();
+    if let Err(e) = lichess::run(&options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "lichess"))]
+fn lichess(_: &[String]) {
+    eprintln!("built without the lichess feature");
+    process::exit(1);
+}
+
+fn usage_error() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
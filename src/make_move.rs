@@ -1,50 +1,115 @@
+use crate::prelude::*;
 use crate::{bitboard::{Bitboard, Square}, movegen::{Move, MoveKind}, position::{CastlingFlags, Colour, Piece, Position}, zobrist::ZOBRIST_CODES};
-use num::FromPrimitive;
 use MoveKind::*;
 use Colour::*;
-use Square::*;
 use Piece::*;
 
+// from is None for a piece appearing (promotion), to is None for a piece leaving (capture)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirtyPiece {
+    pub piece: Piece,
+    pub from: Option<Square>,
+    pub to: Option<Square>,
+}
+
+pub const MAX_DIRTY_PIECES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirtyPieces {
+    pub pieces: [DirtyPiece; MAX_DIRTY_PIECES],
+    pub length: usize,
+}
+
+impl DirtyPieces {
+    pub fn new() -> DirtyPieces {
+        DirtyPieces {
+            pieces: [DirtyPiece { piece: Pawn(White), from: None, to: None }; MAX_DIRTY_PIECES],
+            length: 0,
+        }
+    }
+
+    pub fn push(&mut self, piece: Piece, from: Option<Square>, to: Option<Square>) {
+        self.pieces[self.length] = DirtyPiece { piece, from, to };
+        self.length += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.length = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DirtyPiece> {
+        self.pieces[..self.length].iter()
+    }
+}
+
+impl Default for DirtyPieces {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Position {
     pub fn make_move(&mut self, mv: Move) -> Position {
+        let mut dirty = DirtyPieces::new();
+        self.make_move_dirty(mv, &mut dirty)
+    }
+
+    pub fn make_move_dirty(&mut self, mv: Move, dirty: &mut DirtyPieces) -> Position {
+        self.make_move_prefetch(mv, dirty, |_| ())
+    }
+
+    // hands the child's hash to prefetch as soon as it's final, so the search can start loading
+    // its hash table entry while the rest of the move is made
+    pub fn make_move_prefetch(&mut self, mv: Move, dirty: &mut DirtyPieces, prefetch: impl FnOnce(u64)) -> Position {
 
         let copy = *self;
+        dirty.clear();
         
-        self.halfmove += 1;
+        self.halfmove = self.halfmove.saturating_add(1);
         self.ply += 1;
+        if let Some(sq) = self.en_passant {
+            self.hash ^= ZOBRIST_CODES.en_passant(sq);
+        }
         self.en_passant = None;
         
         let from_bb = Bitboard::from(mv.from);
         let to_bb = Bitboard::from(mv.to);
-        let from_to_bb = from_bb | to_bb;
+        // xor so a Chess960 king that castles without moving stays put
+        let from_to_bb = from_bb ^ to_bb;
         
         self.pieces[mv.piece] ^= from_to_bb;
         self.occupancy[self.turn] ^= from_to_bb;
 
         self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.from);
-        if let Some(sq) = self.en_passant {
-            self.hash ^= ZOBRIST_CODES.en_passant(sq);
-        }
+        self.hash ^= ZOBRIST_CODES.castling(self.castling);
         
         match mv.kind {
             Quiet => {
+                dirty.push(mv.piece, Some(mv.from), Some(mv.to));
                 self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.to);
             },
             Capture(p) => {
+                dirty.push(mv.piece, Some(mv.from), Some(mv.to));
+                dirty.push(p, Some(mv.to), None);
                 self.pieces[p] ^= to_bb;
                 self.halfmove = 0;
                 self.last_irreversible_ply = self.ply;
                 self.occupancy[!self.turn] ^= to_bb;
 
-                self.hash ^= ZOBRIST_CODES.piece(p, mv.to);
+                self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.to) ^ ZOBRIST_CODES.piece(p, mv.to);
             },
             Promotion(p) => {
+                dirty.push(mv.piece, Some(mv.from), None);
+                dirty.push(p, None, Some(mv.to));
                 self.pieces[mv.piece] ^= to_bb;
                 self.pieces[p] ^= to_bb;
 
                 self.hash ^= ZOBRIST_CODES.piece(p, mv.to);
             },
             PromotionCapture(p1, p2 ) => {
+                dirty.push(mv.piece, Some(mv.from), None);
+                dirty.push(p1, None, Some(mv.to));
+                dirty.push(p2, Some(mv.to), None);
                 self.pieces[mv.piece] ^= to_bb;
                 self.pieces[p1] ^= to_bb;
                 self.pieces[p2] ^= to_bb;
@@ -53,6 +118,7 @@ impl Position {
                 self.hash ^= ZOBRIST_CODES.piece(p1, mv.to) ^ ZOBRIST_CODES.piece(p2, mv.to);
             },
             DoublePawnPush => {
+                dirty.push(mv.piece, Some(mv.from), Some(mv.to));
                 self.en_passant = match self.turn {
                     White => mv.from.add(-8),
                     Black => mv.from.add(8),
@@ -61,38 +127,27 @@ impl Position {
                 self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.to) ^ ZOBRIST_CODES.en_passant(self.en_passant.unwrap());
             }
             EnPassant => {
-                let captured_file = mv.to.file();
-                let captured_rank = mv.from.rank();
-                let captured = Square::from_i8(captured_rank*8 + captured_file).unwrap();
+                let captured = Square::make(mv.to.file(), mv.from.rank());
                 self.occupancy[!self.turn].reset(captured);
                 self.pieces[Pawn(!self.turn)].reset(captured);
+                dirty.push(mv.piece, Some(mv.from), Some(mv.to));
+                dirty.push(Pawn(!self.turn), Some(captured), None);
 
-                self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.to);
+                self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.to) ^ ZOBRIST_CODES.piece(Pawn(!self.turn), captured);
             },
-            Castling(castling) => {
-                let (from_to, c) = match castling {
-                    CastlingFlags::WK => {
-                        (Bitboard::from(F1) | Bitboard::from(H1), White)
-                    },
-                    CastlingFlags::WQ => {
-                        (Bitboard::from(D1) | Bitboard::from(A1), White)
-                    },
-                    CastlingFlags::BK => {
-                        (Bitboard::from(F8) | Bitboard::from(H8), Black)
-                    },
-                    CastlingFlags::BQ => {
-                        (Bitboard::from(D8) | Bitboard::from(A8), Black)
-                    },
-                    _ => panic!("Attempted to castle two directions at once!"),
-                };
+            Castling(rook_from, rook_to) => {
+                let rook = Rook(self.turn);
+                // xor like the king, a Chess960 rook can already be where it castles to
+                let rook_from_to = Bitboard::from(rook_from) ^ Bitboard::from(rook_to);
+                dirty.push(mv.piece, Some(mv.from), Some(mv.to));
+                dirty.push(rook, Some(rook_from), Some(rook_to));
 
-                self.pieces[Rook(c)] ^= from_to;
-                self.occupancy[c] ^= from_to;
+                self.pieces[rook] ^= rook_from_to;
+                self.occupancy[self.turn] ^= rook_from_to;
 
-                self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.to);
-                for sq in from_to {
-                    self.hash ^= ZOBRIST_CODES.piece(Rook(c), sq);
-                }
+                self.hash ^= ZOBRIST_CODES.piece(mv.piece, mv.to)
+                    ^ ZOBRIST_CODES.piece(rook, rook_from)
+                    ^ ZOBRIST_CODES.piece(rook, rook_to);
                 self.last_irreversible_ply = self.ply;
             },
         }
@@ -103,29 +158,54 @@ impl Position {
                 Black => self.castling.remove(CastlingFlags::BK | CastlingFlags::BQ),
             }
         }
-        if from_to_bb.intersects(Bitboard::from(H1)) && self.castling.contains(CastlingFlags::WK) {
-            self.castling.remove(CastlingFlags::WK);
-            self.last_irreversible_ply = self.ply;
-        }
-        if from_to_bb.intersects(Bitboard::from(A1)) && self.castling.contains(CastlingFlags::WQ) {
-            self.castling.remove(CastlingFlags::WQ);
-            self.last_irreversible_ply = self.ply;
-        }
-        if from_to_bb.intersects(Bitboard::from(H8)) && self.castling.contains(CastlingFlags::BK) {
-            self.castling.remove(CastlingFlags::BK);
-            self.last_irreversible_ply = self.ply;
-        }
-        if from_to_bb.intersects(Bitboard::from(A8)) && self.castling.contains(CastlingFlags::BQ) {
-            self.castling.remove(CastlingFlags::BQ);
-            self.last_irreversible_ply = self.ply;
+        for right in [CastlingFlags::WK, CastlingFlags::WQ, CastlingFlags::BK, CastlingFlags::BQ] {
+            let rook = Bitboard::from(self.castling_rooks[right.index()]);
+            if (from_bb | to_bb).intersects(rook) && self.castling.contains(right) {
+                self.castling.remove(right);
+                self.last_irreversible_ply = self.ply;
+            }
         }
         
         if let Pawn(_) = mv.piece {
             self.halfmove = 0;
             self.last_irreversible_ply = self.ply;
         }
-        
+
+        self.turn = !self.turn;
+        self.hash ^= ZOBRIST_CODES.castling(self.castling) ^ ZOBRIST_CODES.turn();
+        prefetch(self.hash);
+
+        // only pawns moving, promoting or being captured change the pawn key
+        for d in dirty.iter().filter(|d| matches!(d.piece, Pawn(_))) {
+            for sq in [d.from, d.to].into_iter().flatten() {
+                self.pawn_key ^= ZOBRIST_CODES.piece(d.piece, sq);
+            }
+        }
+        for d in dirty.iter() {
+            match (d.from, d.to) {
+                (None, Some(_)) => self.material_key.add(d.piece),
+                (Some(_), None) => self.material_key.remove(d.piece),
+                _ => (),
+            }
+        }
+
+        copy
+    }
+
+    // passes the turn, which is only legal when the side to move isn't in check
+    pub fn make_null_move(&mut self) -> Position {
+        let copy = *self;
+
+        self.halfmove = self.halfmove.saturating_add(1);
+        self.ply += 1;
+        self.last_irreversible_ply = self.ply;
+        if let Some(sq) = self.en_passant {
+            self.hash ^= ZOBRIST_CODES.en_passant(sq);
+        }
+        self.en_passant = None;
         self.turn = !self.turn;
+        self.hash ^= ZOBRIST_CODES.turn();
+
         copy
     }
 
@@ -136,8 +216,116 @@ impl Position {
     pub fn find_algebraic_move(&self, mv_str: &str) -> Option<Move> {
         let mut moves = self.gen_moves();
         
-        moves.find(|&mv| {
-            mv.to_string() == mv_str
+        // castling also matches king takes rook, the UCI_Chess960 notation. in Chess960 it's only
+        // that, the king's target can be a square it could step to instead
+        let chess960 = self.is_chess960();
+        moves.find(|&mv| match mv.kind {
+            Castling(..) => mv.to_uci(true) == mv_str || !chess960 && mv.to_string() == mv_str,
+            _ => mv.to_string() == mv_str,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // replaying the dirty list onto the previous boards must reproduce the new boards
+    fn check_dirty_pieces(fen: &str) {
+        let mut position = Position::from_fen(fen);
+        let moves = position.gen_moves();
+        for mv in moves {
+            let mut dirty = DirtyPieces::new();
+            let prev = position.make_move_dirty(mv, &mut dirty);
+
+            let mut pieces = prev.pieces;
+            for dp in dirty.iter() {
+                if let Some(from) = dp.from {
+                    pieces[dp.piece].reset(from);
+                }
+                if let Some(to) = dp.to {
+                    pieces[dp.piece].set(to);
+                }
+            }
+            assert_eq!(pieces, position.pieces, "dirty pieces mismatch for {}", mv);
+
+            position = prev;
+        }
+    }
+
+    #[test]
+    fn dirty_pieces_test() {
+        check_dirty_pieces("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        check_dirty_pieces("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1");
+        check_dirty_pieces("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1");
+        check_dirty_pieces("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+    }
+
+    // the incrementally updated hashes must match ones computed from scratch at every node
+    fn check_hash(position: &mut Position, depth: usize) {
+        let mut fresh = *position;
+        fresh.gen_zobrist_hash();
+        assert_eq!(position.hash, fresh.hash, "hash mismatch for {}", position.write_fen());
+        assert_eq!(position.pawn_key, fresh.pawn_key, "pawn key mismatch for {}", position.write_fen());
+        assert_eq!(position.material_key, fresh.material_key, "material key mismatch for {}", position.write_fen());
+
+        if depth == 0 {
+            return;
+        }
+        for mv in position.gen_moves() {
+            // the hash handed to prefetch is already the child's
+            let mut prefetched = 0;
+            let prev = position.make_move_prefetch(mv, &mut DirtyPieces::new(), |hash| prefetched = hash);
+            assert_eq!(prefetched, position.hash);
+            check_hash(position, depth - 1);
+            *position = prev;
+        }
+    }
+
+    #[test]
+    fn incremental_hash_test() {
+        check_hash(&mut Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"), 3);
+        check_hash(&mut Position::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1"), 3);
+        check_hash(&mut Position::from_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1"), 3);
+        // queenside castling leaves the d1 rook where it is
+        check_hash(&mut Position::from_fen("qnnrk1br/pppp1ppp/3b4/8/P3p3/1N4P1/1PPPPP1P/QN1RKBBR w HDhd - 0 4"), 2);
+    }
+
+    #[test]
+    fn chess960_castling_rook_in_place_test() {
+        let mut position = Position::from_fen("qnnrk1br/pppp1ppp/3b4/8/P3p3/1N4P1/1PPPPP1P/QN1RKBBR w HDhd - 0 4");
+        let mv = position.gen_moves().find(|mv| mv.kind == Castling(Square::D1, Square::D1)).unwrap();
+        position.make_move(mv);
+        assert_eq!(position.write_fen(), "qnnrk1br/pppp1ppp/3b4/8/P3p3/1N4P1/1PPPPP1P/QNKR1BBR b kq - 1 4");
+    }
+
+    #[test]
+    fn find_castling_test() {
+        // the king can step to c1, where queenside castling would also put it
+        let position = Position::from_fen("2r3kr/8/8/8/8/8/2P5/RK5R w KQkq - 0 1");
+        assert!(position.is_chess960());
+        assert_eq!(position.find_algebraic_move("b1c1").unwrap().kind, Quiet);
+        assert_eq!(position.find_algebraic_move("b1a1").unwrap().kind, Castling(Square::A1, Square::D1));
+        assert!(position.find_algebraic_move("b1g1").is_none());
+
+        // outside Chess960 either notation castles
+        let position = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert!(!position.is_chess960());
+        assert_eq!(position.find_algebraic_move("e1g1"), position.find_algebraic_move("e1h1"));
+        assert!(matches!(position.find_algebraic_move("e1g1").unwrap().kind, Castling(..)));
+    }
+
+    #[test]
+    fn null_move_test() {
+        let mut position = Position::from_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+        let prev = position.make_null_move();
+        assert_eq!(position.write_fen(), "8/8/8/2k5/3Pp3/8/8/4K3 w - - 1 2");
+
+        let mut fresh = position;
+        fresh.gen_zobrist_hash();
+        assert_eq!(position.hash, fresh.hash);
+
+        position.make_null_move();
+        assert_ne!(position.hash, prev.hash);
+    }
+}
@@ -0,0 +1,366 @@
+// Matches between two configurations of the engine, or against an external UCI engine, over a
+// set of openings with each opening played with both colours. An SPRT can stop the match as soon
+// as it's clear whether a change gains anything.
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use log::error;
+
+use crate::{
+    engine::{calculate_allowed_time, Engine},
+    game::Game,
+    interface::{parse_command, Command},
+    logging::Output,
+    movegen::Move,
+    position::Colour,
+    search::{SearchLimits, MAX_DEPTH},
+    selfplay::{play_game, read_openings, Adjudication},
+    send,
+    uci_engine::UciEngine,
+};
+
+// spare time an external engine gets beyond its clock before it's treated as hung
+const EXTERNAL_GRACE_TIME: Duration = Duration::from_secs(1);
+
+// milliseconds for the whole game plus an increment each move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    pub base: u32,
+    pub inc: u32,
+}
+
+impl TimeControl {
+    // as in the PGN TimeControl tag, in seconds
+    fn tag(self) -> String {
+        format!("{}+{}", self.base as f64 / 1000.0, self.inc as f64 / 1000.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchOptions {
+    pub games: usize,
+    // a and b, with the time control limiting both when there is one
+    pub players: [SearchLimits; 2],
+    pub time_control: Option<TimeControl>,
+    pub openings: Option<String>,
+    pub output: String,
+    // setoption names and values for a and b, sent to the engine playing b when there is one
+    pub settings: [Vec<(String, String)>; 2],
+    // a path to an engine playing as b in place of our own search
+    pub engine: Option<String>,
+    // games then becomes the most that will be played
+    pub sprt: Option<Sprt>,
+    pub adjudication: Adjudication,
+}
+
+impl MatchOptions {
+    pub fn new() -> Self {
+        Self {
+            games: 2,
+            players: [SearchLimits::new(); 2],
+            time_control: Some(TimeControl { base: 10_000, inc: 100 }),
+            openings: None,
+            output: "match.pgn".to_string(),
+            settings: [Vec::new(), Vec::new()],
+            engine: None,
+            sprt: None,
+            adjudication: Adjudication::new(),
+        }
+    }
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// wins, losses and draws from a's point of view
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl MatchScore {
+    pub fn games(self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    // the fraction of the points a scored
+    pub fn score(self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    // a's strength relative to b from the logistic model, infinite when one side scored everything
+    pub fn elo(self) -> f64 {
+        score_to_elo(self.score())
+    }
+
+    // half the width of the 95% confidence interval around elo
+    pub fn elo_error(self) -> f64 {
+        let games = self.games().max(1) as f64;
+        let s = self.score();
+        let variance = (self.wins as f64 + self.draws as f64 / 4.0) / games - s * s;
+        let margin = 1.96 * (variance / games).sqrt();
+        (score_to_elo((s + margin).min(1.0)) - score_to_elo((s - margin).max(0.0))) / 2.0
+    }
+}
+
+// a sequential probability ratio test of elo0 against elo1 for a's advantage over b
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self { elo0, elo1, alpha: 0.05, beta: 0.05 }
+    }
+
+    // the log likelihood ratio crossing these accepts elo0 or elo1
+    pub fn bounds(self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    // the GSPRT normal approximation to the trinomial model, used by most testing frameworks. it
+    // needs some spread in the results, so it's zero while every game has ended the same way
+    pub fn llr(self, score: MatchScore) -> f64 {
+        let games = score.games().max(1) as f64;
+        let (w, d) = (score.wins as f64 / games, score.draws as f64 / games);
+        let s = score.score();
+        let variance = (w + d / 4.0 - s * s) / games;
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+
+        (s1 - s0) * (2.0 * s - s0 - s1) / (2.0 * variance)
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn score_to_elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+pub fn play_match(out: &Output, options: &MatchOptions) {
+    let Some(openings) = read_openings(options.openings.as_deref()) else {
+        return;
+    };
+    let mut external = match options.engine.as_deref().map(UciEngine::start).transpose() {
+        Ok(external) => external,
+        Err(e) => {
+            error!("starting engine: {}", e);
+            return;
+        }
+    };
+    let names = [
+        "Blunderbuss A".to_string(),
+        external.as_ref().map_or("Blunderbuss B".to_string(), |engine| engine.name.clone()),
+    ];
+
+    // an engine each with its own options and table, so one player's searches don't help the other's
+    let mut engines = Vec::new();
+    for (side, settings) in options.settings.iter().enumerate() {
+        let mut engine = Engine::with_output(out.clone());
+        for (name, value) in settings {
+            let result = match &mut external {
+                Some(external) if side == 1 => external.set_option(name, value).map_err(|e| e.to_string()),
+                _ => set_option(&mut engine, name, value),
+            };
+            if let Err(e) = result {
+                error!("setting {} for {}: {}", name, names[side], e);
+                return;
+            }
+        }
+        engines.push(engine);
+    }
+
+    let mut pgn = String::new();
+    let mut score = MatchScore::default();
+    for round in 1..=options.games {
+        // each opening twice, a taking white first
+        let start = openings[(round - 1) / 2 % openings.len()];
+        let a_white = round % 2 == 1;
+        let player = |colour: Colour| if (colour == Colour::White) == a_white { 0 } else { 1 };
+
+        if let Some(engine) = &mut external {
+            if let Err(e) = engine.new_game() {
+                error!("from {}: {}", engine.name, e);
+                break;
+            }
+        }
+
+        for engine in &mut engines {
+            _ = engine.execute(Command::UCINewGame);
+        }
+        let mut clocks = [options.time_control.map_or(0, |tc| tc.base); 2];
+        let (game, result, termination) = play_game(start, &options.adjudication, |game| {
+            let side = player(game.position().turn);
+            let start_time = Instant::now();
+            let played = match &mut external {
+                Some(engine) if side == 1 => {
+                    let white = clocks[player(Colour::White)];
+                    let black = clocks[player(Colour::Black)];
+                    external_move(engine, game, options, [white, black], clocks[side])?
+                }
+                _ => {
+                    let mut limits = options.players[side];
+                    if let Some(tc) = options.time_control {
+                        let allowed = calculate_allowed_time(clocks[side], tc.inc, 0).max(1);
+                        limits.movetime = if limits.movetime == 0 { allowed } else { limits.movetime.min(allowed) };
+                    }
+                    let result = engines[side].search_blocking(game, limits);
+                    result.best_move.map(|mv| (mv, result.score)).ok_or("no move from search")?
+                }
+            };
+
+            if let Some(tc) = options.time_control {
+                let used = start_time.elapsed().as_millis() as u32;
+                if used > clocks[side] {
+                    return Err("time forfeit".to_string());
+                }
+                clocks[side] = clocks[side] - used + tc.inc;
+            }
+            Ok(played)
+        });
+
+        match (result, a_white) {
+            ("1-0", true) | ("0-1", false) => score.wins += 1,
+            ("1-0", false) | ("0-1", true) => score.losses += 1,
+            _ => score.draws += 1,
+        }
+        send!(
+            out,
+            "info string game {} {} ({}), a +{} -{} ={}",
+            round, result, termination, score.wins, score.losses, score.draws
+        );
+
+        let mut tags = vec![
+            ("Event", "Blunderbuss match".to_string()),
+            ("Round", round.to_string()),
+            ("White", names[player(Colour::White)].to_string()),
+            ("Black", names[player(Colour::Black)].to_string()),
+            ("Termination", termination),
+        ];
+        if let Some(tc) = options.time_control {
+            tags.push(("TimeControl", tc.tag()));
+        }
+        pgn.push_str(&game.pgn(&tags, result));
+
+        if let Some(sprt) = options.sprt {
+            let llr = sprt.llr(score);
+            let (lower, upper) = sprt.bounds();
+            send!(
+                out,
+                "info string llr {:.2} ({:.2}, {:.2}) elo {:.1} +/- {:.1}",
+                llr,
+                lower,
+                upper,
+                score.elo(),
+                score.elo_error()
+            );
+            if llr <= lower || llr >= upper {
+                let (hypothesis, elo) = if llr >= upper { ("H1", sprt.elo1) } else { ("H0", sprt.elo0) };
+                send!(out, "info string sprt accepts {}, elo {}", hypothesis, elo);
+                break;
+            }
+        }
+    }
+
+    if let Err(e) = fs::write(&options.output, pgn) {
+        error!("writing {}: {}", options.output, e);
+    }
+    send!(
+        out,
+        "info string a vs b +{} -{} ={}, score {:.1}%, elo {:.1}, written to {}",
+        score.wins,
+        score.losses,
+        score.draws,
+        score.score() * 100.0,
+        score.elo(),
+        options.output
+    );
+}
+
+fn set_option(engine: &mut Engine, name: &str, value: &str) -> Result<(), String> {
+    match parse_command(&format!("setoption name {} value {}", name, value)) {
+        Some(cmd @ Command::SetOption(_)) => engine.execute(cmd).map_err(|e| e.to_string()),
+        _ => Err(format!("can't set it to {}", value)),
+    }
+}
+
+// b's limits are passed on to the engine, the clocks are white's then black's
+fn external_move(
+    engine: &mut UciEngine,
+    game: &Game,
+    options: &MatchOptions,
+    clocks: [u32; 2],
+    own_clock: u32,
+) -> Result<(Move, i32), String> {
+    let limits = options.players[1];
+    let mut go = Vec::new();
+    if let Some(tc) = options.time_control {
+        go.push(format!("wtime {} btime {} winc {} binc {}", clocks[0], clocks[1], tc.inc, tc.inc));
+    }
+    if limits.nodes != u32::MAX {
+        go.push(format!("nodes {}", limits.nodes));
+    }
+    if limits.depth != MAX_DEPTH as u8 {
+        go.push(format!("depth {}", limits.depth));
+    }
+    if limits.movetime != 0 {
+        go.push(format!("movetime {}", limits.movetime));
+    }
+
+    let timeout = options.time_control.map(|_| Duration::from_millis(own_clock as u64) + EXTERNAL_GRACE_TIME);
+    engine
+        .best_move(game, &go.join(" "), timeout)
+        .map_err(|e| format!("{} from {}", e, engine.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_score() {
+        let score = MatchScore { wins: 3, losses: 1, draws: 4 };
+        assert_eq!(score.games(), 8);
+        assert_eq!(score.score(), 0.625);
+        assert!((score.elo() - 88.7).abs() < 0.1);
+        assert_eq!(MatchScore { wins: 2, losses: 2, draws: 0 }.elo(), 0.0);
+        assert_eq!(TimeControl { base: 10_000, inc: 100 }.tag(), "10+0.1");
+    }
+
+    #[test]
+    fn sprt() {
+        let sprt = Sprt::new(0.0, 10.0);
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.94).abs() < 0.01 && (upper - 2.94).abs() < 0.01);
+
+        // scoring well above elo1 is accepted, far below elo0 rejected
+        assert!(sprt.llr(MatchScore { wins: 600, losses: 400, draws: 1000 }) > upper);
+        assert!(sprt.llr(MatchScore { wins: 400, losses: 600, draws: 1000 }) < lower);
+        // without draws, as at fixed nodes with adjudication off
+        assert!(sprt.llr(MatchScore { wins: 600, losses: 400, draws: 0 }) > upper);
+        assert!(sprt.llr(MatchScore { wins: 400, losses: 600, draws: 0 }) < lower);
+        assert_eq!(sprt.llr(MatchScore { wins: 5, losses: 0, draws: 0 }), 0.0);
+        assert_eq!(sprt.llr(MatchScore::default()), 0.0);
+
+        let score = MatchScore { wins: 600, losses: 400, draws: 1000 };
+        assert!(score.elo_error() > 0.0 && score.elo_error() < score.elo());
+    }
+}
@@ -0,0 +1,66 @@
+// Piece counts packed four bits a piece into one key, cheap to keep up to date in make_move and
+// to compare when dispatching endgame evaluators or caching material imbalance.
+
+use crate::{bitboard::Bitboard, position::Piece};
+
+const COUNT_BITS: u32 = 4;
+const COUNT_MASK: u64 = (1 << COUNT_BITS) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MaterialKey(pub u64);
+
+impl MaterialKey {
+    pub fn from_pieces(pieces: &[Bitboard; 12]) -> MaterialKey {
+        let mut key = 0;
+        for (piece, bb) in pieces.iter().enumerate() {
+            key |= (bb.popcount() as u64) << (piece as u32 * COUNT_BITS);
+        }
+
+        MaterialKey(key)
+    }
+
+    pub fn count(self, piece: Piece) -> u32 {
+        (self.0 >> shift(piece) & COUNT_MASK) as u32
+    }
+
+    pub fn add(&mut self, piece: Piece) {
+        self.0 += 1 << shift(piece);
+    }
+
+    pub fn remove(&mut self, piece: Piece) {
+        self.0 -= 1 << shift(piece);
+    }
+}
+
+fn shift(piece: Piece) -> u32 {
+    usize::from(piece) as u32 * COUNT_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::{Colour::*, Piece::*, Position};
+
+    #[test]
+    fn material_counts() {
+        let position = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let key = position.material_key;
+        assert_eq!(key, MaterialKey::from_pieces(&position.pieces));
+        assert_eq!(key.count(Pawn(White)), 8);
+        assert_eq!(key.count(Pawn(Black)), 8);
+        assert_eq!(key.count(Knight(Black)), 2);
+        assert_eq!(key.count(Queen(White)), 1);
+        assert_eq!(key.count(King(Black)), 1);
+
+        // the key only depends on what's on the board, not where
+        let shuffled = Position::from_fen("r3k2r/1p1ppqpb/1bn2pnp/3PN3/1p2P3/2N2Q1p/PPPBBPPP/1R2K2R w Kkq - 0 1");
+        assert_eq!(shuffled.material_key, key);
+
+        let mut promoted = Position::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N w - - 0 1");
+        let mv = promoted.find_algebraic_move("b7a8q").unwrap();
+        promoted.make_move(mv);
+        assert_eq!(promoted.material_key.count(Pawn(White)), 2);
+        assert_eq!(promoted.material_key.count(Queen(White)), 1);
+        assert_eq!(promoted.material_key.count(Knight(Black)), 1);
+    }
+}
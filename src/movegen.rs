@@ -1,104 +1,34 @@
-use std::fmt::Display;
+use core::fmt::Display;
 
+use bitflags::bitflags;
+
+use crate::prelude::*;
 use crate::{
-    bitboard::{Bitboard, Square},
-    magic::{BISHOP_BITS, MAGICS, ROOK_BITS},
+    attacks::{
+        aligned, between, bishop_attacks, king_attacks, knight_attacks, pawn_attacks,
+        rook_attacks,
+    },
+    bitboard::{Bitboard, File, Rank, Square},
     position::{
         CastlingFlags,
         Colour::{self, *},
         Piece, Position,
     },
-    search::{mvv_lva, SearchInfo},
 };
 use Piece::*;
 use Square::*;
 
 pub const MAX_MOVES: usize = 256;
-const KING_MOVES: [u64; 64] = build_king_tbl();
-const KNIGHT_MOVES: [u64; 64] = build_knight_tbl();
-
-const fn build_king_tbl() -> [u64; 64] {
-    let mut moves = [0; 64];
-    let mut sq = 0;
-    while sq < 64 {
-        let king = 1 << sq;
-        moves[sq] = king << 7 & !Bitboard::H_FILE.0
-            | king << 8
-            | king << 9 & !Bitboard::A_FILE.0
-            | king << 1 & !Bitboard::A_FILE.0
-            | king >> 7 & !Bitboard::A_FILE.0
-            | king >> 8
-            | king >> 9 & !Bitboard::H_FILE.0
-            | king >> 1 & !Bitboard::H_FILE.0;
-        sq += 1;
-    }
-
-    moves
-}
-
-const fn build_knight_tbl() -> [u64; 64] {
-    let mut moves = [0; 64];
-    let mut sq = 0;
-    while sq < 64 {
-        let knight = 1 << sq;
-        moves[sq] = knight << 6 & !Bitboard::H_FILE.0 & !Bitboard::G_FILE.0
-            | knight << 15 & !Bitboard::H_FILE.0
-            | knight << 17 & !Bitboard::A_FILE.0
-            | knight << 10 & !Bitboard::A_FILE.0 & !Bitboard::B_FILE.0
-            | knight >> 6 & !Bitboard::A_FILE.0 & !Bitboard::B_FILE.0
-            | knight >> 15 & !Bitboard::A_FILE.0
-            | knight >> 17 & !Bitboard::H_FILE.0
-            | knight >> 10 & !Bitboard::H_FILE.0 & !Bitboard::G_FILE.0;
-
-        sq += 1;
-    }
-    moves
-}
-
-pub fn king_attacks(sq: Square) -> Bitboard {
-    let king = Bitboard::from(sq);
-    let mut attacks = Bitboard(0);
-    attacks |= king << 7 & !Bitboard::H_FILE;
-    attacks |= king << 8;
-    attacks |= king << 9 & !Bitboard::A_FILE;
-    attacks |= king << 1 & !Bitboard::A_FILE;
-    attacks |= king >> 7 & !Bitboard::A_FILE;
-    attacks |= king >> 8;
-    attacks |= king >> 9 & !Bitboard::H_FILE;
-    attacks |= king >> 1 & !Bitboard::H_FILE;
-    attacks
-}
-
-pub fn knight_attacks(sq: Square) -> Bitboard {
-    let knight = Bitboard::from(sq);
-    let mut attacks = Bitboard(0);
-    attacks |= knight << 6 & !Bitboard::H_FILE & !Bitboard::G_FILE;
-    attacks |= knight << 15 & !Bitboard::H_FILE;
-    attacks |= knight << 17 & !Bitboard::A_FILE;
-    attacks |= knight << 10 & !Bitboard::A_FILE & !Bitboard::B_FILE;
-    attacks |= knight >> 6 & !Bitboard::A_FILE & !Bitboard::B_FILE;
-    attacks |= knight >> 15 & !Bitboard::A_FILE;
-    attacks |= knight >> 17 & !Bitboard::H_FILE;
-    attacks |= knight >> 10 & !Bitboard::H_FILE & !Bitboard::G_FILE;
-    attacks
-}
 
-pub fn pawn_attacks(sq: Square, side: Colour) -> Bitboard {
-    let mut attacks = Bitboard(0);
+// squares in front of the pawn on its own file, the path it has to walk to promote
+pub fn pawn_front_span(sq: Square, side: Colour) -> Bitboard {
     let pawn = Bitboard::from(sq);
+    pawn.front_fill(side) & !pawn
+}
 
-    match side {
-        Colour::White => {
-            attacks |= pawn >> 7 & !Bitboard::A_FILE;
-            attacks |= pawn >> 9 & !Bitboard::H_FILE;
-        }
-        Colour::Black => {
-            attacks |= pawn << 7 & !Bitboard::H_FILE;
-            attacks |= pawn << 9 & !Bitboard::A_FILE;
-        }
-    }
-
-    attacks
+// squares the pawn could ever attack as it advances
+pub fn pawn_attack_span(sq: Square, side: Colour) -> Bitboard {
+    pawn_attacks(sq, side).front_fill(side)
 }
 
 pub fn pawn_pushes(sq: Square, side: Colour) -> Bitboard {
@@ -116,22 +46,6 @@ pub fn pawn_pushes(sq: Square, side: Colour) -> Bitboard {
     pushes
 }
 
-fn rook_attacks(sq: Square, mut occ: Bitboard) -> Bitboard {
-    occ &= MAGICS.rook_magics[sq as usize].mask;
-    occ.0 *= MAGICS.rook_magics[sq as usize].magic;
-    occ >>= 64 - ROOK_BITS[sq];
-
-    MAGICS.rook_attacks[sq as usize][occ.0 as usize]
-}
-
-fn bishop_attacks(sq: Square, mut occ: Bitboard) -> Bitboard {
-    occ &= MAGICS.bishop_magics[sq as usize].mask;
-    occ.0 *= MAGICS.bishop_magics[sq as usize].magic;
-    occ >>= 64 - BISHOP_BITS[sq];
-
-    MAGICS.bishop_attacks[sq as usize][occ.0 as usize]
-}
-
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Move {
     pub from: Square,
@@ -140,6 +54,12 @@ pub struct Move {
     pub kind: MoveKind,
 }
 
+impl Default for Move {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Move {
     pub const NULL: Move = Move::new();
 
@@ -151,10 +71,19 @@ impl Move {
             kind: MoveKind::Quiet,
         }
     }
+
+    // castling is king takes rook in Chess960, where the king's target can be a square it could
+    // also step to
+    pub fn to_uci(self, chess960: bool) -> String {
+        match self.kind {
+            MoveKind::Castling(rook, _) if chess960 => format!("{}{}", self.from, rook),
+            _ => self.to_string(),
+        }
+    }
 }
 
 impl Display for Move {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let promotion = if let MoveKind::Promotion(p) | MoveKind::PromotionCapture(p, _) = self.kind
         {
             p.to_string().to_lowercase()
@@ -172,11 +101,28 @@ pub enum MoveKind {
     Capture(Piece),
     DoublePawnPush,
     EnPassant,
-    Castling(CastlingFlags),
+    Castling(Square, Square), // rook from, rook to
     Promotion(Piece),
     PromotionCapture(Piece, Piece),
 }
 
+// higher scores are yielded first when iterating a MoveList
+pub trait MoveOrderer {
+    fn score(&self, mv: &Move, ply: usize) -> u8;
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct GenType: u8 {
+        const CAPTURES = 0b00001;
+        const QUIETS = 0b00010;
+        const EVASIONS = 0b00100;
+        const CHECKS = 0b01000;
+        const PROMOTIONS = 0b10000;
+        const ALL = Self::CAPTURES.bits() | Self::QUIETS.bits();
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct MoveList {
     pub moves: [Move; MAX_MOVES],
@@ -185,6 +131,12 @@ pub struct MoveList {
     pub curr: usize,
 }
 
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MoveList {
     pub fn new() -> MoveList {
         MoveList {
@@ -205,17 +157,9 @@ impl MoveList {
         self.moves[self.length]
     }
 
-    pub fn score(&mut self, ply: usize, info: &SearchInfo) {
+    pub fn score(&mut self, ply: usize, orderer: &impl MoveOrderer) {
         for i in 0..self.length {
-            let mv = &mut self.moves[i];
-
-            if info.current_branch[..ply] == info.triangular_pv[..ply]
-                && info.triangular_pv[ply].is_some_and(|pv_mv| *mv == pv_mv)
-            {
-                self.sort_scores[i] += 100;
-            }
-
-            self.sort_scores[i] += mvv_lva(mv);
+            self.sort_scores[i] = orderer.score(&self.moves[i], ply);
         }
     }
 }
@@ -250,11 +194,120 @@ impl Iterator for MoveList {
 impl Position {
     pub fn gen_moves(&self) -> MoveList {
         let mut moves = MoveList::new();
-        self.gen_captures(&mut moves);
-        self.gen_quiet_moves(&mut moves);
+        self.gen(&mut moves, GenType::ALL);
         moves
     }
 
+    // appends every pseudo-legal move belonging to at least one of the requested classes
+    pub fn gen(&self, moves: &mut MoveList, gen_type: GenType) {
+        let start = moves.length;
+
+        // both halves need it for the king, and it's the dearest part of generating
+        let danger = self.danger_squares();
+        if gen_type.intersects(!GenType::QUIETS) {
+            self.gen_captures(moves, danger);
+        }
+        if gen_type.intersects(!GenType::CAPTURES) {
+            self.gen_quiet_moves(moves, danger);
+        }
+        if gen_type.contains(GenType::ALL) {
+            return;
+        }
+
+        let checkers = if gen_type.contains(GenType::EVASIONS) {
+            self.checkers()
+        } else {
+            Bitboard(0)
+        };
+
+        let mut kept = start;
+        for i in start..moves.length {
+            let mv = moves.moves[i];
+            let keep = match mv.kind {
+                MoveKind::Capture(_) | MoveKind::EnPassant => gen_type.contains(GenType::CAPTURES),
+                MoveKind::PromotionCapture(..) => {
+                    gen_type.intersects(GenType::CAPTURES | GenType::PROMOTIONS)
+                }
+                MoveKind::Promotion(_) => {
+                    gen_type.intersects(GenType::QUIETS | GenType::PROMOTIONS)
+                }
+                _ => gen_type.contains(GenType::QUIETS),
+            } || gen_type.contains(GenType::EVASIONS) && self.is_evasion(mv, checkers)
+                || gen_type.contains(GenType::CHECKS) && self.gives_check(mv);
+
+            if keep {
+                moves.moves[kept] = mv;
+                kept += 1;
+            }
+        }
+        moves.length = kept;
+    }
+
+    fn is_evasion(&self, mv: Move, checkers: Bitboard) -> bool {
+        if checkers.is_empty() || mv.piece == King(self.turn) {
+            return true;
+        }
+        if checkers.more_than_one() {
+            return false;
+        }
+
+        let checker = checkers.get_lsb().unwrap();
+        let king = self.pieces[King(self.turn)].get_lsb().expect("missing king");
+        if mv.kind == MoveKind::EnPassant {
+            let captured = Square::make(mv.to.file(), mv.from.rank());
+            return captured == checker || between(king, checker).is_set(mv.to);
+        }
+
+        mv.to == checker || between(king, checker).is_set(mv.to)
+    }
+
+    pub fn attackers_to(&self, sq: Square, occ: Bitboard) -> Bitboard {
+        let queens = self.pieces[Queen(White)] | self.pieces[Queen(Black)];
+        pawn_attacks(sq, Black) & self.pieces[Pawn(White)]
+            | pawn_attacks(sq, White) & self.pieces[Pawn(Black)]
+            | knight_attacks(sq) & (self.pieces[Knight(White)] | self.pieces[Knight(Black)])
+            | king_attacks(sq) & (self.pieces[King(White)] | self.pieces[King(Black)])
+            | bishop_attacks(sq, occ)
+                & (self.pieces[Bishop(White)] | self.pieces[Bishop(Black)] | queens)
+            | rook_attacks(sq, occ) & (self.pieces[Rook(White)] | self.pieces[Rook(Black)] | queens)
+    }
+
+    // enemy pieces giving check to the side to move
+    pub fn checkers(&self) -> Bitboard {
+        let king = self.pieces[King(self.turn)].get_lsb().expect("missing king");
+        self.attackers_to(king, self.occupied()) & self.occupancy[!self.turn]
+    }
+
+    pub fn gives_check(&self, mv: Move) -> bool {
+        if let MoveKind::EnPassant
+        | MoveKind::Castling(..)
+        | MoveKind::Promotion(_)
+        | MoveKind::PromotionCapture(..) = mv.kind
+        {
+            let mut next = *self;
+            next.make_move(mv);
+            return next.is_check(next.turn);
+        }
+
+        let king = self.pieces[King(!self.turn)].get_lsb().expect("missing king");
+        let occ = self.occupied() ^ Bitboard::from(mv.from) | Bitboard::from(mv.to);
+        let direct = match mv.piece {
+            Pawn(c) => pawn_attacks(mv.to, c),
+            Knight(_) => knight_attacks(mv.to),
+            Bishop(_) => bishop_attacks(mv.to, occ),
+            Rook(_) => rook_attacks(mv.to, occ),
+            Queen(_) => bishop_attacks(mv.to, occ) | rook_attacks(mv.to, occ),
+            King(_) => Bitboard(0),
+        };
+        if direct.is_set(king) {
+            return true;
+        }
+
+        // a discovered check unless the piece stays on the line to the king
+        self.discovered_check_candidates(self.turn).is_set(mv.from)
+            && !aligned(king, mv.from, mv.to)
+    }
+
     pub fn is_sq_attacked_by(&self, sq: Square, side: Colour) -> bool {
         pawn_attacks(sq, !side).intersects(self.pieces[Pawn(side)])
             || knight_attacks(sq).intersects(self.pieces[Knight(side)])
@@ -270,7 +323,90 @@ impl Position {
         self.is_sq_attacked_by(king, !side)
     }
 
-    pub fn gen_quiet_moves(&self, moves: &mut MoveList) {
+    // pieces of side standing alone between one of its sliders and the enemy king
+    pub fn discovered_check_candidates(&self, side: Colour) -> Bitboard {
+        self.slider_blockers(!side, side) & self.occupancy[side]
+    }
+
+    // pieces of side that can't leave the line between an enemy slider and their king
+    pub fn pinned(&self, side: Colour) -> Bitboard {
+        self.slider_blockers(side, !side) & self.occupancy[side]
+    }
+
+    // lone pieces of either colour between king_side's king and a slider of sniper_side
+    fn slider_blockers(&self, king_side: Colour, sniper_side: Colour) -> Bitboard {
+        let king = self.pieces[King(king_side)].get_lsb().expect("missing king");
+        let occ = self.occupied();
+        let queens = self.pieces[Queen(sniper_side)];
+
+        let snipers = rook_attacks(king, Bitboard(0)) & (self.pieces[Rook(sniper_side)] | queens)
+            | bishop_attacks(king, Bitboard(0)) & (self.pieces[Bishop(sniper_side)] | queens);
+
+        let mut blockers = Bitboard(0);
+        for sniper in snipers {
+            let between = between(king, sniper) & occ;
+            if between.only_one() {
+                blockers |= between;
+            }
+        }
+
+        blockers
+    }
+
+    pub fn is_legal(&self, mv: Move) -> bool {
+        // out of check, anything but the king or an en passant capture is only illegal if it
+        // leaves a pin
+        if mv.piece != King(self.turn) && mv.kind != MoveKind::EnPassant && self.checkers().is_empty() {
+            let king = self.pieces[King(self.turn)].get_lsb().expect("missing king");
+            return !self.pinned(self.turn).is_set(mv.from) || aligned(king, mv.from, mv.to);
+        }
+
+        let mut next = *self;
+        next.make_move(mv);
+        !next.is_check(self.turn)
+    }
+
+    pub fn has_legal_move(&self) -> bool {
+        let mut moves = MoveList::new();
+        let danger = self.danger_squares();
+        self.gen_captures(&mut moves, danger);
+        if moves.moves[..moves.length].iter().any(|&mv| self.is_legal(mv)) {
+            return true;
+        }
+
+        moves.length = 0;
+        self.gen_quiet_moves(&mut moves, danger);
+        moves.moves[..moves.length].iter().any(|&mv| self.is_legal(mv))
+    }
+
+    pub fn attacked_squares(&self, side: Colour, occ: Bitboard) -> Bitboard {
+        let mut attacks = Bitboard(0);
+        for piece in Piece::iter_colour(side) {
+            for sq in self.pieces[piece] {
+                attacks |= match piece {
+                    Pawn(c) => pawn_attacks(sq, c),
+                    Knight(_) => knight_attacks(sq),
+                    Bishop(_) => bishop_attacks(sq, occ),
+                    Rook(_) => rook_attacks(sq, occ),
+                    Queen(_) => bishop_attacks(sq, occ) | rook_attacks(sq, occ),
+                    King(_) => king_attacks(sq),
+                };
+            }
+        }
+
+        attacks
+    }
+
+    // squares the side to move's king can't step to. The king is removed from the occupancy so
+    // sliders checking it also cover the squares behind it
+    pub fn danger_squares(&self) -> Bitboard {
+        let occ = self.occupied() ^ self.pieces[King(self.turn)];
+        self.attacked_squares(!self.turn, occ)
+    }
+
+    // danger is the position's danger_squares, passed in so a caller generating both quiets and
+    // captures only works it out once
+    pub fn gen_quiet_moves(&self, moves: &mut MoveList, danger: Bitboard) {
         let occ = self.occupied();
         self.gen_castling(moves);
 
@@ -285,11 +421,11 @@ impl Position {
                     Bishop(_) => bishop_attacks(from, occ),
                     Rook(_) => rook_attacks(from, occ),
                     Queen(_) => bishop_attacks(from, occ) | rook_attacks(from, occ),
-                    King(_) => king_attacks(from),
+                    King(_) => king_attacks(from) & !danger,
                 } & !occ;
 
                 if let Pawn(c) = piece {
-                    let backranks = Bitboard(0xff | (0xff << 56));
+                    let backranks = Bitboard::RANK_1 | Bitboard::RANK_8;
                     if bb.intersects(backranks) {
                         let promotables = [Queen(c), Rook(c), Bishop(c), Knight(c)];
                         for p in promotables {
@@ -317,7 +453,7 @@ impl Position {
         }
     }
 
-    pub fn gen_captures(&self, moves: &mut MoveList) {
+    pub fn gen_captures(&self, moves: &mut MoveList, danger: Bitboard) {
         let occ = self.occupied();
         let opponent = self.occupancy[!self.turn];
         self.gen_en_passant(moves);
@@ -330,7 +466,7 @@ impl Position {
                     Bishop(_) => bishop_attacks(from, occ),
                     Rook(_) => rook_attacks(from, occ),
                     Queen(_) => bishop_attacks(from, occ) | rook_attacks(from, occ),
-                    King(_) => king_attacks(from),
+                    King(_) => king_attacks(from) & !danger,
                 } & opponent;
 
                 for to in bb {
@@ -363,8 +499,8 @@ impl Position {
     }
 
     pub fn gen_double_pawn_pushes(&self, moves: &mut MoveList, c: Colour, from: Square) {
-        match (c, from as i8) {
-            (White, 48..=55) => {
+        match (c, from.rank()) {
+            (White, Rank::R2) => {
                 let mut path = Bitboard::from(from);
                 path >>= 8;
                 path |= path >> 8;
@@ -380,7 +516,7 @@ impl Position {
                     kind: MoveKind::DoublePawnPush,
                 })
             }
-            (Black, 8..=15) => {
+            (Black, Rank::R7) => {
                 let mut path = Bitboard::from(from);
                 path <<= 8;
                 path |= path << 8;
@@ -419,66 +555,217 @@ impl Position {
             return;
         }
 
-        let occ = self.occupied();
+        let king = self.pieces[King(self.turn)].get_lsb().expect("missing king");
+        let back_rank = A1.relative_to(self.turn).rank();
+        let sides = [
+            (CastlingFlags::kingside(self.turn), File::G, File::F),
+            (CastlingFlags::queenside(self.turn), File::C, File::D),
+        ];
 
-        match self.turn {
-            White => {
-                if self.castling.contains(CastlingFlags::WK)
-                    && occ.0 & 0x60 << 56 == 0
-                    && !self.is_sq_attacked_by(F1, Black)
-                    && !self.is_sq_attacked_by(G1, Black)
-                    && !self.is_sq_attacked_by(E1, Black)
-                {
-                    moves.push(Move {
-                        from: Square::E1,
-                        to: Square::G1,
-                        piece: King(White),
-                        kind: MoveKind::Castling(CastlingFlags::WK),
-                    });
-                }
-                if self.castling.contains(CastlingFlags::WQ)
-                    && occ.0 & 0xe << 56 == 0
-                    && !self.is_sq_attacked_by(D1, Black)
-                    && !self.is_sq_attacked_by(C1, Black)
-                    && !self.is_sq_attacked_by(E1, Black)
-                {
-                    moves.push(Move {
-                        from: Square::E1,
-                        to: Square::C1,
-                        piece: King(White),
-                        kind: MoveKind::Castling(CastlingFlags::WQ),
-                    });
-                }
+        for (right, king_file, rook_file) in sides {
+            if !self.castling.contains(right) {
+                continue;
             }
-            Black => {
-                if self.castling.contains(CastlingFlags::BK)
-                    && occ.0 & 0x60 == 0
-                    && !self.is_sq_attacked_by(F8, White)
-                    && !self.is_sq_attacked_by(G8, White)
-                    && !self.is_sq_attacked_by(E8, White)
-                {
-                    moves.push(Move {
-                        from: Square::E8,
-                        to: Square::G8,
-                        piece: King(Black),
-                        kind: MoveKind::Castling(CastlingFlags::BK),
-                    });
-                }
 
-                if self.castling.contains(CastlingFlags::BQ)
-                    && occ.0 & 0xe == 0
-                    && !self.is_sq_attacked_by(D8, White)
-                    && !self.is_sq_attacked_by(C8, White)
-                    && !self.is_sq_attacked_by(E8, White)
-                {
-                    moves.push(Move {
-                        from: Square::E8,
-                        to: Square::C8,
-                        piece: King(Black),
-                        kind: MoveKind::Castling(CastlingFlags::BQ),
-                    });
+            // in Chess960 the king and rook can start anywhere on the back rank, so everything
+            // either of them crosses or lands on must be empty apart from the two of them
+            let rook = self.castling_rooks[right.index()];
+            let (king_to, rook_to) = (Square::make(king_file, back_rank), Square::make(rook_file, back_rank));
+            let king_path = between(king, king_to) | Bitboard::from(king_to);
+            let rook_path = between(rook, rook_to) | Bitboard::from(rook_to);
+            let occ = self.occupied() ^ Bitboard::from(king) ^ Bitboard::from(rook);
+            if (king_path | rook_path).intersects(occ) {
+                continue;
+            }
+
+            if (king_path | Bitboard::from(king)).any(|sq| self.is_sq_attacked_by(sq, !self.turn)) {
+                continue;
+            }
+
+            moves.push(Move {
+                from: king,
+                to: king_to,
+                piece: King(self.turn),
+                kind: MoveKind::Castling(rook, rook_to),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_legal_move_test() {
+        let position = Position::from_fen(crate::fen::STARTING_FEN);
+        assert!(position.has_legal_move());
+
+        // checkmate
+        let position = Position::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(!position.has_legal_move());
+
+        // stalemate
+        let position = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(!position.has_legal_move());
+
+        // back rank mate, the knight is defended by the rook through the king
+        let position = Position::from_fen("7k/8/8/8/8/8/5PPP/3r2Kn w - - 0 1");
+        assert!(!position.has_legal_move());
+
+        // only legal move is a capture of the checking piece
+        let position = Position::from_fen("7k/8/8/8/8/8/5PPP/2Rr2K1 w - - 0 1");
+        assert!(position.has_legal_move());
+        let legal: Vec<Move> = position.gen_moves().filter(|&mv| position.is_legal(mv)).collect();
+        assert_eq!(legal.len(), 1);
+        assert_eq!(legal[0].kind, MoveKind::Capture(Rook(Black)));
+    }
+
+    #[test]
+    fn gen_type_test() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+        ];
+
+        for fen in fens {
+            let position = Position::from_fen(fen);
+            let all = position.gen_moves();
+            let legal: Vec<Move> = all.moves[..all.length]
+                .iter()
+                .copied()
+                .filter(|&mv| position.is_legal(mv))
+                .collect();
+
+            for mv in &all.moves[..all.length] {
+                let mut next = position;
+                next.make_move(*mv);
+                assert_eq!(
+                    position.gives_check(*mv),
+                    next.is_check(next.turn),
+                    "{} {}",
+                    fen,
+                    mv
+                );
+            }
+
+            let mut captures = MoveList::new();
+            position.gen(&mut captures, GenType::CAPTURES);
+            let mut quiets = MoveList::new();
+            position.gen(&mut quiets, GenType::QUIETS);
+            assert_eq!(captures.length + quiets.length, all.length);
+            assert!(captures.moves[..captures.length]
+                .iter()
+                .all(|mv| !matches!(mv.kind, MoveKind::Quiet | MoveKind::Promotion(_))));
+
+            let mut promotions = MoveList::new();
+            position.gen(&mut promotions, GenType::PROMOTIONS);
+            assert!(promotions.moves[..promotions.length]
+                .iter()
+                .all(|mv| matches!(
+                    mv.kind,
+                    MoveKind::Promotion(_) | MoveKind::PromotionCapture(..)
+                )));
+
+            let mut checks = MoveList::new();
+            position.gen(&mut checks, GenType::CHECKS);
+            assert!(checks.moves[..checks.length]
+                .iter()
+                .all(|&mv| position.gives_check(mv)));
+
+            // every legal move is an evasion
+            let mut evasions = MoveList::new();
+            position.gen(&mut evasions, GenType::EVASIONS);
+            assert!(legal
+                .iter()
+                .all(|mv| evasions.moves[..evasions.length].contains(mv)));
+        }
+
+        // in check evasions are a strict subset of the pseudo-legal moves
+        let position = Position::from_fen("4k3/8/8/8/1b6/8/8/R3K2R w KQ - 0 1");
+        let mut evasions = MoveList::new();
+        position.gen(&mut evasions, GenType::EVASIONS);
+        let legal = evasions.moves[..evasions.length]
+            .iter()
+            .filter(|&&mv| position.is_legal(mv))
+            .count();
+        assert_eq!(legal, 4);
+        assert!(evasions.length < position.gen_moves().length);
+    }
+
+    #[test]
+    fn king_moves_avoid_danger_squares() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "7k/8/8/8/8/8/2r5/K7 w - - 0 1",
+            "4k3/8/8/8/8/8/8/r3K3 w - - 0 1",
+            "8/8/4k3/3P4/8/8/8/4K3 b - - 0 1",
+        ];
+
+        for fen in fens {
+            let position = Position::from_fen(fen);
+            let moves = position.gen_moves();
+            for mv in moves.moves[..moves.length].iter() {
+                if let King(_) = mv.piece {
+                    assert!(position.is_legal(*mv), "{} {}", fen, mv);
                 }
             }
         }
+
+        // the king can't retreat along the checking rook's ray
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1");
+        assert!(position.danger_squares().is_set(F1));
+    }
+
+    #[test]
+    fn pawn_span_test() {
+        assert_eq!(pawn_front_span(E4, White), Bitboard::from(E5) | Bitboard::from(E6) | Bitboard::from(E7) | Bitboard::from(E8));
+        assert_eq!(pawn_front_span(E2, Black), Bitboard::from(E1));
+        assert_eq!(
+            pawn_attack_span(A6, White),
+            Bitboard::from(B7) | Bitboard::from(B8)
+        );
+        assert_eq!(
+            pawn_attack_span(D3, Black),
+            Bitboard::from(C2) | Bitboard::from(C1) | Bitboard::from(E2) | Bitboard::from(E1)
+        );
+    }
+
+    #[test]
+    fn pinned_test() {
+        // the e4 knight is pinned by the rook and the d2 pawn by the bishop
+        let position = Position::from_fen("4r1k1/8/8/8/1b2N3/8/3P4/2N1K3 w - - 0 1");
+        assert_eq!(position.pinned(White), Bitboard::from(E4) | Bitboard::from(D2));
+        assert_eq!(position.pinned(Black), Bitboard(0));
+
+        let pinned = Move { from: E4, to: D6, piece: Knight(White), kind: MoveKind::Quiet };
+        let free = Move { from: C1, to: B3, piece: Knight(White), kind: MoveKind::Quiet };
+        assert!(!position.is_legal(pinned));
+        assert!(position.is_legal(free));
+    }
+
+    #[test]
+    fn discovered_check_candidates_test() {
+        // knight on e4 blocks the e1 rook, the bishops aren't aimed at the king
+        let position = Position::from_fen("4k3/8/8/8/4N3/2B5/1B6/4R1K1 w - - 0 1");
+        assert_eq!(position.discovered_check_candidates(White), Bitboard::from(E4));
+
+        // two blockers don't reveal check
+        let position = Position::from_fen("4k3/4p3/8/8/4N3/8/8/4R1K1 w - - 0 1");
+        assert_eq!(position.discovered_check_candidates(White), Bitboard(0));
+
+        // enemy blocker is a pin, not a discovery
+        let position = Position::from_fen("4k3/4n3/8/8/8/8/8/4R1K1 w - - 0 1");
+        assert_eq!(position.discovered_check_candidates(White), Bitboard(0));
+        assert_eq!(position.discovered_check_candidates(Black), Bitboard(0));
+
+        let position = Position::from_fen("4k3/8/8/1b6/8/3P4/8/5K2 b - - 0 1");
+        assert_eq!(position.discovered_check_candidates(Black), Bitboard(0));
+        let position = Position::from_fen("4k3/8/8/1b6/2n5/8/8/5K2 b - - 0 1");
+        assert_eq!(position.discovered_check_candidates(Black), Bitboard::from(C4));
     }
 }
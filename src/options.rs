@@ -0,0 +1,120 @@
+// The options the engine advertises in reply to uci. Each has a kind, which decides how it's
+// advertised and which values setoption accepts for it.
+
+use std::fmt::{self, Display};
+
+use crate::tt::DEFAULT_HASH_MB;
+
+#[derive(Debug)]
+pub enum OptionKind {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    Combo { default: &'static str, vars: &'static [&'static str] },
+    Button,
+    String { default: &'static str },
+}
+
+#[derive(Debug)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+
+// a value that's been checked against its option's kind
+#[derive(Debug, PartialEq)]
+pub enum OptionValue {
+    Check(bool),
+    Spin(i64),
+    // string and combo options, combos as the var they matched
+    Text(String),
+    Button,
+}
+
+pub const OPTIONS: [UciOption; 12] = [
+    UciOption { name: "OwnBook", kind: OptionKind::Check { default: false } },
+    UciOption { name: "Book File", kind: OptionKind::String { default: "<empty>" } },
+    UciOption { name: "Debug Log File", kind: OptionKind::String { default: "<empty>" } },
+    UciOption { name: "JSON Output", kind: OptionKind::Check { default: false } },
+    UciOption { name: "Hash", kind: OptionKind::Spin { default: DEFAULT_HASH_MB as i64, min: 1, max: 1024 } },
+    UciOption { name: "Clear Hash", kind: OptionKind::Button },
+    UciOption {
+        name: "Log Level",
+        kind: OptionKind::Combo { default: "warn", vars: &["off", "error", "warn", "info", "debug", "trace"] },
+    },
+    UciOption { name: "Log Target", kind: OptionKind::String { default: "uci" } },
+    UciOption { name: "UCI_Chess960", kind: OptionKind::Check { default: false } },
+    UciOption { name: "Deterministic", kind: OptionKind::Check { default: false } },
+    UciOption { name: "Learning", kind: OptionKind::Check { default: false } },
+    UciOption { name: "Learning File", kind: OptionKind::String { default: "<empty>" } },
+];
+
+// option names are case insensitive
+pub fn find_option(name: &str) -> Option<&'static UciOption> {
+    OPTIONS.iter().find(|option| option.name.eq_ignore_ascii_case(name))
+}
+
+impl UciOption {
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        match self.kind {
+            OptionKind::Check { .. } => match value {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(format!("{} must be true or false, not {}", self.name, value)),
+            },
+            OptionKind::Spin { min, max, .. } => match value.parse() {
+                Ok(n) if (min..=max).contains(&n) => Ok(OptionValue::Spin(n)),
+                _ => Err(format!("{} must be a number from {} to {}, not {}", self.name, min, max, value)),
+            },
+            OptionKind::Combo { vars, .. } => match vars.iter().find(|var| var.eq_ignore_ascii_case(value)) {
+                Some(var) => Ok(OptionValue::Text(var.to_string())),
+                None => Err(format!("{} must be one of {}, not {}", self.name, vars.join(" "), value)),
+            },
+            OptionKind::Button => Ok(OptionValue::Button),
+            // <empty> is how GUIs send an empty string
+            OptionKind::String { .. } if value == "<empty>" => Ok(OptionValue::Text(String::new())),
+            OptionKind::String { .. } => Ok(OptionValue::Text(value.to_string())),
+        }
+    }
+}
+
+impl Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match self.kind {
+            OptionKind::Check { default } => write!(f, "check default {}", default),
+            OptionKind::Spin { default, min, max } => write!(f, "spin default {} min {} max {}", default, min, max),
+            OptionKind::Combo { default, vars } => {
+                write!(f, "combo default {}", default)?;
+                vars.iter().try_for_each(|var| write!(f, " var {}", var))
+            }
+            OptionKind::Button => write!(f, "button"),
+            OptionKind::String { default } => write!(f, "string default {}", default),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_values() {
+        let spin = UciOption { name: "Hash", kind: OptionKind::Spin { default: 16, min: 1, max: 1024 } };
+        assert_eq!(spin.to_string(), "option name Hash type spin default 16 min 1 max 1024");
+        assert_eq!(spin.parse("64"), Ok(OptionValue::Spin(64)));
+        assert!(spin.parse("0").is_err());
+        assert!(spin.parse("lots").is_err());
+
+        let level = find_option("log level").unwrap();
+        assert_eq!(
+            level.to_string(),
+            "option name Log Level type combo default warn var off var error var warn var info var debug var trace"
+        );
+        assert_eq!(level.parse("Debug"), Ok(OptionValue::Text("debug".to_string())));
+        assert!(level.parse("loud").is_err());
+
+        assert_eq!(find_option("book file").unwrap().parse("<empty>"), Ok(OptionValue::Text(String::new())));
+        assert!(find_option("ownbook").unwrap().parse("yes").is_err());
+        assert!(find_option("threads").is_none());
+    }
+}
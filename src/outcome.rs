@@ -0,0 +1,77 @@
+// Whether a game is over and how, for self-play, adjudication and GUIs embedding the library.
+
+use crate::position::{Colour, Position};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DrawReason {
+    FiftyMove,
+    Repetition,
+    InsufficientMaterial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Ongoing,
+    Checkmate(Colour),
+    Stalemate,
+    Draw(DrawReason),
+}
+
+impl Outcome {
+    pub fn is_over(self) -> bool {
+        self != Outcome::Ongoing
+    }
+
+    // the PGN result tag
+    pub fn result(self) -> &'static str {
+        match self {
+            Outcome::Ongoing => "*",
+            Outcome::Checkmate(Colour::White) => "1-0",
+            Outcome::Checkmate(Colour::Black) => "0-1",
+            Outcome::Stalemate | Outcome::Draw(_) => "1/2-1/2",
+        }
+    }
+}
+
+impl Position {
+    // history is laid out as for is_draw, mate on the hundredth halfmove beats the fifty-move rule
+    pub fn outcome(&self, history: &[u64]) -> Outcome {
+        if !self.has_legal_move() {
+            return if self.is_check(self.turn) { Outcome::Checkmate(!self.turn) } else { Outcome::Stalemate };
+        }
+
+        if self.is_fifty_move_draw() {
+            Outcome::Draw(DrawReason::FiftyMove)
+        } else if self.repetitions(history) >= 2 {
+            Outcome::Draw(DrawReason::Repetition)
+        } else if self.is_insufficient_material() {
+            Outcome::Draw(DrawReason::InsufficientMaterial)
+        } else {
+            Outcome::Ongoing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    fn outcome(fen: &str) -> Outcome {
+        Position::from_fen(fen).outcome(&[])
+    }
+
+    #[test]
+    fn outcome_test() {
+        assert_eq!(outcome(STARTING_FEN), Outcome::Ongoing);
+        assert_eq!(outcome("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"), Outcome::Checkmate(Colour::Black));
+        assert_eq!(outcome("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), Outcome::Stalemate);
+        assert_eq!(outcome("4k3/8/8/8/8/8/8/R3K3 w - - 100 80"), Outcome::Draw(DrawReason::FiftyMove));
+        assert_eq!(outcome("4k3/8/8/8/8/8/8/4KB2 w - - 0 1"), Outcome::Draw(DrawReason::InsufficientMaterial));
+        assert_eq!(outcome("R5k1/5ppp/8/8/8/8/8/4K3 b - - 100 80"), Outcome::Checkmate(Colour::White));
+
+        assert_eq!(Outcome::Checkmate(Colour::Black).result(), "0-1");
+        assert_eq!(Outcome::Draw(DrawReason::Repetition).result(), "1/2-1/2");
+        assert!(!Outcome::Ongoing.is_over());
+    }
+}
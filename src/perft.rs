@@ -1,29 +1,159 @@
-use crate::position::Position;
+#[cfg(feature = "uci")]
+use std::fs;
 
-pub fn perft_divide(pos: &mut Position, depth: u8) {
-    let mut total_nodes = 0;
+#[cfg(feature = "uci")]
+use log::warn;
 
-    let moves = pos.gen_moves();
+use crate::prelude::*;
+use crate::{
+    movegen::{Move, MoveKind},
+    position::Position,
+};
+#[cfg(feature = "uci")]
+use crate::{engine::EngineError, epd::EpdRecord, logging::Output, send};
 
-    for mv in moves {
+// a node count for each position and depth seen, so transpositions are only counted once. any
+// mistake in the incremental zobrist hashes shows up as a wrong total
+pub struct PerftTable {
+    entries: Vec<PerftEntry>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct PerftEntry {
+    hash: u64,
+    depth: u8,
+    nodes: u64,
+}
+
+impl PerftTable {
+    // rounded down to a power of two entries
+    pub fn new(mb: usize) -> Self {
+        let len = (mb * 1024 * 1024 / size_of::<PerftEntry>()).max(1);
+        Self { entries: vec![PerftEntry::default(); 1 << len.ilog2()] }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        hash as usize & (self.entries.len() - 1)
+    }
+
+    fn probe(&self, hash: u64, depth: u8) -> Option<u64> {
+        let entry = self.entries[self.index(hash)];
+        (entry.hash == hash && entry.depth == depth).then_some(entry.nodes)
+    }
+
+    // always replaces, the newest entries are the likeliest to be seen again
+    fn store(&mut self, hash: u64, depth: u8, nodes: u64) {
+        let index = self.index(hash);
+        self.entries[index] = PerftEntry { hash, depth, nodes };
+    }
+}
+
+// what the leaves of a perft tree are, as tabulated for the standard perft positions so move
+// generators can be compared in more detail than node counts. captures include en passants, checks
+// include checkmates
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PerftResult {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub checkmates: u64,
+}
+
+// every leaf is made and looked at, so this is a lot slower than perft_nodes
+pub fn perft(pos: &mut Position, depth: u8) -> PerftResult {
+    let mut result = PerftResult::default();
+    if depth == 0 {
+        result.nodes = 1;
+    } else {
+        perft_leaves(pos, depth, &mut result);
+    }
+    result
+}
+
+fn perft_leaves(pos: &mut Position, depth: u8, result: &mut PerftResult) {
+    for mv in pos.gen_moves() {
         let prev = pos.make_move(mv);
         if pos.is_check(!pos.turn) {
             *pos = prev;
             continue
         }
-        let nodes = perft(pos, depth - 1);
-        println!("{} {}", mv, nodes);
-        total_nodes += nodes;
+
+        if depth > 1 {
+            perft_leaves(pos, depth - 1, result);
+            *pos = prev;
+            continue
+        }
+
+        result.nodes += 1;
+        match mv.kind {
+            MoveKind::Capture(_) => result.captures += 1,
+            MoveKind::EnPassant => {
+                result.captures += 1;
+                result.en_passants += 1;
+            }
+            MoveKind::Castling(..) => result.castles += 1,
+            MoveKind::Promotion(_) => result.promotions += 1,
+            MoveKind::PromotionCapture(..) => {
+                result.captures += 1;
+                result.promotions += 1;
+            }
+            MoveKind::Quiet | MoveKind::DoublePawnPush => (),
+        }
+        if pos.is_check(pos.turn) {
+            result.checks += 1;
+            if !pos.gen_moves().into_iter().any(|mv| pos.is_legal(mv)) {
+                result.checkmates += 1;
+            }
+        }
+        *pos = prev;
+    }
+}
+
+// the nodes under each legal move, in generation order. hash_mb of 0 counts every node without a
+// table
+pub fn perft_divide(pos: &mut Position, depth: u8, hash_mb: usize) -> Vec<(Move, u64)> {
+    perft_divide_with(pos, depth, hash_mb, |_, _| ())
+}
+
+// on_move hears about each move as soon as it's counted, for showing progress on deep perfts
+pub fn perft_divide_with(
+    pos: &mut Position,
+    depth: u8,
+    hash_mb: usize,
+    mut on_move: impl FnMut(Move, u64),
+) -> Vec<(Move, u64)> {
+    let mut table = (hash_mb > 0).then(|| PerftTable::new(hash_mb));
+    let mut results = Vec::new();
+
+    for mv in pos.gen_moves() {
+        let prev = pos.make_move(mv);
+        if pos.is_check(!pos.turn) {
+            *pos = prev;
+            continue
+        }
+        let nodes = match table.as_mut() {
+            Some(table) => perft_hashed(pos, depth.saturating_sub(1), table),
+            None => perft_nodes(pos, depth.saturating_sub(1)),
+        };
+        on_move(mv, nodes);
+        results.push((mv, nodes));
         *pos = prev;
     }
 
-    println!("\n{}", total_nodes);
+    results
 }
 
-pub fn perft(pos: &mut Position, depth: u8) -> u64 {
+// only counts the leaves, which at the last ply is_legal can mostly do without making the moves
+pub fn perft_nodes(pos: &mut Position, depth: u8) -> u64 {
     if depth == 0 {
         return 1;
     }
+    if depth == 1 {
+        return pos.gen_moves().into_iter().filter(|&mv| pos.is_legal(mv)).count() as u64;
+    }
 
     let mut nodes = 0;
 
@@ -34,16 +164,96 @@ pub fn perft(pos: &mut Position, depth: u8) -> u64 {
             *pos = prev;
             continue
         }
-        nodes += perft(pos, depth - 1);
+        nodes += perft_nodes(pos, depth - 1);
         *pos = prev;
     }
 
     nodes
 }
 
+pub fn perft_hashed(pos: &mut Position, depth: u8, table: &mut PerftTable) -> u64 {
+    // storing the last plies would cost more than counting them
+    if depth <= 1 {
+        return perft_nodes(pos, depth);
+    }
+    if let Some(nodes) = table.probe(pos.hash, depth) {
+        return nodes;
+    }
+
+    let mut nodes = 0;
+
+    let moves = pos.gen_moves();
+    for mv in moves {
+        let prev = pos.make_move(mv);
+        if pos.is_check(!pos.turn) {
+            *pos = prev;
+            continue
+        }
+        nodes += perft_hashed(pos, depth - 1, table);
+        *pos = prev;
+    }
+
+    table.store(pos.hash, depth, nodes);
+    nodes
+}
+
+// checks the D<depth> <nodes> operations of every position in an EPD suite, skipping deeper ones
+#[cfg(feature = "uci")]
+pub fn perft_suite(out: &Output, path: &str, max_depth: u8, hash_mb: usize) -> Result<(), EngineError> {
+    let contents = fs::read_to_string(path).map_err(|error| EngineError::File { path: path.to_string(), error })?;
+
+    let mut table = (hash_mb > 0).then(|| PerftTable::new(hash_mb));
+    let mut passed = 0;
+    let mut total = 0;
+    for (i, line) in contents.lines().filter(|line| !line.trim().is_empty()).enumerate() {
+        let record = match EpdRecord::parse(line) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("parsing EPD: {}", e);
+                continue;
+            }
+        };
+
+        let mut failures = Vec::new();
+        for op in &record.operations {
+            let Some(depth) = op.opcode.strip_prefix('D').and_then(|depth| depth.parse().ok()) else {
+                continue;
+            };
+            let Some(expected) = op.operands.first().and_then(|nodes| nodes.parse::<u64>().ok()) else {
+                continue;
+            };
+            if depth > max_depth {
+                continue;
+            }
+
+            let mut position = record.position;
+            let nodes = match table.as_mut() {
+                Some(table) => perft_hashed(&mut position, depth, table),
+                None => perft_nodes(&mut position, depth),
+            };
+            if nodes != expected {
+                failures.push(format!("depth {} found {} expected {}", depth, nodes, expected));
+            }
+        }
+
+        let id = record.id.clone().unwrap_or_else(|| (i + 1).to_string());
+        total += 1;
+        if failures.is_empty() {
+            passed += 1;
+            send!(out, "{}: passed", id);
+        } else {
+            send!(out, "{}: failed, {}", id, failures.join(", "));
+        }
+    }
+
+    send!(out, "passed {}/{}", passed, total);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{epd::EpdRecord, fen::STARTING_FEN};
     use seq_macro::seq;
     use std::env;
     use std::fs;
@@ -53,29 +263,25 @@ mod tests {
         ($first:expr, $last:expr) => {
             seq!(N in $first..$last {
                 #(#[test_case(N)])*
-                fn perft_test_n(n: usize) -> Result<(), Box<dyn std::error::Error>> {
+                fn perft_test_n(n: usize) -> Result<(), Box<dyn core::error::Error>> {
                     perft_test(n)
                 }
             });
         };
     }
 
-    fn perft_test(n: usize) -> Result<(), Box<dyn std::error::Error>> {
+    fn perft_test(n: usize) -> Result<(), Box<dyn core::error::Error>> {
         let mut path = env::current_dir().unwrap();
         path.push("perftsuite.epd");
         let contents = fs::read_to_string(path)?;
-        let tests: Vec<&str> = contents.split("\n").collect();
-        let test: Vec<&str> = tests[n].split(";").collect();
-        let fen = test[0];
-
-        let mut position = Position::new();
-        position.read_fen(fen);
-
-        for depth in 1..test.len() {
-            let nodes = perft(&mut position, depth as u8);
-            let expected = test[depth].split_whitespace().collect::<Vec<&str>>()[1]
-                .parse()
-                .unwrap();
+        let line = contents.lines().nth(n).expect("missing perft test");
+        let EpdRecord { mut position, operations, .. } = EpdRecord::parse(line)?;
+
+        // operations are D<depth> <nodes>
+        for op in operations {
+            let depth: u8 = op.opcode.trim_start_matches('D').parse()?;
+            let expected: u64 = op.operands[0].parse()?;
+            let nodes = perft_nodes(&mut position, depth);
             if nodes != expected {
                 println!("{}", position);
                 println!("depth: {}", depth);
@@ -86,4 +292,53 @@ mod tests {
         Ok(())
     }
     test_cases!(0,50);
+
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189, 326672])]
+    #[test_case("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002, 667366])]
+    #[test_case("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471, 273318])]
+    fn chess960_perft_test(fen: &str, expected: &[u64]) {
+        let mut position = Position::from_fen(fen);
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(perft_nodes(&mut position, depth as u8 + 1), nodes, "depth {}", depth + 1);
+        }
+    }
+
+    #[test_case("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4, 4085603)]
+    #[test_case("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674624)]
+    #[test_case("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 4, 422333)]
+    fn hashed_perft_test(fen: &str, depth: u8, expected: u64) {
+        // small enough that entries get replaced
+        let mut table = PerftTable::new(1);
+        assert_eq!(perft_hashed(&mut Position::from_fen(fen), depth, &mut table), expected);
+    }
+
+    #[test]
+    fn perft_result_test() {
+        let mut position = Position::from_fen(STARTING_FEN);
+        let expected = PerftResult { nodes: 197281, captures: 1576, checks: 469, checkmates: 8, ..Default::default() };
+        assert_eq!(perft(&mut position, 4), expected);
+
+        let mut position = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let expected = PerftResult {
+            nodes: 97862,
+            captures: 17102,
+            en_passants: 45,
+            castles: 3162,
+            promotions: 0,
+            checks: 993,
+            checkmates: 1,
+        };
+        assert_eq!(perft(&mut position, 3), expected);
+    }
+
+    #[test]
+    fn divide_test() {
+        let mut position = Position::from_fen(STARTING_FEN);
+        let mut seen = Vec::new();
+        let results = perft_divide_with(&mut position, 3, 0, |mv, nodes| seen.push((mv, nodes)));
+        assert_eq!(results, seen);
+        assert_eq!(results.len(), 20);
+        assert_eq!(results.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+        assert_eq!(perft_divide(&mut position, 3, 1), results);
+    }
 }
@@ -0,0 +1,109 @@
+use core::arch::x86_64::_pext_u64;
+
+use spin::Once;
+use crate::prelude::*;
+
+use num::FromPrimitive;
+
+use crate::{
+    bitboard::{Bitboard, Square},
+    magic::{bishop_attacks_mask, rook_attacks_mask},
+};
+
+static PEXT: Once<PextTables> = Once::new();
+
+pub fn has_bmi2() -> bool {
+    crate::cpu::features().bmi2
+}
+
+pub fn tables() -> &'static PextTables {
+    PEXT.call_once(PextTables::init)
+}
+
+pub struct PextTables {
+    pub rook_masks: [Bitboard; 64],
+    pub bishop_masks: [Bitboard; 64],
+    pub rook_offsets: [usize; 64],
+    pub bishop_offsets: [usize; 64],
+    pub attacks: Vec<Bitboard>,
+}
+
+impl PextTables {
+    fn init() -> Self {
+        let mut tables = PextTables {
+            rook_masks: [Bitboard(0); 64],
+            bishop_masks: [Bitboard(0); 64],
+            rook_offsets: [0; 64],
+            bishop_offsets: [0; 64],
+            attacks: Vec::new(),
+        };
+
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+
+            tables.rook_masks[i] = rook_attacks_mask(sq);
+            tables.rook_offsets[i] = tables.attacks.len();
+            for occ in tables.rook_masks[i].subsets() {
+                tables.attacks.push(crate::magic::rook_attacks(sq, occ));
+            }
+
+            tables.bishop_masks[i] = bishop_attacks_mask(sq);
+            tables.bishop_offsets[i] = tables.attacks.len();
+            for occ in tables.bishop_masks[i].subsets() {
+                tables.attacks.push(crate::magic::bishop_attacks(sq, occ));
+            }
+        }
+
+        tables
+    }
+}
+
+// the lookups themselves are built for BMI2, so pext is inlined into them rather than called
+
+/// # Safety
+///
+/// The CPU must support BMI2, check [`has_bmi2`] first.
+#[target_feature(enable = "bmi2")]
+pub unsafe fn rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    let sq = sq as usize;
+    let tables = tables();
+    let idx = _pext_u64(occ.0, tables.rook_masks[sq].0) as usize;
+    tables.attacks[tables.rook_offsets[sq] + idx]
+}
+
+/// # Safety
+///
+/// The CPU must support BMI2, check [`has_bmi2`] first.
+#[target_feature(enable = "bmi2")]
+pub unsafe fn bishop_attacks(sq: Square, occ: Bitboard) -> Bitboard {
+    let sq = sq as usize;
+    let tables = tables();
+    let idx = _pext_u64(occ.0, tables.bishop_masks[sq].0) as usize;
+    tables.attacks[tables.bishop_offsets[sq] + idx]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::XorShift;
+
+    #[test]
+    fn pext_matches_ray_attacks() {
+        if !has_bmi2() {
+            return;
+        }
+
+        let mut prng = XorShift::new();
+        for i in 0..64 {
+            let sq = Square::from_usize(i).unwrap();
+            for _ in 0..1000 {
+                let occ = Bitboard(prng.gen_next() & prng.gen_next());
+                // SAFETY: has_bmi2 was checked above
+                unsafe {
+                    assert_eq!(rook_attacks(sq, occ), crate::magic::rook_attacks(sq, occ));
+                    assert_eq!(bishop_attacks(sq, occ), crate::magic::bishop_attacks(sq, occ));
+                }
+            }
+        }
+    }
+}
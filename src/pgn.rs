@@ -0,0 +1,102 @@
+// PGN export for games, the seven tag roster plus whatever the caller adds, with SAN movetext
+// wrapped to the 80 columns the standard asks for.
+
+use crate::{fen::STARTING_FEN, game::Game, position::Colour::*};
+
+const LINE_LENGTH: usize = 80;
+
+impl Game {
+    // tags override the roster defaults by name, the result goes in both the tag and the movetext
+    pub fn pgn(&self, tags: &[(&str, String)], result: &str) -> String {
+        let mut roster = vec![
+            ("Event", "?".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", "????.??.??".to_string()),
+            ("Round", "?".to_string()),
+            ("White", "?".to_string()),
+            ("Black", "?".to_string()),
+            ("Result", result.to_string()),
+        ];
+        for (name, value) in tags {
+            match roster.iter_mut().find(|(tag, _)| tag == name) {
+                Some((_, v)) => *v = value.clone(),
+                None => roster.push((name, value.clone())),
+            }
+        }
+        let fen = self.start().write_fen();
+        if fen != STARTING_FEN {
+            roster.push(("SetUp", "1".to_string()));
+            roster.push(("FEN", fen));
+        }
+
+        let mut pgn = String::new();
+        for (name, value) in roster {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut position = *self.start();
+        for (i, &mv) in self.moves().iter().enumerate() {
+            let fullmove = position.ply / 2 + 1;
+            match position.turn {
+                White => tokens.push(format!("{}.", fullmove)),
+                Black if i == 0 => tokens.push(format!("{}...", fullmove)),
+                Black => (),
+            }
+            tokens.push(position.san(mv));
+            position.make_move(mv);
+        }
+        tokens.push(result.to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push_str("\n\n");
+
+        pgn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::apply_uci_moves, position::Position};
+
+    #[test]
+    fn pgn_test() {
+        let start = Position::from_fen(STARTING_FEN);
+        let game = apply_uci_moves(start, ["f2f3", "e7e5", "g2g4", "d8h4"]).unwrap();
+        let pgn = game.pgn(&[("White", "A".to_string()), ("Round", "3".to_string())], "0-1");
+        assert_eq!(
+            pgn,
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"3\"]\n[White \"A\"]\n[Black \"?\"]\n\
+             [Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n"
+        );
+
+        let start = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 b Q - 0 30");
+        let game = apply_uci_moves(start, ["e8d7", "e1c1"]).unwrap();
+        let pgn = game.pgn(&[], "*");
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 30\"]\n"));
+        assert!(pgn.ends_with("\n30... Kd7 31. O-O-O+ *\n\n"));
+
+        // long games wrap
+        let mut game = Game::default();
+        for _ in 0..10 {
+            game.push_uci_moves(["g1f3", "g8f6", "f3g1", "f6g8"]).unwrap();
+        }
+        let pgn = game.pgn(&[], "1/2-1/2");
+        assert!(pgn.lines().all(|line| line.len() <= LINE_LENGTH));
+        assert!(pgn.lines().filter(|line| line.starts_with(|c: char| c.is_ascii_digit())).count() > 1);
+    }
+}
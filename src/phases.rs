@@ -0,0 +1,89 @@
+// Where the search's time goes, for bench to report so optimisation goes where it counts. Timing
+// every call costs more than some of the calls themselves, so it's only compiled in with the
+// phase-timers feature. Without it timed is just the call and there are no totals.
+
+#[cfg(feature = "phase-timers")]
+use std::{cell::Cell, time::Instant};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    MoveGen,
+    MakeMove,
+    Eval,
+    See,
+    // probing and storing the transposition table
+    Tt,
+}
+
+pub const PHASES: [Phase; 5] = [Phase::MoveGen, Phase::MakeMove, Phase::Eval, Phase::See, Phase::Tt];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTotal {
+    pub calls: u64,
+    pub time: Duration,
+}
+
+// per thread, so searches on other threads don't muddle a bench's totals
+#[cfg(feature = "phase-timers")]
+thread_local! {
+    static TOTALS: Cell<[PhaseTotal; PHASES.len()]> = const { Cell::new([PhaseTotal { calls: 0, time: Duration::ZERO }; PHASES.len()]) };
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::MoveGen => "movegen",
+            Phase::MakeMove => "make_move",
+            Phase::Eval => "eval",
+            Phase::See => "see",
+            Phase::Tt => "tt",
+        }
+    }
+}
+
+#[inline(always)]
+pub fn timed<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "phase-timers")]
+    {
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        TOTALS.with(|totals| {
+            let mut all = totals.get();
+            all[phase as usize].calls += 1;
+            all[phase as usize].time += elapsed;
+            totals.set(all);
+        });
+        result
+    }
+    #[cfg(not(feature = "phase-timers"))]
+    {
+        _ = phase;
+        f()
+    }
+}
+
+// the totals on this thread since the last take, none when they aren't being kept
+pub fn take_totals() -> Option<[PhaseTotal; PHASES.len()]> {
+    #[cfg(feature = "phase-timers")]
+    return Some(TOTALS.with(|totals| totals.take()));
+    #[cfg(not(feature = "phase-timers"))]
+    None
+}
+
+#[cfg(all(test, feature = "phase-timers"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_totals() {
+        take_totals();
+        assert_eq!(timed(Phase::Eval, || 2 + 2), 4);
+        timed(Phase::Eval, || ());
+        let totals = take_totals().unwrap();
+        assert_eq!(totals[Phase::Eval as usize].calls, 2);
+        assert_eq!(totals[Phase::See as usize], PhaseTotal::default());
+        assert_eq!(take_totals().unwrap()[Phase::Eval as usize].calls, 0);
+    }
+}
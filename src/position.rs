@@ -1,9 +1,11 @@
-use std::{fmt::Display, ops::{Index, IndexMut, Not}};
+use core::{fmt::Display, ops::{Index, IndexMut, Not}};
 
-use crate::bitboard::{Bitboard, Square::{self, *}};
+use crate::prelude::*;
+use crate::{bitboard::{Bitboard, File, Rank, Square::{self, *}}, material::MaterialKey};
 use bitflags::bitflags;
 use Colour::*;
-use enum_iterator::Sequence;
+use enum_iterator::{all, Sequence};
+use itertools::Itertools;
 use Piece::*;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -12,11 +14,22 @@ pub struct Position {
     pub occupancy: [Bitboard; 2],
     pub turn: Colour,
     pub castling: CastlingFlags,
+    // the rook each castling right belongs to, indexed by CastlingFlags::index
+    pub castling_rooks: [Square; 4],
     pub en_passant: Option<Square>,
     pub halfmove: u8,
-    pub ply: u8,
+    pub ply: u16,
     pub hash: u64,
-    pub last_irreversible_ply: u8,
+    // hash of the pawns alone, for pawn structure caches
+    pub pawn_key: u64,
+    pub material_key: MaterialKey,
+    pub last_irreversible_ply: u16,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Position {
@@ -27,18 +40,20 @@ impl Position {
             occupancy: [Bitboard(0); 2],
             turn: White,
             castling: CastlingFlags::empty(),
+            castling_rooks: STANDARD_CASTLING_ROOKS,
             en_passant: None,
             halfmove: 0,
             ply: 0,
             hash: 0,
+            pawn_key: 0,
+            material_key: MaterialKey(0),
             last_irreversible_ply: 0,
         }
     }
 
+    // for FENs known to be valid, input from outside should go through try_from_fen
     pub fn from_fen(fen: &str) -> Position {
-        let mut position = Position::new();
-        position.read_fen(fen);
-        position
+        Position::try_from_fen(fen).unwrap_or_else(|e| panic!("invalid FEN {}: {}", fen, e))
     }
 
     pub fn occupied(&self) -> Bitboard {
@@ -46,37 +61,116 @@ impl Position {
     }
 
     pub fn piece_on(&self, sq: Square) -> Option<Piece> {
-        if let Some(p) = self.pieces.iter().position(|bb| bb.is_set(sq)) {
-            Some(p.into())
-        } else {
-            None
+        self.pieces.iter().position(|bb| bb.is_set(sq)).map(|p| p.into())
+    }
+
+    // castling rights with the king or a rook off its usual square, which only Chess960 has
+    pub fn is_chess960(&self) -> bool {
+        [White, Black].into_iter().any(|side| {
+            let king = self.pieces[King(side)].get_lsb();
+            [CastlingFlags::kingside(side), CastlingFlags::queenside(side)].into_iter().any(|right| {
+                self.castling.contains(right)
+                    && (king != Some(E1.relative_to(side))
+                        || self.castling_rooks[right.index()] != STANDARD_CASTLING_ROOKS[right.index()])
+            })
+        })
+    }
+
+    // the same position with the colours swapped and the board mirrored top to bottom, so the
+    // evaluation from the side to move is unchanged
+    pub fn flipped(&self) -> Position {
+        let mut flipped = *self;
+        for piece in enum_iterator::all::<Piece>() {
+            flipped.pieces[piece] = self.pieces[piece.swap_colour()].flip_vertical();
+        }
+        for side in [White, Black] {
+            flipped.occupancy[side] = self.occupancy[!side].flip_vertical();
         }
+
+        flipped.turn = !self.turn;
+        flipped.castling = CastlingFlags::from_bits_truncate(self.castling.bits() >> 2 | self.castling.bits() << 2);
+        for right in [CastlingFlags::WK, CastlingFlags::WQ, CastlingFlags::BK, CastlingFlags::BQ] {
+            let mirrored = CastlingFlags::from_bits_truncate(right.bits() >> 2 | right.bits() << 2);
+            flipped.castling_rooks[mirrored.index()] = self.castling_rooks[right.index()].flip_vertical();
+        }
+        flipped.en_passant = self.en_passant.map(Square::flip_vertical);
+
+        // keep the fullmove number, white moves on even plies
+        let shift = |ply: u16| match self.turn {
+            White => ply + 1,
+            Black => ply.saturating_sub(1),
+        };
+        flipped.ply = shift(self.ply);
+        flipped.last_irreversible_ply = shift(self.last_irreversible_ply);
+        flipped.gen_zobrist_hash();
+
+        flipped
     }
 }
 
-impl Display for Position {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let delim = "\n  +---+---+---+---+---+---+---+---+\n";
-        let mut square = Some(A8);
-        while let Some(sq) = square {
-            if sq.file() == 0 {
-                write!(f, "{}", delim)?;
-                write!(f, "{} |", 8 - sq.rank())?;
-            }
-            if let Some(p) = self.piece_on(sq) {
-                write!(f, " {} |", p)?;
-            } else {
-                write!(f, "   |")?;
+// how Position::pretty draws the board, the default being ASCII from white's side
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoardStyle {
+    pub unicode: bool,
+    pub from_black: bool,
+}
+
+impl Position {
+    // the board with coordinates followed by the state that isn't visible on it, like Stockfish's d
+    pub fn pretty(&self, style: BoardStyle) -> String {
+        let delim = "  +---+---+---+---+---+---+---+---+\n";
+        let mut ranks: Vec<Rank> = all::<Rank>().collect();
+        let mut files: Vec<File> = all::<File>().collect();
+        if style.from_black {
+            ranks.reverse();
+            files.reverse();
+        }
+
+        let mut s = String::new();
+        for &rank in &ranks {
+            s.push_str(delim);
+            s.push_str(&format!("{} |", rank));
+            for &file in &files {
+                match self.piece_on(Square::make(file, rank)) {
+                    Some(p) if style.unicode => s.push_str(&format!(" {} |", p.glyph())),
+                    Some(p) => s.push_str(&format!(" {} |", p)),
+                    None => s.push_str("   |"),
+                }
             }
-            
-            square = sq.add(1);
+            s.push('\n');
         }
-        write!(f, "{}  ", delim)?;
-        for file in 'a'..='h' {
-            write!(f, "  {} ", file)?;
+        s.push_str(delim);
+        s.push_str("  ");
+        for file in files {
+            s.push_str(&format!("  {} ", file));
         }
 
-        writeln!(f, "\nFen: {}", self.write_fen())
+        let side = match self.turn {
+            White => "White",
+            Black => "Black",
+        };
+        let en_passant = self.en_passant.map_or("-".to_string(), |sq| sq.to_string());
+        s.push_str(&format!("\n\nFen: {}", self.write_fen()));
+        s.push_str(&format!("\nKey: {:016X}", self.hash));
+        s.push_str(&format!("\nSide to move: {}", side));
+        s.push_str(&format!("\nCastling: {}", self.write_castling(false)));
+        s.push_str(&format!("\nEn passant: {}", en_passant));
+        s.push_str(&format!("\nHalfmove clock: {}", self.halfmove));
+        // a board still being set up can be missing a king, with nothing to check
+        let checkers = if self.pieces[King(White)].is_empty() || self.pieces[King(Black)].is_empty() {
+            Bitboard(0)
+        } else {
+            self.checkers()
+        };
+        s.push_str(&format!("\nCheckers: {}\n", checkers.into_iter().map(|sq| sq.to_string()).join(" ")));
+
+        s
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.pretty(BoardStyle::default()))
     }
 }
 
@@ -90,8 +184,32 @@ bitflags! {
     }
 }
 
+// rook squares for WK, WQ, BK and BQ outside of Chess960
+pub const STANDARD_CASTLING_ROOKS: [Square; 4] = [H1, A1, H8, A8];
+
+impl CastlingFlags {
+    pub fn kingside(side: Colour) -> CastlingFlags {
+        match side {
+            White => CastlingFlags::WK,
+            Black => CastlingFlags::BK,
+        }
+    }
+
+    pub fn queenside(side: Colour) -> CastlingFlags {
+        match side {
+            White => CastlingFlags::WQ,
+            Black => CastlingFlags::BQ,
+        }
+    }
+
+    // position of a single right in Position::castling_rooks
+    pub fn index(self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+}
+
 impl Display for CastlingFlags {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let mut castling = String::new();
 
         if self.is_empty() {
@@ -119,7 +237,7 @@ pub enum Colour {
 }
 
 impl Display for Colour {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let c = match self {
             White => "w",
             Black => "b",
@@ -154,10 +272,45 @@ impl Piece {
         pieces.into_iter()
     }
 
+    pub fn colour(self) -> Colour {
+        match self {
+            Pawn(c) | Knight(c) | Bishop(c) | Rook(c) | Queen(c) | King(c) => c,
+        }
+    }
+
+    // the Unicode chess symbol, white pieces being the outlined ones
+    pub fn glyph(self) -> char {
+        match self {
+            Pawn(White) => '♙',
+            Knight(White) => '♘',
+            Bishop(White) => '♗',
+            Rook(White) => '♖',
+            Queen(White) => '♕',
+            King(White) => '♔',
+            Pawn(Black) => '♟',
+            Knight(Black) => '♞',
+            Bishop(Black) => '♝',
+            Rook(Black) => '♜',
+            Queen(Black) => '♛',
+            King(Black) => '♚',
+        }
+    }
+
+    pub fn swap_colour(self) -> Piece {
+        match self {
+            Pawn(c) => Pawn(!c),
+            Knight(c) => Knight(!c),
+            Bishop(c) => Bishop(!c),
+            Rook(c) => Rook(!c),
+            Queen(c) => Queen(!c),
+            King(c) => King(!c),
+        }
+    }
+
 }
 
 impl Display for Piece {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let p = match self {
             Pawn(White) => "P",
             Knight(White) => "N",
@@ -318,3 +471,61 @@ impl<T> IndexMut<Colour> for [T; 2] {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::evaluate, perft::perft_nodes};
+
+    #[test]
+    fn flipped_test() {
+        let position = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K1R1 w Qkq - 3 7");
+        let flipped = position.flipped();
+        assert_eq!(flipped.write_fen(), "r3k1r1/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b KQq - 3 7");
+        assert_eq!(flipped.hash, Position::from_fen(&flipped.write_fen()).hash);
+        assert_eq!(flipped.flipped(), position);
+
+        let position = Position::from_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+        assert_eq!(position.flipped().write_fen(), "4k3/8/8/3pP3/2K5/8/8/8 w - d6 0 1");
+    }
+
+    #[test]
+    fn flipped_is_symmetric() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkb1r/pp1p1ppp/2p5/4P3/2B5/8/PPP1NnPP/RNBQK2R w KQkq - 0 6",
+        ] {
+            let mut position = Position::from_fen(fen);
+            let mut flipped = position.flipped();
+            assert_eq!(evaluate(&position), evaluate(&flipped));
+            assert_eq!(perft_nodes(&mut position, 3), perft_nodes(&mut flipped, 3));
+        }
+    }
+
+    #[test]
+    fn pretty_board() {
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 b Q - 3 40");
+        let ascii = position.to_string();
+        assert!(ascii.starts_with("  +---+---+---+---+---+---+---+---+\n8 |   |   |   |   | k |"));
+        assert!(ascii.contains("1 | R |   |   |   | K |   |   |   |\n"));
+        assert!(ascii.contains("    a   b   c   d   e   f   g   h \n"));
+        assert!(ascii.contains("\nFen: 4k3/8/8/8/8/8/8/R3K3 b Q - 3 40\n"));
+        assert!(ascii.contains(&format!("\nKey: {:016X}\n", position.hash)));
+        assert!(ascii.contains("\nSide to move: Black\nCastling: Q\nEn passant: -\n"));
+
+        let flipped = position.pretty(BoardStyle { unicode: true, from_black: true });
+        assert!(flipped.contains("1 |   |   |   | ♔ |   |   |   | ♖ |\n"));
+        assert!(flipped.contains("8 |   |   |   | ♚ |   |   |   |   |\n"));
+        assert!(flipped.contains("    h   g   f   e   d   c   b   a \n"));
+        assert!(flipped.find("1 |") < flipped.find("8 |"));
+    }
+
+    #[test]
+    fn pretty_board_state() {
+        let position = Position::from_fen("k7/8/8/8/8/8/8/R3K3 b Q - 3 40");
+        assert!(position.to_string().contains("\nHalfmove clock: 3\nCheckers: a1\n"));
+
+        // without kings there's nothing to be in check
+        assert!(Position::new().to_string().ends_with("\nCheckers: \n"));
+    }
+}
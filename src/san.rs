@@ -0,0 +1,128 @@
+// Standard algebraic notation, as used by EPD bm/am operations and PGN movetext.
+
+use crate::prelude::*;
+use crate::{
+    movegen::{Move, MoveKind},
+    position::{Piece::*, Position},
+};
+
+impl Position {
+    // the move must be legal in this position
+    pub fn san(&self, mv: Move) -> String {
+        let mut san = match mv.kind {
+            MoveKind::Castling(rook, _) if rook.file() > mv.from.file() => "O-O".to_string(),
+            MoveKind::Castling(..) => "O-O-O".to_string(),
+            _ => self.san_without_check(mv),
+        };
+
+        let mut next = *self;
+        next.make_move(mv);
+        if next.is_check(next.turn) {
+            san.push(if next.has_legal_move() { '+' } else { '#' });
+        }
+
+        san
+    }
+
+    fn san_without_check(&self, mv: Move) -> String {
+        let capture = matches!(
+            mv.kind,
+            MoveKind::Capture(_) | MoveKind::PromotionCapture(..) | MoveKind::EnPassant
+        );
+        let mut san = String::new();
+
+        if let Pawn(_) = mv.piece {
+            if capture {
+                san.push_str(&format!("{}x", mv.from.file()));
+            }
+            san.push_str(&mv.to.to_string());
+            if let MoveKind::Promotion(p) | MoveKind::PromotionCapture(p, _) = mv.kind {
+                san.push_str(&format!("={}", p.to_string().to_uppercase()));
+            }
+            return san;
+        }
+
+        san.push_str(&mv.piece.to_string().to_uppercase());
+
+        // name the from file, else rank, else both when another piece of the same kind can go there
+        let others: Vec<Move> = self
+            .gen_moves()
+            .filter(|other| other.piece == mv.piece && other.to == mv.to && other.from != mv.from)
+            .filter(|&other| self.is_legal(other))
+            .collect();
+        if !others.is_empty() {
+            if others.iter().all(|other| other.from.file() != mv.from.file()) {
+                san.push_str(&mv.from.file().to_string());
+            } else if others.iter().all(|other| other.from.rank() != mv.from.rank()) {
+                san.push_str(&mv.from.rank().to_string());
+            } else {
+                san.push_str(&mv.from.to_string());
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+
+        san
+    }
+
+    // accepts check and annotation marks whether or not they're right, and 0-0 for O-O
+    pub fn find_san_move(&self, san: &str) -> Option<Move> {
+        let wanted = strip_san(san);
+        self.gen_moves()
+            .filter(|&mv| self.is_legal(mv))
+            .find(|&mv| strip_san(&self.san(mv)) == wanted)
+    }
+}
+
+// drops check and annotation marks and reads 0-0 as O-O, so two SANs for a move compare equal
+pub(crate) fn strip_san(san: &str) -> String {
+    san.trim_end_matches(['+', '#', '!', '?']).replace('0', "O")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    fn san(fen: &str, mv: &str) -> String {
+        let position = Position::from_fen(fen);
+        position.san(position.find_algebraic_move(mv).unwrap())
+    }
+
+    #[test]
+    fn san_test() {
+        assert_eq!(san(STARTING_FEN, "e2e4"), "e4");
+        assert_eq!(san(STARTING_FEN, "g1f3"), "Nf3");
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san(kiwipete, "e2a6"), "Bxa6");
+        assert_eq!(san(kiwipete, "c3b1"), "Nb1");
+        assert_eq!(san(kiwipete, "a1b1"), "Rb1");
+
+        assert_eq!(san("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1", "e4d3"), "exd3");
+        assert_eq!(san("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", "g2h1q"), "gxh1=Q");
+        assert_eq!(san("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", "g2g1n"), "g1=N+");
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K2R w - - 0 1", "a1a8"), "Ra8+");
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8#");
+        assert_eq!(san("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", "a4a2"), "R4a2");
+        assert_eq!(san("1k6/8/8/8/7Q/8/8/K3Q2Q w - - 0 1", "h4e4"), "Q4e4");
+        assert_eq!(san("1k6/8/8/8/7Q/8/8/K3Q2Q w - - 0 1", "h1e4"), "Qh1e4");
+    }
+
+    #[test]
+    fn find_san_test() {
+        let position = Position::from_fen(STARTING_FEN);
+        assert_eq!(position.find_san_move("Nf3").unwrap().to_string(), "g1f3");
+        assert_eq!(position.find_san_move("e4!").unwrap().to_string(), "e2e4");
+        assert!(position.find_san_move("Ke2").is_none());
+
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(position.find_san_move("0-0-0").unwrap().to_string(), "e1c1");
+    }
+}
@@ -1,21 +1,28 @@
 use std::{
     cmp::{max, min},
-    time::Instant,
+    fmt::Display,
+    panic,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use crossbeam_channel::{Receiver, SendError, Sender};
+use crossbeam_channel::{unbounded, Receiver, SendError, Sender};
 
 use crate::{
+    attacks::{bishop_attacks, knight_attacks, pawn_attacks, ray, rook_attacks},
     bitboard::{Bitboard, Square},
-    engine::MAX_GAME_PLY,
     eval::evaluate,
-    magic::{bishop_attacks, rook_attacks},
-    movegen::{knight_attacks, pawn_attacks, Move, MoveKind, MoveList},
+    game::{Game, MAX_GAME_PLY},
+    make_move::DirtyPieces,
+    movegen::{GenType, Move, MoveKind, MoveList, MoveOrderer},
+    phases::{timed, Phase},
     position::{
         Colour::*,
         Piece::{self, *},
         Position,
     },
+    tt::{pack_move, score_from_tt, score_to_tt, Bound, TranspositionTable, TtEntry},
 };
 
 pub const MAX_DEPTH: usize = 64;
@@ -23,9 +30,33 @@ const PV_SIZE: usize = MAX_DEPTH * (MAX_DEPTH + 1) / 2;
 const STALEMATE: i32 = 0;
 pub const CHECKMATE: i32 = 1_000_000;
 const UNRAVEL: i32 = CHECKMATE + 1;
-const HALFMOVE_DRAW_COUNT: u8 = 100;
+// from this depth each iteration first searches this close to the last one's score
+const ASPIRATION_DEPTH: u8 = 4;
+const ASPIRATION_WINDOW: i32 = 50;
+// between progress reports on iterations that take a while
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
 
-type SendResult = Result<(), SendError<SendInfo>>;
+// nothing is left to receive the search's info, so there's no one to search for
+#[derive(Debug)]
+pub enum SearchError {
+    Disconnected,
+}
+
+impl Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::Disconnected => write!(f, "search info receiver disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+impl From<SendError<SendInfo>> for SearchError {
+    fn from(_: SendError<SendInfo>) -> Self {
+        SearchError::Disconnected
+    }
+}
 
 #[derive(Debug)]
 pub enum SearchCommand {
@@ -35,11 +66,83 @@ pub enum SearchCommand {
 
 #[derive(Debug)]
 pub enum SendInfo {
-    Full(FullInfo),
+    Full(Box<FullInfo>),
     CurrMove(CurrMoveInfo),
+    Progress(ProgressInfo),
+    Stats(StatsInfo),
     Done(Option<Move>),
 }
 
+// receives a search's reports as it goes. a Sender<SendInfo> is one, passing each report on down
+// the channel, but embedders can take them as calls instead of matching on SendInfo. an error from
+// on_depth_complete, on_stats or on_finished ends the search with it
+pub trait SearchListener {
+    // an iteration finished, with its score and principal variation
+    fn on_depth_complete(&mut self, info: FullInfo) -> Result<(), SearchError>;
+
+    // a root move is about to be searched
+    fn on_currmove(&mut self, _info: CurrMoveInfo) -> Result<(), SearchError> {
+        Ok(())
+    }
+
+    // every PROGRESS_INTERVAL during long iterations
+    fn on_progress(&mut self, _info: ProgressInfo) -> Result<(), SearchError> {
+        Ok(())
+    }
+
+    // after each iteration, only when searching with debug on
+    fn on_stats(&mut self, _info: StatsInfo) -> Result<(), SearchError> {
+        Ok(())
+    }
+
+    // the search is over, this is always the last call
+    fn on_finished(&mut self, best: Option<Move>) -> Result<(), SearchError>;
+}
+
+impl<L: SearchListener + ?Sized> SearchListener for &mut L {
+    fn on_depth_complete(&mut self, info: FullInfo) -> Result<(), SearchError> {
+        (**self).on_depth_complete(info)
+    }
+
+    fn on_currmove(&mut self, info: CurrMoveInfo) -> Result<(), SearchError> {
+        (**self).on_currmove(info)
+    }
+
+    fn on_progress(&mut self, info: ProgressInfo) -> Result<(), SearchError> {
+        (**self).on_progress(info)
+    }
+
+    fn on_stats(&mut self, info: StatsInfo) -> Result<(), SearchError> {
+        (**self).on_stats(info)
+    }
+
+    fn on_finished(&mut self, best: Option<Move>) -> Result<(), SearchError> {
+        (**self).on_finished(best)
+    }
+}
+
+impl SearchListener for Sender<SendInfo> {
+    fn on_depth_complete(&mut self, info: FullInfo) -> Result<(), SearchError> {
+        Ok(self.send(SendInfo::Full(Box::new(info)))?)
+    }
+
+    fn on_currmove(&mut self, info: CurrMoveInfo) -> Result<(), SearchError> {
+        Ok(self.send(SendInfo::CurrMove(info))?)
+    }
+
+    fn on_progress(&mut self, info: ProgressInfo) -> Result<(), SearchError> {
+        Ok(self.send(SendInfo::Progress(info))?)
+    }
+
+    fn on_stats(&mut self, info: StatsInfo) -> Result<(), SearchError> {
+        Ok(self.send(SendInfo::Stats(info))?)
+    }
+
+    fn on_finished(&mut self, best: Option<Move>) -> Result<(), SearchError> {
+        Ok(self.send(SendInfo::Done(best))?)
+    }
+}
+
 #[derive(Debug)]
 pub struct FullInfo {
     pub depth: u8,
@@ -58,28 +161,236 @@ pub struct CurrMoveInfo {
     pub time: u32,
 }
 
+// nodes and time so far in the current iteration
+#[derive(Debug)]
+pub struct ProgressInfo {
+    pub nodes: u32,
+    pub time: u32,
+    // in permille
+    pub hashfull: u16,
+}
+
+// counted over each iteration for the diagnostics sent with debug on
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SearchStats {
+    pub qnodes: u32,
+    pub cutoffs: u32,
+    pub first_move_cutoffs: u32,
+    pub tt_probes: u32,
+    pub tt_hits: u32,
+    // searches again with the full window after the aspiration window failed
+    pub re_searches: u32,
+}
+
+#[derive(Debug)]
+pub struct StatsInfo {
+    pub depth: u8,
+    pub nodes: u32,
+    pub previous_nodes: u32,
+    pub stats: SearchStats,
+}
+
+impl StatsInfo {
+    // how many times more nodes this iteration took than the last
+    pub fn branching_factor(&self) -> f64 {
+        self.nodes as f64 / self.previous_nodes.max(1) as f64
+    }
+
+    // of the beta cutoffs, how many came from the first move searched
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        self.stats.first_move_cutoffs as f64 / self.stats.cutoffs.max(1) as f64
+    }
+
+    pub fn qnode_share(&self) -> f64 {
+        self.stats.qnodes as f64 / self.nodes.max(1) as f64
+    }
+
+    pub fn tt_hit_rate(&self) -> f64 {
+        self.stats.tt_hits as f64 / self.stats.tt_probes.max(1) as f64
+    }
+}
+
+impl Display for StatsInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "depth {} ebf {:.2} tt hits {:.1}% first move cutoffs {:.1}% qnodes {:.1}% re-searches {}",
+            self.depth,
+            self.branching_factor(),
+            self.tt_hit_rate() * 100.0,
+            self.first_move_cutoff_rate() * 100.0,
+            self.qnode_share() * 100.0,
+            self.stats.re_searches
+        )
+    }
+}
+
+// every limit that's set applies, the search stops at whichever comes first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchLimits {
+    pub nodes: u32,
+    pub depth: u8,
+    pub movetime: u32,
+}
+
+impl SearchLimits {
+    pub fn new() -> Self {
+        Self {
+            nodes: u32::MAX,
+            depth: MAX_DEPTH as u8,
+            movetime: 0,
+        }
+    }
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// everything a finished search found. score, depth and pv come from the last iteration to
+// complete, nodes and time cover the whole search
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    // the reply the search expects, when the pv goes that far
+    pub ponder_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+    // in ms
+    pub time: u32,
+}
+
+impl SearchListener for SearchResult {
+    fn on_depth_complete(&mut self, info: FullInfo) -> Result<(), SearchError> {
+        self.score = info.score;
+        self.depth = info.depth;
+        self.nodes += info.nodes as u64;
+        // refilled rather than replaced, so later iterations reuse the allocation
+        self.pv.clear();
+        self.pv.extend(info.pv.iter().map_while(|&mv| mv));
+        Ok(())
+    }
+
+    fn on_finished(&mut self, best: Option<Move>) -> Result<(), SearchError> {
+        self.best_move = best;
+        self.ponder_move = match self.pv[..] {
+            [first, reply, ..] if Some(first) == best => Some(reply),
+            _ => None,
+        };
+        Ok(())
+    }
+}
+
+// a search running on a thread of its own, so its caller can get on with something else
+pub struct SearchHandle {
+    stop_tx: Sender<SearchCommand>,
+    thread: JoinHandle<SearchResult>,
+}
+
+impl SearchHandle {
+    // the search still finishes with a result, from the iterations it completed
+    pub fn stop(&self) {
+        _ = self.stop_tx.send(SearchCommand::Stop);
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    // waits for the search to finish, which without a stop is whenever it reaches its limits
+    pub fn join(self) -> SearchResult {
+        self.thread.join().unwrap_or_else(|e| panic::resume_unwind(e))
+    }
+}
+
+// runs a search on this thread, returning once it reaches its limits. the table is kept by the
+// caller, so a game's searches build on each other
+pub fn search_blocking(game: &Game, limits: SearchLimits, tt: &TranspositionTable, debug: bool) -> SearchResult {
+    let (stop_tx, stop_rx) = unbounded();
+    search_until_stopped(game, limits, tt, debug, stop_tx, stop_rx)
+}
+
+// the search keeps its share of the table until it's done, whatever its owner does with theirs
+pub fn start_search(game: &Game, limits: SearchLimits, tt: Arc<TranspositionTable>, debug: bool) -> SearchHandle {
+    let (stop_tx, stop_rx) = unbounded();
+    let game = game.clone();
+    let timer_tx = stop_tx.clone();
+    let thread = thread::spawn(move || {
+        search_until_stopped(&game, limits, &tt, debug, timer_tx, stop_rx)
+    });
+    SearchHandle { stop_tx, thread }
+}
+
+fn search_until_stopped(
+    game: &Game,
+    limits: SearchLimits,
+    tt: &TranspositionTable,
+    debug: bool,
+    stop_tx: Sender<SearchCommand>,
+    stop_rx: Receiver<SearchCommand>,
+) -> SearchResult {
+    // the timer outliving the search is harmless, its channel is gone by then
+    if limits.movetime != 0 {
+        let movetime = limits.movetime;
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(movetime as u64));
+            let _ = stop_tx.send(SearchCommand::Stop);
+        });
+    }
+
+    let start_time = Instant::now();
+    let mut result = SearchResult::default();
+    tt.new_search();
+    // collecting the result can't fail
+    _ = iterative_deepening(
+        *game.position(),
+        limits.depth,
+        limits.nodes,
+        game.hash_history(),
+        tt,
+        &mut result,
+        stop_rx,
+        debug,
+    );
+    result.time = start_time.elapsed().as_millis() as u32;
+    result
+}
+
+// the search's own state, only ever seen by the search. callers hear from its listener instead
 #[derive(Debug)]
-pub struct SearchInfo {
+struct SearchInfo<'a, L> {
     pub depth: u8,
     pub seldepth: u8,
     pub score: i32,
     pub nodes: u32,
     pub stop_nodes: u32,
     pub time: Instant,
+    pub last_progress: Instant,
     pub triangular_pv: [Option<Move>; PV_SIZE],
     pub current_branch: [Option<Move>; MAX_DEPTH],
     pub history: [u64; MAX_GAME_PLY],
-    pub tx: Sender<SendInfo>,
+    pub tt: &'a TranspositionTable,
+    // the move the table has for the node at each ply, packed, for move ordering
+    pub tt_moves: [u16; MAX_DEPTH],
+    pub listener: L,
     pub rx: Receiver<SearchCommand>,
     pub stop: bool,
+    pub debug: bool,
+    pub stats: SearchStats,
 }
 
-impl SearchInfo {
+impl<'a, L: SearchListener> SearchInfo<'a, L> {
     fn new(
         stop_nodes: u32,
         history: [u64; MAX_GAME_PLY],
-        tx: Sender<SendInfo>,
+        tt: &'a TranspositionTable,
+        listener: L,
         rx: Receiver<SearchCommand>,
+        debug: bool,
     ) -> Self {
         SearchInfo {
             depth: 0,
@@ -88,16 +399,21 @@ impl SearchInfo {
             nodes: 0,
             stop_nodes,
             time: Instant::now(),
+            last_progress: Instant::now(),
             triangular_pv: [None; PV_SIZE],
             current_branch: [None; MAX_DEPTH],
             history,
-            tx,
+            tt,
+            tt_moves: [0; MAX_DEPTH],
+            listener,
             rx,
             stop: false,
+            debug,
+            stats: SearchStats::default(),
         }
     }
 
-    fn send_full(&mut self) -> SendResult {
+    fn send_full(&mut self) -> Result<(), SearchError> {
         let full = FullInfo {
             depth: self.depth,
             seldepth: self.seldepth,
@@ -107,10 +423,10 @@ impl SearchInfo {
             pv: self.triangular_pv[0..MAX_DEPTH].try_into().unwrap(),
         };
 
-        self.tx.send(SendInfo::Full(full))
+        self.listener.on_depth_complete(full)
     }
 
-    fn send_currmove(&self, mv: Move, mv_num: u8) -> SendResult {
+    fn send_currmove(&mut self, mv: Move, mv_num: u8) -> Result<(), SearchError> {
         let curr_move = CurrMoveInfo {
             depth: self.depth,
             mv,
@@ -118,11 +434,42 @@ impl SearchInfo {
             time: self.time.elapsed().as_millis() as u32,
         };
 
-        self.tx.send(SendInfo::CurrMove(curr_move))
+        self.listener.on_currmove(curr_move)
+    }
+
+    fn send_progress(&mut self) -> Result<(), SearchError> {
+        let progress = ProgressInfo {
+            nodes: self.nodes,
+            time: self.time.elapsed().as_millis() as u32,
+            hashfull: self.tt.hashfull(),
+        };
+
+        self.listener.on_progress(progress)
+    }
+
+    // polled every so many nodes, reports progress when it's due and returns whether to stop
+    fn check_in(&mut self) -> bool {
+        if self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+            self.last_progress = Instant::now();
+            _ = self.send_progress();
+        }
+
+        self.depth > 1 && self.rx.try_recv().is_ok()
+    }
+
+    fn send_stats(&mut self, previous_nodes: u32) -> Result<(), SearchError> {
+        let stats = StatsInfo {
+            depth: self.depth,
+            nodes: self.nodes,
+            previous_nodes,
+            stats: self.stats,
+        };
+
+        self.listener.on_stats(stats)
     }
 
-    fn send_bestmove(&self) -> SendResult {
-        self.tx.send(SendInfo::Done(self.triangular_pv[0]))
+    fn send_bestmove(&mut self) -> Result<(), SearchError> {
+        self.listener.on_finished(self.triangular_pv[0])
     }
 
     fn hoist_pv(&mut self, target: usize, source: usize, len: usize) {
@@ -135,30 +482,56 @@ impl SearchInfo {
     }
 }
 
+impl<L> MoveOrderer for SearchInfo<'_, L> {
+    fn score(&self, mv: &Move, ply: usize) -> u8 {
+        let mut score = mvv_lva(mv);
+        let pv_move = self.current_branch[..ply] == self.triangular_pv[..ply]
+            && self.triangular_pv[ply].is_some_and(|pv_mv| *mv == pv_mv);
+        if pv_move || (self.tt_moves[ply] != 0 && self.tt_moves[ply] == pack_move(*mv)) {
+            score += 100;
+        }
+
+        score
+    }
+}
+
+pub struct MvvLva;
+
+impl MoveOrderer for MvvLva {
+    fn score(&self, mv: &Move, _ply: usize) -> u8 {
+        mvv_lva(mv)
+    }
+}
+
+// the table is kept from earlier searches, which it can still help
+#[allow(clippy::too_many_arguments)]
 pub fn iterative_deepening(
     mut pos: Position,
     stop_depth: u8,
     stop_nodes: u32,
     history: [u64; MAX_GAME_PLY],
-    tx: Sender<SendInfo>,
+    tt: &TranspositionTable,
+    listener: impl SearchListener,
     rx: Receiver<SearchCommand>,
-) {
-    // clear receiver in case stop sent from previous search
-    for _ in rx.try_iter() {
-        print!("");
-    }
-
-    let mut info = SearchInfo::new(stop_nodes, history, tx, rx);
+    debug: bool,
+) -> Result<(), SearchError> {
+    let mut info = SearchInfo::new(stop_nodes, history, tt, listener, rx, debug);
+    let mut previous_nodes = 0;
 
     for depth in 1..=stop_depth {
         info.time = Instant::now();
         info.depth = depth;
         info.nodes = 0;
-        info.score = negamax(&mut pos, -i32::MAX, i32::MAX, depth, 0, 0, &mut info);
+        info.stats = SearchStats::default();
+        info.score = aspiration_search(&mut pos, depth, &mut info);
 
         if info.score < UNRAVEL {
-            info.send_full().unwrap();
+            info.send_full()?;
+            if info.debug {
+                info.send_stats(previous_nodes)?;
+            }
         }
+        previous_nodes = info.nodes;
 
         if CHECKMATE - info.score.abs() <= depth as i32 {
             break;
@@ -169,7 +542,37 @@ pub fn iterative_deepening(
         }
     }
 
-    info.send_bestmove().unwrap();
+    info.send_bestmove()?;
+    Ok(())
+}
+
+// a window around the last iteration's score cuts more, a score outside it isn't exact so that
+// side of the window is opened and the iteration searched again. negamax leaves the position
+// behind a move that failed high, so each attempt starts from a copy of the root
+fn aspiration_search(pos: &mut Position, depth: u8, info: &mut SearchInfo<impl SearchListener>) -> i32 {
+    let (mut alpha, mut beta) = (-i32::MAX, i32::MAX);
+    if depth >= ASPIRATION_DEPTH && info.score.abs() < CHECKMATE - MAX_DEPTH as i32 {
+        alpha = info.score - ASPIRATION_WINDOW;
+        beta = info.score + ASPIRATION_WINDOW;
+    }
+
+    let root = *pos;
+    loop {
+        let score = negamax(pos, alpha, beta, depth, 0, 0, info);
+        *pos = root;
+        if info.stop || info.nodes >= info.stop_nodes {
+            return score;
+        }
+
+        if score <= alpha && alpha != -i32::MAX {
+            alpha = -i32::MAX;
+        } else if score >= beta && beta != i32::MAX {
+            beta = i32::MAX;
+        } else {
+            return score;
+        }
+        info.stats.re_searches += 1;
+    }
 }
 
 fn negamax(
@@ -179,9 +582,9 @@ fn negamax(
     depth: u8,
     ply: usize,
     pv_idx: usize,
-    info: &mut SearchInfo,
+    info: &mut SearchInfo<impl SearchListener>,
 ) -> i32 {
-    if info.depth > 1 && info.nodes % 10_000 == 0 && info.rx.try_recv().is_ok() {
+    if info.nodes.is_multiple_of(10_000) && info.check_in() {
         info.stop = true;
         return UNRAVEL;
     }
@@ -190,7 +593,12 @@ fn negamax(
         info.seldepth = ply as u8;
     }
 
-    if pos.halfmove >= HALFMOVE_DRAW_COUNT || detect_repetition(pos, info.history, ply < 2) {
+    // a single repetition is enough inside the tree, near the root it takes a real threefold
+    let repetition_limit = if ply < 2 { 2 } else { 1 };
+    if pos.is_fifty_move_draw()
+        || pos.repetitions(&info.history) >= repetition_limit
+        || pos.is_insufficient_material()
+    {
         return STALEMATE;
     }
 
@@ -198,21 +606,38 @@ fn negamax(
         return quiescence_search(pos, alpha, beta, ply, info);
     }
 
+    // only bounds that fail high or low are taken, a score inside the window would need a pv.
+    // the root always searches, for its move
+    let entry = timed(Phase::Tt, || info.tt.probe(pos.hash));
+    info.stats.tt_probes += 1;
+    info.stats.tt_hits += entry.is_some() as u32;
+    info.tt_moves[ply] = entry.map_or(0, |entry| entry.mv);
+    if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+        let score = score_from_tt(entry.score, ply);
+        match entry.bound {
+            Bound::Exact | Bound::Lower if score >= beta => return beta,
+            Bound::Exact | Bound::Upper if score <= alpha => return alpha,
+            _ => (),
+        }
+    }
+
+    let original_alpha = alpha;
     let next_pv_idx = pv_idx + MAX_DEPTH - ply;
 
-    let mut moves = pos.gen_moves();
+    let mut moves = timed(Phase::MoveGen, || pos.gen_moves());
     moves.score(ply, info);
 
     let mut legal_moves = 0;
+    let tt = info.tt;
     for mv in moves {
-        let prev = pos.make_move(mv);
+        let prev = timed(Phase::MakeMove, || pos.make_move_prefetch(mv, &mut DirtyPieces::new(), |hash| tt.prefetch(hash)));
         if pos.is_check(prev.turn) {
             *pos = prev;
             continue;
         }
 
         info.current_branch[ply] = Some(mv);
-        info.history[prev.ply as usize] = prev.hash;
+        info.history[prev.ply as usize % MAX_GAME_PLY] = prev.hash;
         legal_moves += 1;
 
         if ply == 0 {
@@ -228,6 +653,11 @@ fn negamax(
         info.nodes += 1;
 
         if score >= beta {
+            info.stats.cutoffs += 1;
+            if legal_moves == 1 {
+                info.stats.first_move_cutoffs += 1;
+            }
+            store(info, prev.hash, Some(mv), beta, depth, ply, Bound::Lower);
             return beta;
         }
 
@@ -248,17 +678,35 @@ fn negamax(
         }
     }
 
+    if alpha > original_alpha {
+        store(info, pos.hash, info.triangular_pv[pv_idx], alpha, depth, ply, Bound::Exact);
+    } else {
+        store(info, pos.hash, None, alpha, depth, ply, Bound::Upper);
+    }
     alpha
 }
 
+fn store(
+    info: &mut SearchInfo<impl SearchListener>,
+    key: u64,
+    mv: Option<Move>,
+    score: i32,
+    depth: u8,
+    ply: usize,
+    bound: Bound,
+) {
+    let entry = TtEntry { mv: mv.map_or(0, pack_move), score: score_to_tt(score, ply), depth, bound };
+    timed(Phase::Tt, || info.tt.store(key, entry));
+}
+
 fn quiescence_search(
     pos: &mut Position,
     mut alpha: i32,
     beta: i32,
     ply: usize,
-    info: &mut SearchInfo,
+    info: &mut SearchInfo<impl SearchListener>,
 ) -> i32 {
-    if info.depth > 1 && info.nodes % 10_000 == 0 && info.rx.try_recv().is_ok() {
+    if info.nodes.is_multiple_of(10_000) && info.check_in() {
         info.stop = true;
         return UNRAVEL;
     }
@@ -266,8 +714,10 @@ fn quiescence_search(
     if ply as u8 > info.seldepth {
         info.seldepth = ply as u8;
     }
+    // captures are ordered without the table
+    info.tt_moves[ply] = 0;
 
-    let standing_pat = evaluate(pos);
+    let standing_pat = timed(Phase::Eval, || evaluate(pos));
     if standing_pat >= beta {
         return beta;
     }
@@ -277,7 +727,7 @@ fn quiescence_search(
     }
 
     let mut captures = MoveList::new();
-    pos.gen_captures(&mut captures);
+    timed(Phase::MoveGen, || pos.gen(&mut captures, GenType::CAPTURES));
     captures.score(ply, info);
 
     for capture in captures {
@@ -287,7 +737,7 @@ fn quiescence_search(
             _ => panic!(),
         };
 
-        if static_exchange_evaluation(pos, capture.from, capture.to, capture.piece, target) < 0 {
+        if timed(Phase::See, || static_exchange_evaluation(pos, capture.from, capture.to, capture.piece, target)) < 0 {
             continue;
         }
 
@@ -298,13 +748,14 @@ fn quiescence_search(
             }
         }
 
-        let prev = pos.make_move(capture);
+        let prev = timed(Phase::MakeMove, || pos.make_move(capture));
         if pos.is_check(prev.turn) {
             *pos = prev;
             continue;
         }
 
         info.nodes += 1;
+        info.stats.qnodes += 1;
         let score = -quiescence_search(pos, -beta, -alpha, ply + 1, info);
         *pos = prev;
 
@@ -328,8 +779,33 @@ fn static_exchange_evaluation(
     position: &Position,
     from: Square,
     to: Square,
-    mut attacker: Piece,
+    attacker: Piece,
     target: Piece,
+) -> i32 {
+    swap_off(position, from, to, attacker, target.value(), None)
+}
+
+// the exchange on a move's square with the pieces that take part, first the mover, in order. a
+// quiet move starts with nothing captured, so only says whether the piece is lost
+pub fn see(position: &Position, mv: Move) -> (i32, Vec<(Piece, Square)>) {
+    let captured = match mv.kind {
+        MoveKind::Capture(target) | MoveKind::PromotionCapture(_, target) => target.value(),
+        MoveKind::EnPassant => Pawn(!position.turn).value(),
+        _ => 0,
+    };
+
+    let mut sequence = Vec::new();
+    let gain = swap_off(position, mv.from, mv.to, mv.piece, captured, Some(&mut sequence));
+    (gain, sequence)
+}
+
+fn swap_off(
+    position: &Position,
+    from: Square,
+    to: Square,
+    mut attacker: Piece,
+    captured: i32,
+    mut sequence: Option<&mut Vec<(Piece, Square)>>,
 ) -> i32 {
     let mut gain = [0; 32];
     let mut depth = 0;
@@ -346,15 +822,18 @@ fn static_exchange_evaluation(
     let mut occ = position.occupied();
     let mut removed = Bitboard(0);
 
-    let mut attacks = pawn_attacks(to, White) & position.pieces[Pawn(White)]
-        | pawn_attacks(to, Black) & position.pieces[Pawn(Black)]
+    let mut attacks = pawn_attacks(to, Black) & position.pieces[Pawn(White)]
+        | pawn_attacks(to, White) & position.pieces[Pawn(Black)]
         | knight_attacks(to) & knights
         | bishop_attacks(to, occ) & (bishops | queens)
         | rook_attacks(to, occ) & (rooks | queens);
 
-    gain[depth] = target.value();
+    gain[depth] = captured;
 
     'swap: loop {
+        if let Some(sequence) = sequence.as_mut() {
+            sequence.push((attacker, from_bb.get_lsb().unwrap()));
+        }
         depth += 1;
         side = !side;
 
@@ -364,18 +843,16 @@ fn static_exchange_evaluation(
             break;
         }
 
-        attacks ^= from_bb;
+        // cleared rather than toggled, a pawn push's mover doesn't attack the square
+        attacks &= !from_bb;
         occ ^= from_bb;
         removed |= from_bb;
 
         if may_xray.intersects(from_bb) {
-            if let Rook(_) | Queen(_) = attacker {
-                attacks |= rook_attacks(to, occ) & (rooks | queens) & !removed;
-            }
-
-            if let Pawn(_) | Bishop(_) | Queen(_) = attacker {
-                attacks |= bishop_attacks(to, occ) & (bishops | queens) & !removed;
-            }
+            // only a slider lined up behind the piece that just captured can be revealed
+            let behind = ray(to, from_bb.get_lsb().unwrap()) & !removed;
+            attacks |= rook_attacks(to, occ) & (rooks | queens) & behind;
+            attacks |= bishop_attacks(to, occ) & (bishops | queens) & behind;
         }
 
         for piece in Piece::iter_colour(side) {
@@ -400,25 +877,6 @@ fn static_exchange_evaluation(
     gain[0]
 }
 
-fn detect_repetition(pos: &Position, history: [u64; MAX_GAME_PLY], is_root: bool) -> bool {
-    if pos.ply - pos.last_irreversible_ply < 4 {
-        return false;
-    }
-
-    let mut count = 0;
-    for ply in (pos.last_irreversible_ply..=pos.ply).rev().step_by(2) {
-        if history[ply as usize] == pos.hash {
-            count += 1;
-        }
-
-        if count >= 2 || (count == 1 && !is_root) {
-            return true;
-        }
-    }
-
-    false
-}
-
 pub fn mvv_lva(mv: &Move) -> u8 {
     let attacker = mv.piece;
     let (MoveKind::Capture(victim) | MoveKind::PromotionCapture(_, victim)) = mv.kind else {
@@ -453,17 +911,121 @@ mod tests {
         let eval = static_exchange_evaluation(&position, from, to, Rook(White), Pawn(Black));
         assert_eq!(eval, 82);
 
-        position.read_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -");
+        position.read_fen("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - -").unwrap();
         println!("{position}");
         let from = Square::from_algebraic("d3").unwrap();
         let eval = static_exchange_evaluation(&position, from, to, Knight(White), Pawn(Black));
         assert_eq!(eval, -255);
 
-        position.read_fen("r1bq1r1k/p1pn1pp1/1p2p3/6b1/3PB3/8/PPPQ1PPP/2KR3R w - - 0 2");
+        position.read_fen("r1bq1r1k/p1pn1pp1/1p2p3/6b1/3PB3/8/PPPQ1PPP/2KR3R w - - 0 2").unwrap();
         println!("{position}");
         let from = Square::from_algebraic("d2").unwrap();
         let to = Square::from_algebraic("g5").unwrap();
         let eval = static_exchange_evaluation(&position, from, to, Queen(White), Bishop(Black));
         assert_eq!(eval, -660);
+
+        // a black pawn defends from above the square
+        position.read_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let to = Square::from_algebraic("d5").unwrap();
+        let from = Square::from_algebraic("d1").unwrap();
+        let eval = static_exchange_evaluation(&position, from, to, Queen(White), Pawn(Black));
+        assert_eq!(eval, Pawn(Black).value() - Queen(White).value());
+
+        // and a white one from below
+        position.read_fen("3qk3/8/8/3P4/2P5/8/8/4K3 b - - 0 1").unwrap();
+        let from = Square::from_algebraic("d8").unwrap();
+        let eval = static_exchange_evaluation(&position, from, to, Queen(Black), Pawn(White));
+        assert_eq!(eval, Pawn(White).value() - Queen(Black).value());
+    }
+
+    #[test]
+    fn see_test() {
+        // the pawn recaptures, before the queen
+        let position = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let (gain, sequence) = see(&position, position.find_algebraic_move("d5e6").unwrap());
+        assert_eq!(gain, 0);
+        assert_eq!(sequence, [(Pawn(White), Square::from_algebraic("d5").unwrap()), (Pawn(Black), Square::from_algebraic("d7").unwrap())]);
+
+        // a quiet move onto a square a pawn guards
+        let (gain, _) = see(&position, position.find_algebraic_move("d5d6").unwrap());
+        assert_eq!(gain, -82);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        // depth, nodes and pv of each iteration
+        iterations: Vec<(u8, u32, [Option<Move>; MAX_DEPTH])>,
+        stats: Vec<SearchStats>,
+        best: Option<Option<Move>>,
+    }
+
+    impl SearchListener for Recorder {
+        fn on_depth_complete(&mut self, info: FullInfo) -> Result<(), SearchError> {
+            assert!(self.best.is_none());
+            self.iterations.push((info.depth, info.nodes, info.pv));
+            Ok(())
+        }
+
+        fn on_stats(&mut self, info: StatsInfo) -> Result<(), SearchError> {
+            self.stats.push(info.stats);
+            Ok(())
+        }
+
+        fn on_finished(&mut self, best: Option<Move>) -> Result<(), SearchError> {
+            self.best = Some(best);
+            Ok(())
+        }
+    }
+
+    fn record(fen: &str, depth: u8) -> Recorder {
+        let (_stop_tx, stop_rx) = crossbeam_channel::unbounded();
+        let mut recorder = Recorder::default();
+        let tt = TranspositionTable::new(1);
+        iterative_deepening(Position::from_fen(fen), depth, u32::MAX, [0; MAX_GAME_PLY], &tt, &mut recorder, stop_rx, true)
+            .unwrap();
+        recorder
+    }
+
+    #[test]
+    fn listener_test() {
+        let recorder = record("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", 3);
+        let depths: Vec<u8> = recorder.iterations.iter().map(|(depth, ..)| *depth).collect();
+        assert_eq!(depths, [1, 2, 3]);
+        assert!(recorder.best.unwrap().is_some());
+    }
+
+    #[test]
+    fn search_is_reproducible() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let first = record(fen, 4);
+        let second = record(fen, 4);
+        assert_eq!(first.iterations, second.iterations);
+        assert_eq!(first.best, second.best);
+    }
+
+    #[test]
+    fn stats_test() {
+        let recorder = record("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5);
+        assert_eq!(recorder.stats.len(), recorder.iterations.len());
+        for stats in &recorder.stats {
+            assert!(stats.tt_probes > 0 && stats.tt_hits <= stats.tt_probes);
+            assert!(stats.first_move_cutoffs <= stats.cutoffs);
+        }
+        // the first iterations search the full window
+        assert!(recorder.stats[..ASPIRATION_DEPTH as usize - 1].iter().all(|stats| stats.re_searches == 0));
+        // later ones find the table filled by the earlier ones
+        assert!(recorder.stats[4].tt_hits > 0);
+    }
+
+    #[test]
+    fn fail_high_re_search_keeps_root() {
+        // the root fails high above the window at depth 4 and searches again
+        let fen = "R7/2p2ppk/2p4p/p1P5/r1P4P/6P1/5PK1/8 w - - 0 33";
+        let recorder = record(fen, 4);
+        assert!(recorder.stats.iter().any(|stats| stats.re_searches > 0));
+        let position = Position::from_fen(fen);
+        let legal = |mv: Move| position.gen_moves().into_iter().any(|legal| legal == mv) && position.is_legal(mv);
+        assert!(recorder.iterations.iter().all(|(_, _, pv)| legal(pv[0].unwrap())));
+        assert!(legal(recorder.best.unwrap().unwrap()));
     }
 }
@@ -0,0 +1,247 @@
+// Games of the engine against itself from a set of openings, adjudicated and written out as PGN,
+// for regression testing and generating training data.
+
+use std::fs;
+
+use log::error;
+
+use crate::{
+    epd::EpdRecord,
+    fen::STARTING_FEN,
+    game::Game,
+    logging::Output,
+    movegen::Move,
+    outcome::{DrawReason, Outcome},
+    position::{Colour, Position},
+    search::{search_blocking, SearchLimits},
+    send,
+    tt::{TranspositionTable, DEFAULT_HASH_MB},
+};
+
+// when a game is called before it's over, going by the scores each side's search gives its own
+// moves. there are no tablebases to adjudicate with, but insufficient material already ends games
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjudication {
+    // a side resigns once it's scored at most -resign_score for resign_moves of its moves in a row
+    pub resign_moves: usize,
+    pub resign_score: i32,
+    // a draw once both sides have scored within draw_score of zero for draw_moves moves each,
+    // counting from draw_after moves into the game. 0 moves turns either off
+    pub draw_after: usize,
+    pub draw_moves: usize,
+    pub draw_score: i32,
+    // and a draw whatever the scores once the game is this long
+    pub max_plies: usize,
+}
+
+impl Adjudication {
+    pub fn new() -> Self {
+        Self {
+            resign_moves: 4,
+            resign_score: 1000,
+            draw_after: 40,
+            draw_moves: 8,
+            draw_score: 10,
+            max_plies: 400,
+        }
+    }
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct SelfPlayOptions {
+    pub games: usize,
+    pub limits: SearchLimits,
+    // an EPD file of start positions, played in turn
+    pub openings: Option<String>,
+    pub output: String,
+    pub adjudication: Adjudication,
+}
+
+impl SelfPlayOptions {
+    pub fn new() -> Self {
+        Self {
+            games: 1,
+            // enough for a decent move while keeping games quick to generate
+            limits: SearchLimits { nodes: 20_000, ..SearchLimits::new() },
+            openings: None,
+            output: "selfplay.pgn".to_string(),
+            adjudication: Adjudication::new(),
+        }
+    }
+}
+
+impl Default for SelfPlayOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn selfplay(out: &Output, options: &SelfPlayOptions) {
+    let Some(openings) = read_openings(options.openings.as_deref()) else {
+        return;
+    };
+
+    let mut pgn = String::new();
+    let mut results = [0; 3];
+    // both sides are the same engine, so they can share what they've searched
+    let tt = TranspositionTable::new(DEFAULT_HASH_MB);
+    for round in 1..=options.games {
+        let start = openings[(round - 1) % openings.len()];
+        tt.clear();
+        let (game, result, termination) = play_game(start, &options.adjudication, |game| search(game, options.limits, &tt));
+        match result {
+            "1-0" => results[0] += 1,
+            "0-1" => results[1] += 1,
+            _ => results[2] += 1,
+        }
+        send!(out, "info string game {} {} ({}) after {} plies", round, result, termination, game.moves().len());
+
+        let tags = [
+            ("Event", "Blunderbuss self-play".to_string()),
+            ("Round", round.to_string()),
+            ("White", "Blunderbuss".to_string()),
+            ("Black", "Blunderbuss".to_string()),
+            ("Termination", termination),
+        ];
+        pgn.push_str(&game.pgn(&tags, result));
+    }
+
+    if let Err(e) = fs::write(&options.output, pgn) {
+        error!("writing {}: {}", options.output, e);
+        return;
+    }
+    send!(out, "info string +{} -{} ={} written to {}", results[0], results[1], results[2], options.output);
+}
+
+// the standard start position without a file, None after reporting a bad one
+pub fn read_openings(path: Option<&str>) -> Option<Vec<Position>> {
+    let Some(path) = path else {
+        return Some(vec![Position::from_fen(STARTING_FEN)]);
+    };
+
+    match read_epd_positions(path) {
+        Ok(openings) => Some(openings),
+        Err(e) => {
+            error!("reading openings: {}", e);
+            None
+        }
+    }
+}
+
+fn read_epd_positions(path: &str) -> Result<Vec<Position>, Box<dyn std::error::Error>> {
+    let mut openings = Vec::new();
+    for line in fs::read_to_string(path)?.lines().filter(|line| !line.trim().is_empty()) {
+        openings.push(EpdRecord::parse(line)?.position);
+    }
+
+    if openings.is_empty() {
+        return Err("no positions".into());
+    }
+    Ok(openings)
+}
+
+// the finished game, its PGN result and why it ended. search plays a move for the side to move,
+// returning its score with it or why that side forfeits
+pub fn play_game(
+    start: Position,
+    adjudication: &Adjudication,
+    mut search: impl FnMut(&Game) -> Result<(Move, i32), String>,
+) -> (Game, &'static str, String) {
+    let mut game = Game::new(start);
+    // each side's moves in a row scored as lost, and plies in a row scored as level
+    let mut losing = [0; 2];
+    let mut level = 0;
+
+    loop {
+        let outcome = game.outcome();
+        if outcome.is_over() {
+            let termination = match outcome {
+                Outcome::Checkmate(_) => "checkmate",
+                Outcome::Stalemate => "stalemate",
+                Outcome::Draw(DrawReason::FiftyMove) => "fifty-move rule",
+                Outcome::Draw(DrawReason::Repetition) => "threefold repetition",
+                Outcome::Draw(DrawReason::InsufficientMaterial) => "insufficient material",
+                Outcome::Ongoing => unreachable!(),
+            };
+            return (game, outcome.result(), termination.to_string());
+        }
+        if game.moves().len() >= adjudication.max_plies {
+            return (game, "1/2-1/2", "adjudicated, move limit".to_string());
+        }
+
+        let position = *game.position();
+        let (mv, score) = match search(&game) {
+            Ok(played) => played,
+            Err(termination) => {
+                let result = if position.turn == Colour::White { "0-1" } else { "1-0" };
+                return (game, result, termination);
+            }
+        };
+
+        // scores are from the side to move's point of view
+        let side = position.turn as usize;
+        losing[side] = if score <= -adjudication.resign_score { losing[side] + 1 } else { 0 };
+        if adjudication.resign_moves > 0 && losing[side] >= adjudication.resign_moves {
+            let result = if position.turn == Colour::White { "0-1" } else { "1-0" };
+            return (game, result, "adjudicated, resignation".to_string());
+        }
+
+        level = if score.abs() <= adjudication.draw_score { level + 1 } else { 0 };
+        if adjudication.draw_moves > 0
+            && level >= adjudication.draw_moves * 2
+            && game.moves().len() >= adjudication.draw_after * 2
+        {
+            return (game, "1/2-1/2", "adjudicated, draw".to_string());
+        }
+
+        game.push(mv);
+    }
+}
+
+// runs a search on this thread, returning its move and score
+pub fn search(game: &Game, limits: SearchLimits, tt: &TranspositionTable) -> Result<(Move, i32), String> {
+    let result = search_blocking(game, limits, tt, false);
+    result.best_move.map(|mv| (mv, result.score)).ok_or("no move from search".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // plays the first legal move, giving white's moves one score and black's another
+    fn play(adjudication: &Adjudication, white: i32, black: i32) -> (Game, &'static str, String) {
+        play_game(Position::from_fen(STARTING_FEN), adjudication, |game| {
+            let position = game.position();
+            let mv = position.gen_moves().find(|&mv| position.is_legal(mv)).unwrap();
+            Ok((mv, if position.turn == Colour::White { white } else { black }))
+        })
+    }
+
+    #[test]
+    fn adjudication() {
+        let adjudication = Adjudication::new();
+        let (game, result, termination) = play(&adjudication, 50, -1500);
+        assert_eq!((result, termination.as_str()), ("1-0", "adjudicated, resignation"));
+        assert_eq!(game.moves().len(), 7);
+
+        // level for three moves each, but not before two moves in
+        let adjudication = Adjudication { draw_after: 2, draw_moves: 3, ..Adjudication::new() };
+        let (game, result, termination) = play(&adjudication, 0, 5);
+        assert_eq!((result, termination.as_str()), ("1/2-1/2", "adjudicated, draw"));
+        assert_eq!(game.moves().len(), 5);
+        let (game, _, _) = play(&Adjudication { draw_after: 4, ..adjudication }, 0, 5);
+        assert_eq!(game.moves().len(), 8);
+
+        // without either the game runs to the move limit
+        let adjudication = Adjudication { resign_moves: 0, draw_moves: 0, max_plies: 30, ..Adjudication::new() };
+        let (game, result, termination) = play(&adjudication, 0, -1500);
+        assert_eq!((result, termination.as_str()), ("1/2-1/2", "adjudicated, move limit"));
+        assert_eq!(game.moves().len(), 30);
+    }
+}
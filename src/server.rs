@@ -0,0 +1,59 @@
+// Serves the UCI protocol over TCP, for analysis frontends on other machines or outside a
+// container. Clients are served one at a time, each by a fresh engine, with everything the engine
+// sends going to the client instead of stdout. JSON Output works here as over stdio.
+//
+// Clients can't use the file options and commands (Book File, Debug Log File, Learning File, Log
+// Target to a file, bench file, perft suite, solve, selfplay and match) unless the server is started
+// allowing them, since any client that can connect could otherwise write over files on the host.
+
+use std::{
+    error::Error,
+    io::BufReader,
+    net::{Shutdown, TcpListener, TcpStream},
+};
+
+use log::{error, info};
+
+use crate::{
+    engine::Engine,
+    logging::{self, Output},
+};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:4000";
+
+pub fn serve(address: &str, allow_files: bool) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(address)?;
+    info!("listening on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("accepting connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = serve_client(stream, allow_files) {
+            error!("serving client: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn serve_client(stream: TcpStream, allow_files: bool) -> Result<(), Box<dyn Error>> {
+    let peer = stream.peer_addr()?;
+    info!("connection from {}", peer);
+
+    let output = Output::new(stream.try_clone()?);
+    let mut engine = Engine::with_output(output);
+    engine.allow_files = allow_files;
+    let result = engine.run_from(BufReader::new(stream.try_clone()?));
+    // the engine pointed logging at the client
+    logging::set_log_output(None);
+
+    // wakes the reader thread if the client sent quit rather than hanging up
+    _ = stream.shutdown(Shutdown::Both);
+    info!("{} disconnected", peer);
+    Ok(result?)
+}
@@ -0,0 +1,115 @@
+// Knight and king attack sets for both sides at once, for evaluation terms that need the union of
+// many pieces' attacks (mobility, king safety). With AVX2 each of the four sets gets its own lane
+// and all eight shift directions are done in one pass, otherwise it falls back to the scalar fills
+// in attacks.rs.
+
+use crate::{
+    attacks::{all_king_attacks, all_knight_attacks},
+    bitboard::Bitboard,
+    position::Colour,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LeaperAttacks {
+    pub knights: [Bitboard; 2],
+    pub kings: [Bitboard; 2],
+}
+
+impl LeaperAttacks {
+    pub fn knights(&self, side: Colour) -> Bitboard {
+        self.knights[side]
+    }
+
+    pub fn kings(&self, side: Colour) -> Bitboard {
+        self.kings[side]
+    }
+}
+
+// knights and kings are indexed by colour
+pub fn leaper_attacks(knights: [Bitboard; 2], kings: [Bitboard; 2]) -> LeaperAttacks {
+    #[cfg(target_arch = "x86_64")]
+    if crate::cpu::features().avx2 {
+        return unsafe { avx2::leaper_attacks(knights, kings) };
+    }
+
+    scalar_leaper_attacks(knights, kings)
+}
+
+pub fn scalar_leaper_attacks(knights: [Bitboard; 2], kings: [Bitboard; 2]) -> LeaperAttacks {
+    LeaperAttacks {
+        knights: knights.map(all_knight_attacks),
+        kings: kings.map(all_king_attacks),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use core::arch::x86_64::*;
+
+    use super::LeaperAttacks;
+    use crate::bitboard::Bitboard;
+
+    const A: u64 = Bitboard::A_FILE.0;
+    const B: u64 = Bitboard::B_FILE.0;
+    const G: u64 = Bitboard::G_FILE.0;
+    const H: u64 = Bitboard::H_FILE.0;
+
+    // lanes are white knights, black knights, white kings, black kings. each row is one direction,
+    // shifted left with the first mask and right with the second
+    const STEPS: [([i64; 4], [u64; 4], [u64; 4]); 4] = [
+        ([6, 6, 7, 7], [!(G | H), !(G | H), !H, !H], [!(A | B), !(A | B), !A, !A]),
+        ([15, 15, 8, 8], [!H, !H, !0, !0], [!A, !A, !0, !0]),
+        ([17, 17, 9, 9], [!A, !A, !A, !A], [!H, !H, !H, !H]),
+        ([10, 10, 1, 1], [!(A | B), !(A | B), !A, !A], [!(G | H), !(G | H), !H, !H]),
+    ];
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn leaper_attacks(knights: [Bitboard; 2], kings: [Bitboard; 2]) -> LeaperAttacks {
+        let pieces = _mm256_setr_epi64x(knights[0].0 as i64, knights[1].0 as i64, kings[0].0 as i64, kings[1].0 as i64);
+
+        let mut attacks = _mm256_setzero_si256();
+        for (shifts, left_masks, right_masks) in STEPS {
+            let shifts = _mm256_loadu_si256(shifts.as_ptr() as *const __m256i);
+            let left_masks = _mm256_loadu_si256(left_masks.as_ptr() as *const __m256i);
+            let right_masks = _mm256_loadu_si256(right_masks.as_ptr() as *const __m256i);
+            let left = _mm256_and_si256(_mm256_sllv_epi64(pieces, shifts), left_masks);
+            let right = _mm256_and_si256(_mm256_srlv_epi64(pieces, shifts), right_masks);
+            attacks = _mm256_or_si256(attacks, _mm256_or_si256(left, right));
+        }
+
+        let mut out = [0u64; 4];
+        _mm256_storeu_si256(out.as_mut_ptr() as *mut __m256i, attacks);
+        LeaperAttacks {
+            knights: [Bitboard(out[0]), Bitboard(out[1])],
+            kings: [Bitboard(out[2]), Bitboard(out[3])],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        attacks::{king_attacks, knight_attacks},
+        magic::XorShift,
+    };
+
+    #[test]
+    fn leaper_attacks_match_tables() {
+        let mut prng = XorShift::new();
+        let mut sparse = || Bitboard(prng.gen_next() & prng.gen_next() & prng.gen_next());
+        for _ in 0..1000 {
+            let knights = [sparse(), sparse()];
+            let kings = [sparse(), sparse()];
+            let attacks = leaper_attacks(knights, kings);
+            assert_eq!(attacks, scalar_leaper_attacks(knights, kings));
+
+            for side in [Colour::White, Colour::Black] {
+                let expected = knights[side].fold(Bitboard(0), |acc, sq| acc | knight_attacks(sq));
+                assert_eq!(attacks.knights(side), expected);
+                let expected = kings[side].fold(Bitboard(0), |acc, sq| acc | king_attacks(sq));
+                assert_eq!(attacks.kings(side), expected);
+            }
+        }
+    }
+}
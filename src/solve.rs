@@ -0,0 +1,86 @@
+use std::fs;
+
+use log::warn;
+
+use crate::{engine::{Engine, EngineError}, epd::EpdRecord, game::Game, interface::SearchControl, san::strip_san, send};
+
+pub const DEFAULT_SOLVE_TIME: u32 = 1000;
+
+impl Engine {
+    // searches every position in an EPD suite for movetime ms and checks the move against its
+    // bm and am operations, which are in SAN
+    pub fn solve(&mut self, path: &str, movetime: u32) -> Result<(), EngineError> {
+        let contents = fs::read_to_string(path).map_err(|error| EngineError::File { path: path.to_string(), error })?;
+
+        let mut results = Vec::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let record = match EpdRecord::parse(line) {
+                Ok(record) => record,
+                Err(e) => {
+                    warn!("parsing EPD: {}", e);
+                    continue;
+                }
+            };
+            if record.best_moves.is_empty() && record.avoid_moves.is_empty() {
+                continue;
+            }
+
+            self.game = Game::new(record.position);
+            self.best_move = None;
+            let mut control = SearchControl::new();
+            control.movetime = movetime;
+            self.search(control)?;
+            self.wait_for_search();
+
+            let played = self.best_move.map(|mv| record.position.san(mv));
+            let solved = played.as_deref().is_some_and(|played| is_solution(&record, played));
+            results.push((record, played, solved));
+        }
+
+        send!(self.output, "=============================================");
+        for (i, (record, played, solved)) in results.iter().enumerate() {
+            let id = record.id.clone().unwrap_or_else(|| (i + 1).to_string());
+            let expected = if record.best_moves.is_empty() {
+                format!("am {}", record.avoid_moves.join(" "))
+            } else {
+                format!("bm {}", record.best_moves.join(" "))
+            };
+            let result = if *solved { "solved" } else { "failed" };
+            send!(self.output, "{}: {}, played {}, {}", id, result, played.as_deref().unwrap_or("none"), expected);
+        }
+
+        let solved = results.iter().filter(|(_, _, solved)| *solved).count();
+        send!(self.output, "solved {}/{}", solved, results.len());
+        Ok(())
+    }
+}
+
+// a move is right when it's one of the best moves, if any are given, and none of the avoided ones
+fn is_solution(record: &EpdRecord, played: &str) -> bool {
+    let played = strip_san(played);
+    let matches = |san: &String| strip_san(san) == played;
+    (record.best_moves.is_empty() || record.best_moves.iter().any(matches))
+        && !record.avoid_moves.iter().any(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solution_test() {
+        let record = EpdRecord::parse("8/6pk/p3p3/2Q1P3/1p2P3/5P1P/5KP1/1q6 w - - bm Qb6 Qc7+; am Qxb4;").unwrap();
+        assert!(is_solution(&record, "Qb6"));
+        assert!(is_solution(&record, "Qc7"));
+        assert!(!is_solution(&record, "Qxb4"));
+        assert!(!is_solution(&record, "Kg3"));
+
+        let record = EpdRecord::parse("8/6pk/p3p3/2Q1P3/1p2P3/5P1P/5KP1/1q6 w - - am Qxb4+;").unwrap();
+        assert!(is_solution(&record, "Kg3"));
+        assert!(!is_solution(&record, "Qxb4"));
+
+        let record = EpdRecord::parse("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - bm 0-0; am 0-0-0;").unwrap();
+        assert!(is_solution(&record, "O-O"));
+        assert!(!is_solution(&record, "O-O-O"));
+    }
+}
@@ -0,0 +1,448 @@
+// The transposition table: what the search has found out about positions it's already been to,
+// kept between iterations and searches. An entry is 16 bytes, the full key and one packed word of
+// move, score, depth and bound, and entries come four to a 64 byte bucket so a probe only ever
+// touches one cache line. Three entries in each bucket are depth-preferred, only giving way to a
+// search at least as deep, and the last is always replaced, taking whatever the others push out.
+//
+// Every entry is stamped with the generation of the search that stored it, bumped for each new
+// search, so entries left from earlier ones give way before the current search's, however deep
+// they were. Otherwise a long session fills the preferred entries with deep results for positions
+// the game has long since left.
+//
+// The table is shared between search threads without a lock. Both words of an entry are atomics,
+// written and read independently, and the key is stored xored with the data, so an entry torn by
+// two threads writing it at once no longer matches its key and reads as a miss. Entries lost to a
+// race are just searched again, which is cheaper than making every probe wait.
+
+use std::{
+    fmt::{self, Display},
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+};
+
+use crate::{
+    movegen::{Move, MoveKind},
+    position::{Piece, Position},
+    search::{CHECKMATE, MAX_DEPTH},
+};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+const BUCKET_ENTRIES: usize = 4;
+// generations fit in the 6 bits of the data word left over, so they wrap after 64 searches
+const GENERATIONS: u8 = 64;
+// scores past this are mates, stored as distances from the node rather than the root
+const MATE_BOUND: i32 = CHECKMATE - MAX_DEPTH as i32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Exact = 1,
+    // the score is at least this, the search failed high
+    Lower = 2,
+    // at most this, it failed low
+    Upper = 3,
+}
+
+impl Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Exact => write!(f, "exact"),
+            Bound::Lower => write!(f, "lower"),
+            Bound::Upper => write!(f, "upper"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtEntry {
+    // packed as pack_move does, 0 for none
+    pub mv: u16,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+impl TtEntry {
+    // move in bits 0-15, score 16-47, depth 48-55, bound 56-57 and generation 58-63. the bound
+    // is never 0, so neither is the word of a stored entry
+    fn pack(self, generation: u8) -> u64 {
+        self.mv as u64
+            | (self.score as u32 as u64) << 16
+            | (self.depth as u64) << 48
+            | (self.bound as u64) << 56
+            | (generation as u64) << 58
+    }
+
+    fn unpack(data: u64) -> Option<TtEntry> {
+        let bound = match data >> 56 & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        Some(TtEntry { mv: data as u16, score: (data >> 16) as u32 as i32, depth: (data >> 48) as u8, bound })
+    }
+}
+
+// a copy of one entry of the table, taken to decide what to replace
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    key: u64,
+    data: u64,
+}
+
+impl Slot {
+    fn depth(&self) -> u8 {
+        (self.data >> 48) as u8
+    }
+
+    // how many searches ago it was stored
+    fn age(&self, generation: u8) -> u8 {
+        generation.wrapping_sub((self.data >> 58) as u8) % GENERATIONS
+    }
+
+    // which of the preferred entries to replace, the lowest first. a generation counts for more
+    // than any depth a search reaches
+    fn worth(&self, generation: u8) -> i32 {
+        self.depth() as i32 - self.age(generation) as i32 * 256
+    }
+}
+
+// the key is xored with the data, see the top of the file
+#[derive(Debug, Default)]
+#[repr(C)]
+struct AtomicSlot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl AtomicSlot {
+    // relaxed is enough, a torn entry is caught by its key whatever order the words land in
+    fn load(&self) -> Slot {
+        let data = self.data.load(Ordering::Relaxed);
+        Slot { key: self.key.load(Ordering::Relaxed) ^ data, data }
+    }
+
+    fn store(&self, slot: Slot) {
+        self.key.store(slot.key ^ slot.data, Ordering::Relaxed);
+        self.data.store(slot.data, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+#[repr(C, align(64))]
+struct Bucket {
+    slots: [AtomicSlot; BUCKET_ENTRIES],
+}
+
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
+}
+
+// the entries would be far too many to show
+impl std::fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TranspositionTable")
+            .field("capacity", &self.capacity())
+            .field("generation", &self.generation())
+            .finish()
+    }
+}
+
+impl TranspositionTable {
+    // rounded down to a power of two buckets
+    pub fn new(mb: usize) -> Self {
+        let len = (mb * 1024 * 1024 / size_of::<Bucket>()).max(1);
+        Self { buckets: (0..1 << len.ilog2()).map(|_| Bucket::default()).collect(), generation: AtomicU8::new(0) }
+    }
+
+    // the only change that needs the table to itself
+    pub fn resize(&mut self, mb: usize) {
+        *self = Self::new(mb);
+    }
+
+    // not while a search is using it, or that search's entries could survive
+    pub fn clear(&self) {
+        let empty = Slot::default();
+        self.buckets.iter().flat_map(|bucket| &bucket.slots).for_each(|slot| slot.store(empty));
+    }
+
+    // for each go, what's stored from now on is newer than everything already there
+    pub fn new_search(&self) {
+        self.generation.store((self.generation() + 1) % GENERATIONS, Ordering::Relaxed);
+    }
+
+    pub fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    // how full it is in permille, as UCI's hashfull wants, going by how many of the first thousand
+    // entries this search has stored
+    pub fn hashfull(&self) -> u16 {
+        let generation = self.generation();
+        let sample = self.buckets.iter().flat_map(|bucket| &bucket.slots).take(1000).map(AtomicSlot::load);
+        let (mut used, mut total) = (0, 0);
+        for slot in sample {
+            used += (slot.data != 0 && slot.age(generation) == 0) as usize;
+            total += 1;
+        }
+        (used * 1000 / total) as u16
+    }
+
+    // what new was given, rounded down like it was
+    pub fn mb(&self) -> usize {
+        self.buckets.len() * size_of::<Bucket>() / (1024 * 1024)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_ENTRIES
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.buckets.len() - 1)
+    }
+
+    fn find(&self, key: u64) -> Option<Slot> {
+        let bucket = &self.buckets[self.index(key)];
+        bucket.slots.iter().map(AtomicSlot::load).find(|slot| slot.key == key && slot.data != 0)
+    }
+
+    // starts loading the key's bucket into cache, so a probe soon after doesn't wait on memory
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let bucket: *const Bucket = &self.buckets[self.index(key)];
+            // only a hint, it reads nothing
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(bucket as *const i8) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        TtEntry::unpack(self.find(key)?.data)
+    }
+
+    // with how many searches ago it was stored, for looking at the table by hand
+    pub fn probe_with_age(&self, key: u64) -> Option<(TtEntry, u8)> {
+        let slot = self.find(key)?;
+        Some((TtEntry::unpack(slot.data)?, slot.age(self.generation())))
+    }
+
+    // a position already in the bucket is always updated, keeping its move when the new search
+    // didn't find one. decided on a copy of the bucket, which another thread may change meanwhile
+    pub fn store(&self, key: u64, mut entry: TtEntry) {
+        let generation = self.generation();
+        let atomics = &self.buckets[self.index(key)].slots;
+        let slots = atomics.each_ref().map(AtomicSlot::load);
+
+        if let Some(i) = slots.iter().position(|slot| slot.key == key && slot.data != 0) {
+            if entry.mv == 0 {
+                entry.mv = slots[i].data as u16;
+            }
+            atomics[i].store(Slot { key, data: entry.pack(generation) });
+            return;
+        }
+
+        let new = Slot { key, data: entry.pack(generation) };
+        let always = BUCKET_ENTRIES - 1;
+        // empty slots first, then the oldest and shallowest
+        let i = (0..always).min_by_key(|&i| (slots[i].data != 0, slots[i].worth(generation))).unwrap();
+        let victim = slots[i];
+        if victim.data == 0 || victim.age(generation) > 0 || entry.depth >= victim.depth() {
+            // only this search's entries are worth keeping on
+            if victim.data != 0 && victim.age(generation) == 0 {
+                atomics[always].store(victim);
+            }
+            atomics[i].store(new);
+        } else {
+            atomics[always].store(new);
+        }
+    }
+}
+
+// from, to and promotion, enough to pick the move out of those generated in the same position
+pub fn pack_move(mv: Move) -> u16 {
+    let promotion = match mv.kind {
+        MoveKind::Promotion(piece) | MoveKind::PromotionCapture(piece, _) => match piece {
+            Piece::Knight(_) => 1,
+            Piece::Bishop(_) => 2,
+            Piece::Rook(_) => 3,
+            _ => 4,
+        },
+        _ => 0,
+    };
+    mv.from as u16 | (mv.to as u16) << 6 | promotion << 12
+}
+
+// the move a packed one was made from, if it's one of the position's
+pub fn unpack_move(position: &Position, mv: u16) -> Option<Move> {
+    position.gen_moves().find(|&generated| mv != 0 && pack_move(generated) == mv)
+}
+
+// mate scores are stored as distances from the node, so they're still right reached by another path
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        score if score > MATE_BOUND => score + ply as i32,
+        score if score < -MATE_BOUND => score - ply as i32,
+        score => score,
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        score if score > MATE_BOUND => score - ply as i32,
+        score if score < -MATE_BOUND => score + ply as i32,
+        score => score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::STARTING_FEN;
+
+    fn entry(depth: u8) -> TtEntry {
+        TtEntry { mv: depth as u16 + 1, score: -(depth as i32) * 100, depth, bound: Bound::Lower }
+    }
+
+    #[test]
+    fn entry_layout() {
+        assert_eq!(size_of::<AtomicSlot>(), 16);
+        assert_eq!(size_of::<Bucket>(), 64);
+        assert_eq!(align_of::<Bucket>(), 64);
+        for entry in [entry(0), entry(MAX_DEPTH as u8), TtEntry { mv: 0, score: -CHECKMATE, depth: 255, bound: Bound::Upper }] {
+            assert_eq!(TtEntry::unpack(entry.pack(0)), Some(entry));
+            assert_eq!(TtEntry::unpack(entry.pack(GENERATIONS - 1)), Some(entry));
+        }
+        assert_eq!(TtEntry::unpack(0), None);
+        assert_eq!(TranspositionTable::new(1).capacity(), 1024 * 1024 / 16);
+        assert_eq!(TranspositionTable::new(3).capacity(), 2 * 1024 * 1024 / 16);
+    }
+
+    #[test]
+    fn replacement() {
+        let tt = TranspositionTable::new(1);
+        let buckets = tt.buckets.len() as u64;
+        let key = |n: u64| 5 + n * buckets;
+
+        for depth in [4, 2, 6] {
+            tt.store(key(depth as u64), entry(depth));
+        }
+        // the bucket's full, a shallower search goes in the always replaced entry and pushes out
+        // the last one there
+        tt.store(key(1), entry(1));
+        tt.store(key(0), entry(0));
+        assert_eq!(tt.probe(key(1)), None);
+        assert_eq!(tt.probe(key(0)), Some(entry(0)));
+
+        // a deeper one replaces the shallowest, which moves to the always replaced entry
+        tt.store(key(3), entry(3));
+        assert_eq!(tt.probe(key(2)), Some(entry(2)));
+        assert_eq!(tt.probe(key(3)), Some(entry(3)));
+        assert_eq!(tt.probe(key(0)), None);
+        assert_eq!(tt.probe(key(4)), Some(entry(4)));
+
+        // updates keep the move when there isn't a new one
+        tt.store(key(4), TtEntry { mv: 0, ..entry(1) });
+        assert_eq!(tt.probe(key(4)), Some(TtEntry { mv: entry(4).mv, ..entry(1) }));
+
+        tt.clear();
+        assert_eq!(tt.probe(key(4)), None);
+    }
+
+    #[test]
+    fn hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        // one entry in each of the first 125 buckets, an eighth of the sample
+        for key in 0..125 {
+            tt.store(key, entry(1));
+        }
+        assert_eq!(tt.hashfull(), 125);
+        // only this search's entries count
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn aging() {
+        let tt = TranspositionTable::new(1);
+        let buckets = tt.buckets.len() as u64;
+        let key = |n: u64| 7 + n * buckets;
+
+        for depth in [20, 21, 22] {
+            tt.store(key(depth as u64), entry(depth));
+        }
+        tt.store(key(1), entry(1));
+        assert_eq!(tt.probe(key(1)), Some(entry(1)));
+
+        // the next search's shallow entries take the place of the last one's deep ones, oldest and
+        // shallowest first, and the old ones aren't kept on in the always replaced entry
+        tt.new_search();
+        tt.store(key(2), entry(2));
+        assert_eq!(tt.probe(key(20)), None);
+        assert_eq!(tt.probe(key(1)), Some(entry(1)));
+        tt.store(key(3), entry(3));
+        assert_eq!(tt.probe(key(21)), None);
+        assert_eq!(tt.probe(key(22)), Some(entry(22)));
+
+        // an updated entry is current again, and one from this search is moved there
+        tt.store(key(22), entry(22));
+        tt.store(key(4), entry(4));
+        assert_eq!(tt.probe(key(1)), None);
+        assert_eq!(tt.probe(key(2)), Some(entry(2)));
+        assert_eq!(tt.probe(key(4)), Some(entry(4)));
+        assert_eq!(tt.probe(key(3)), Some(entry(3)));
+        assert_eq!(tt.probe(key(22)), Some(entry(22)));
+
+        for _ in 0..GENERATIONS {
+            tt.new_search();
+        }
+        assert_eq!(tt.generation(), 1);
+    }
+
+    // threads storing over each other's entries only ever lose them, a probe never returns one
+    // made of two
+    #[test]
+    fn shared_between_threads() {
+        let tt = TranspositionTable::new(1);
+        let buckets = tt.buckets.len() as u64;
+        // all in the first bucket, and every entry's fields follow from its key
+        let key = |thread: u64, n: u64| ((thread + 1) << 40) + (n % 16) * buckets;
+        let entry = |key: u64| TtEntry { mv: (key >> 40) as u16, score: (key / buckets) as i32, depth: 1, bound: Bound::Exact };
+
+        std::thread::scope(|s| {
+            for thread in 0..4 {
+                let tt = &tt;
+                s.spawn(move || {
+                    for n in 0..100_000 {
+                        tt.store(key(thread, n), entry(key(thread, n)));
+                        let other = key((thread + 1) % 4, n);
+                        if let Some(found) = tt.probe(other) {
+                            assert_eq!(found, entry(other));
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn moves_and_mates() {
+        let position = Position::from_fen(STARTING_FEN);
+        let mut packed: Vec<u16> = position.gen_moves().map(pack_move).collect();
+        packed.sort();
+        packed.dedup();
+        assert_eq!(packed.len(), 20);
+        for mv in position.gen_moves() {
+            assert_eq!(unpack_move(&position, pack_move(mv)), Some(mv));
+        }
+        assert_eq!(unpack_move(&position, 0), None);
+
+        let mate_in_two = CHECKMATE - 3;
+        assert_eq!(score_from_tt(score_to_tt(mate_in_two, 5), 5), mate_in_two);
+        assert_eq!(score_from_tt(score_to_tt(-mate_in_two, 5), 2), -mate_in_two - 3);
+        assert_eq!(score_to_tt(150, 5), 150);
+    }
+}
@@ -0,0 +1,150 @@
+// An external UCI engine run as a subprocess, so matches can be played against other engines
+// without needing a separate tournament manager.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError};
+
+use crate::{game::Game, movegen::Move, search::CHECKMATE};
+
+// how long an engine gets to answer uci and isready
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct UciEngine {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciEngine {
+    pub fn start(path: &str) -> io::Result<Self> {
+        let mut child = Command::new(path).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // read on a thread so a hung engine can be timed out
+        let (tx, lines) = unbounded();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Self { name: path.to_string(), child, stdin, lines };
+        engine.send("uci")?;
+        for line in engine.read_until("uciok", Some(HANDSHAKE_TIMEOUT))? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+        }
+        engine.new_game()?;
+
+        Ok(engine)
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.read_until("readyok", Some(HANDSHAKE_TIMEOUT))?;
+        Ok(())
+    }
+
+    // taken from the next isready, which new_game sends
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    // go takes the arguments to the go command, the engine has until timeout to reply. returns
+    // the move with the last score it reported, from the side to move's point of view
+    pub fn best_move(&mut self, game: &Game, go: &str, timeout: Option<Duration>) -> io::Result<(Move, i32)> {
+        let moves: Vec<String> = game.moves().iter().map(|mv| mv.to_string()).collect();
+        self.send(&format!("position fen {} moves {}", game.start().write_fen(), moves.join(" ")))?;
+        self.send(&format!("go {}", go))?;
+
+        let lines = self.read_until("bestmove", timeout)?;
+        let score = lines.iter().rev().find_map(|line| parse_score(line)).unwrap_or(0);
+        let bestmove = lines.last().and_then(|line| line.split_whitespace().nth(1)).unwrap_or_default();
+        match game.position().find_algebraic_move(bestmove) {
+            Some(mv) if game.position().is_legal(mv) => Ok((mv, score)),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("illegal move {}", bestmove))),
+        }
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    // every line up to and including the first starting with token
+    fn read_until(&mut self, token: &str, timeout: Option<Duration>) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        loop {
+            let line = match timeout {
+                Some(timeout) => self.lines.recv_timeout(timeout),
+                None => self.lines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let line = match line {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+
+            let done = line.split_whitespace().next() == Some(token);
+            lines.push(line);
+            if done {
+                return Ok(lines);
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        // give it a moment to quit by itself before killing it
+        _ = self.send("quit");
+        for _ in 0..100 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+// mates as scores near CHECKMATE, like our own search reports them
+fn parse_score(line: &str) -> Option<i32> {
+    let mut tokens = line.split_whitespace().skip_while(|t| *t != "score").skip(1);
+    let kind = tokens.next()?;
+    let value: i32 = tokens.next()?.parse().ok()?;
+    match kind {
+        "cp" => Some(value),
+        "mate" => Some((CHECKMATE - value.abs()) * value.signum()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scores() {
+        assert_eq!(parse_score("info depth 5 score cp -31 nodes 100 pv e2e4"), Some(-31));
+        assert_eq!(parse_score("info depth 9 score mate -2 nodes 100"), Some(-(CHECKMATE - 2)));
+        assert_eq!(parse_score("info depth 5 score lowerbound nodes 100"), None);
+        assert_eq!(parse_score("info currmove e2e4 currmovenumber 1"), None);
+    }
+}
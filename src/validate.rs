@@ -0,0 +1,176 @@
+// Sanity checks for positions that didn't come out of make_move, run after FEN parsing and
+// available to library users building positions by hand.
+
+use core::fmt::Display;
+
+use enum_iterator::all;
+
+use crate::{
+    bitboard::{Bitboard, Square},
+    position::{
+        CastlingFlags,
+        Colour::{self, *},
+        Piece::{self, *},
+        Position,
+    },
+};
+
+#[derive(Debug, PartialEq)]
+pub enum PositionError {
+    KingCount(Colour, u32),
+    PawnOnBackRank(Square),
+    OverlappingPieces(Square),
+    Occupancy(Colour),
+    EnPassant(Square),
+    CastlingRights(CastlingFlags),
+    OpponentInCheck,
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PositionError::KingCount(side, n) => write!(f, "Side {} has {} kings", side, n),
+            PositionError::PawnOnBackRank(sq) => write!(f, "Pawn on back rank at {}", sq),
+            PositionError::OverlappingPieces(sq) => write!(f, "More than one piece on {}", sq),
+            PositionError::Occupancy(side) => write!(f, "Occupancy doesn't match the pieces for side {}", side),
+            PositionError::EnPassant(sq) => write!(f, "No double pawn push could have left en passant on {}", sq),
+            PositionError::CastlingRights(right) => write!(f, "King or rook missing for castling right {:?}", right),
+            PositionError::OpponentInCheck => write!(f, "The side not to move is in check"),
+        }
+    }
+}
+
+impl core::error::Error for PositionError {}
+
+impl Position {
+    pub fn validate(&self) -> Result<(), PositionError> {
+        for side in [White, Black] {
+            let kings = self.pieces[King(side)].popcount();
+            if kings != 1 {
+                return Err(PositionError::KingCount(side, kings));
+            }
+        }
+
+        let pawns = self.pieces[Pawn(White)] | self.pieces[Pawn(Black)];
+        if let Some(sq) = (pawns & (Bitboard::RANK_1 | Bitboard::RANK_8)).get_lsb() {
+            return Err(PositionError::PawnOnBackRank(sq));
+        }
+
+        let mut seen = Bitboard(0);
+        for piece in all::<Piece>() {
+            if let Some(sq) = (seen & self.pieces[piece]).get_lsb() {
+                return Err(PositionError::OverlappingPieces(sq));
+            }
+            seen |= self.pieces[piece];
+        }
+
+        for side in [White, Black] {
+            let pieces = Piece::iter_colour(side).fold(Bitboard(0), |acc, p| acc | self.pieces[p]);
+            if self.occupancy[side] != pieces {
+                return Err(PositionError::Occupancy(side));
+            }
+        }
+
+        self.validate_en_passant()?;
+        self.validate_castling()?;
+
+        if self.is_check(!self.turn) {
+            return Err(PositionError::OpponentInCheck);
+        }
+
+        Ok(())
+    }
+
+    // the en passant square must be empty, directly behind a pawn that could have just double
+    // pushed from its also empty starting square
+    fn validate_en_passant(&self) -> Result<(), PositionError> {
+        let Some(sq) = self.en_passant else {
+            return Ok(());
+        };
+
+        let (ep_rank, pushed, start) = match self.turn {
+            White => (Square::A6.rank(), sq.add(8), sq.add(-8)),
+            Black => (Square::A3.rank(), sq.add(-8), sq.add(8)),
+        };
+        let occupied = self.occupied();
+        let plausible = sq.rank() == ep_rank
+            && !occupied.is_set(sq)
+            && start.is_some_and(|start| !occupied.is_set(start))
+            && pushed.is_some_and(|pushed| self.pieces[Pawn(!self.turn)].is_set(pushed));
+
+        if plausible {
+            Ok(())
+        } else {
+            Err(PositionError::EnPassant(sq))
+        }
+    }
+
+    // each right needs the king on its back rank and the castling rook on the right side of it
+    fn validate_castling(&self) -> Result<(), PositionError> {
+        for right in self.castling.iter() {
+            let side = if right.intersects(CastlingFlags::WK | CastlingFlags::WQ) { White } else { Black };
+            let back_rank = Bitboard::rank(Square::A1.relative_to(side).rank());
+            let rook = self.castling_rooks[right.index()];
+            let king = (self.pieces[King(side)] & back_rank).get_lsb();
+
+            let consistent = back_rank.is_set(rook)
+                && self.pieces[Rook(side)].is_set(rook)
+                && king.is_some_and(|king| (rook.file() > king.file()) == (right == CastlingFlags::kingside(side)));
+            if !consistent {
+                return Err(PositionError::CastlingRights(right));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(fen: &str) -> Result<(), PositionError> {
+        Position::from_fen(fen).validate()
+    }
+
+    #[test]
+    fn valid_positions() {
+        assert_eq!(validate(crate::fen::STARTING_FEN), Ok(()));
+        assert_eq!(validate("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"), Ok(()));
+        assert_eq!(validate("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1"), Ok(()));
+    }
+
+    #[test]
+    fn invalid_positions() {
+        let mut position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        position.pieces[King(Black)] = Bitboard(0);
+        assert_eq!(position.validate(), Err(PositionError::KingCount(Black, 0)));
+
+        let mut position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        position.pieces[Pawn(White)].set(Square::D8);
+        position.occupancy[White].set(Square::D8);
+        assert_eq!(position.validate(), Err(PositionError::PawnOnBackRank(Square::D8)));
+
+        let mut position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        position.pieces[Knight(White)].set(Square::E8);
+        assert_eq!(position.validate(), Err(PositionError::OverlappingPieces(Square::E8)));
+
+        let mut position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        position.occupancy[White].set(Square::A1);
+        assert_eq!(position.validate(), Err(PositionError::Occupancy(White)));
+
+        let mut position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        position.en_passant = Some(Square::E6);
+        assert_eq!(position.validate(), Err(PositionError::EnPassant(Square::E6)));
+
+        let mut position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        position.pieces[Rook(White)].reset(Square::H1);
+        position.occupancy[White].reset(Square::H1);
+        assert_eq!(position.validate(), Err(PositionError::CastlingRights(CastlingFlags::WK)));
+
+        let mut position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        position.pieces[Rook(White)].set(Square::E2);
+        position.occupancy[White].set(Square::E2);
+        assert_eq!(position.validate(), Err(PositionError::OpponentInCheck));
+    }
+}
@@ -1,21 +1,32 @@
-use rand::Rng;
-use lazy_static::lazy_static;
-use crate::{bitboard::Square, position::{CastlingFlags, Colour::*, Piece, Position}};
+use crate::{bitboard::Square, material::MaterialKey, position::{CastlingFlags, Colour::*, Piece::{self, *}, Position}};
 
-lazy_static! {
-    pub static ref ZOBRIST_CODES: ZobristCodes = ZobristCodes::init();
-}
+// generated at compile time so hashes are identical across runs and builds
+pub static ZOBRIST_CODES: ZobristCodes = ZobristCodes::init();
 
 const CASTLING_OFFSET: usize = 64*12; // 12 pieces with 64 squares
 const EN_PASSANT_OFFSET: usize = CASTLING_OFFSET + 16; // 2^4 castling arrangements
 const TURN_OFFSET: usize = EN_PASSANT_OFFSET + 8; // 8 possible en-passent files
+const N_CODES: usize = TURN_OFFSET + 1;
+const SEED: u64 = 0x2545F4914F6CDD1D;
 
-pub struct ZobristCodes([u64; 793]);
+pub struct ZobristCodes([u64; N_CODES]);
 
 impl ZobristCodes {
-    pub fn init() -> Self {
-        let mut codes = [0; 793];
-        rand::thread_rng().fill(&mut codes[..]);
+    pub const fn init() -> Self {
+        let mut codes = [0; N_CODES];
+        let mut state = SEED;
+
+        let mut i = 0;
+        while i < N_CODES {
+            // splitmix64, a const-friendly generator with well mixed output
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            codes[i] = z ^ (z >> 31);
+            i += 1;
+        }
+
         ZobristCodes(codes)
     }
 
@@ -40,6 +51,7 @@ impl ZobristCodes {
 impl Position {
     pub fn gen_zobrist_hash(&mut self) {
         self.hash = 0;
+        self.pawn_key = 0;
 
         for (piece, bb) in self.pieces.iter().enumerate() {
             for sq in *bb {
@@ -47,6 +59,13 @@ impl Position {
             }
         }
 
+        for piece in [Pawn(White), Pawn(Black)] {
+            for sq in self.pieces[piece] {
+                self.pawn_key ^= ZOBRIST_CODES.piece(piece, sq);
+            }
+        }
+        self.material_key = MaterialKey::from_pieces(&self.pieces);
+
         self.hash ^= ZOBRIST_CODES.castling(self.castling);
 
         if let Some(sq) = self.en_passant {
@@ -57,4 +76,18 @@ impl Position {
             self.hash ^= ZOBRIST_CODES.turn();
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zobrist_codes_are_distinct() {
+        let mut codes = ZOBRIST_CODES.0.to_vec();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), N_CODES);
+        assert!(!codes.contains(&0));
+    }
 }
\ No newline at end of file